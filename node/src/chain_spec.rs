@@ -1,8 +1,10 @@
 use sp_core::{Pair, Public, sr25519};
 use node_template_runtime::{
-	AccountId, AuraConfig, BalancesConfig, GenesisConfig, GrandpaConfig,
-	SudoConfig, SystemConfig, WASM_BINARY, Signature
+	AccountId, AuraConfig, BalancesConfig, CouncilConfig, DemocracyConfig, ExecutorsConfig,
+	GenesisConfig, GrandpaConfig, MinimumCandidateBond, MinimumExecutorBond, SessionConfig,
+	StakingConfig, SystemConfig, ValidatorSetConfig, WASM_BINARY, Signature, opaque::SessionKeys,
 };
+use sp_executor::ExecutorId;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
 use sp_finality_grandpa::AuthorityId as GrandpaId;
 use sp_runtime::traits::{Verify, IdentifyAccount};
@@ -30,14 +32,27 @@ pub fn get_account_id_from_seed<TPublic: Public>(seed: &str) -> AccountId where
 	AccountPublic::from(get_from_seed::<TPublic>(seed)).into_account()
 }
 
-/// Generate an Aura authority key.
-pub fn authority_keys_from_seed(s: &str) -> (AuraId, GrandpaId) {
+/// Generate the account of an authority, along with its Aura and GRANDPA keys.
+pub fn authority_keys_from_seed(s: &str) -> (AccountId, AuraId, GrandpaId) {
 	(
+		get_account_id_from_seed::<sr25519::Public>(s),
 		get_from_seed::<AuraId>(s),
 		get_from_seed::<GrandpaId>(s),
 	)
 }
 
+fn session_keys(aura: AuraId, grandpa: GrandpaId) -> SessionKeys {
+	SessionKeys { aura, grandpa }
+}
+
+/// Generate an executor key, along with the account bonding it.
+pub fn executor_keys_from_seed(s: &str) -> (AccountId, ExecutorId) {
+	(
+		get_account_id_from_seed::<sr25519::Public>(s),
+		get_from_seed::<ExecutorId>(s),
+	)
+}
+
 pub fn development_config() -> Result<ChainSpec, String> {
 	let wasm_binary = WASM_BINARY.ok_or_else(|| "Development wasm not available".to_string())?;
 
@@ -49,12 +64,19 @@ pub fn development_config() -> Result<ChainSpec, String> {
 		ChainType::Development,
 		move || testnet_genesis(
 			wasm_binary,
-			// Initial PoA authorities
+			// Initial authorities, bonded as candidates
 			vec![
 				authority_keys_from_seed("Alice"),
 			],
-			// Sudo account
-			get_account_id_from_seed::<sr25519::Public>("Alice"),
+			// Initial executors
+			vec![
+				executor_keys_from_seed("Alice"),
+			],
+			// Initial council
+			vec![
+				get_account_id_from_seed::<sr25519::Public>("Alice"),
+				get_account_id_from_seed::<sr25519::Public>("Bob"),
+			],
 			// Pre-funded accounts
 			vec![
 				get_account_id_from_seed::<sr25519::Public>("Alice"),
@@ -88,13 +110,22 @@ pub fn local_testnet_config() -> Result<ChainSpec, String> {
 		ChainType::Local,
 		move || testnet_genesis(
 			wasm_binary,
-			// Initial PoA authorities
+			// Initial authorities, bonded as candidates
 			vec![
 				authority_keys_from_seed("Alice"),
 				authority_keys_from_seed("Bob"),
 			],
-			// Sudo account
-			get_account_id_from_seed::<sr25519::Public>("Alice"),
+			// Initial executors
+			vec![
+				executor_keys_from_seed("Charlie"),
+				executor_keys_from_seed("Dave"),
+			],
+			// Initial council
+			vec![
+				get_account_id_from_seed::<sr25519::Public>("Alice"),
+				get_account_id_from_seed::<sr25519::Public>("Bob"),
+				get_account_id_from_seed::<sr25519::Public>("Charlie"),
+			],
 			// Pre-funded accounts
 			vec![
 				get_account_id_from_seed::<sr25519::Public>("Alice"),
@@ -128,8 +159,9 @@ pub fn local_testnet_config() -> Result<ChainSpec, String> {
 /// Configure initial storage state for FRAME modules.
 fn testnet_genesis(
 	wasm_binary: &[u8],
-	initial_authorities: Vec<(AuraId, GrandpaId)>,
-	root_key: AccountId,
+	initial_authorities: Vec<(AccountId, AuraId, GrandpaId)>,
+	initial_executors: Vec<(AccountId, ExecutorId)>,
+	council: Vec<AccountId>,
 	endowed_accounts: Vec<AccountId>,
 	_enable_println: bool,
 ) -> GenesisConfig {
@@ -143,15 +175,39 @@ fn testnet_genesis(
 			// Configure endowed accounts with initial balance of 1 << 60.
 			balances: endowed_accounts.iter().cloned().map(|k|(k, 1 << 60)).collect(),
 		},
+		// no invulnerables, the authorities are elected by their bond.
+		pallet_validator_set: ValidatorSetConfig {
+			validators: vec![],
+		},
+		pallet_validator_staking: StakingConfig {
+			// Bond the initial authorities with the minimum.
+			candidates: initial_authorities.iter()
+				.map(|x| (x.0.clone(), MinimumCandidateBond::get()))
+				.collect(),
+		},
+		pallet_session: SessionConfig {
+			keys: initial_authorities.iter()
+				.map(|x| (x.0.clone(), x.0.clone(), session_keys(x.1.clone(), x.2.clone())))
+				.collect(),
+		},
+		// the authorities are set by the session.
 		pallet_aura: AuraConfig {
-			authorities: initial_authorities.iter().map(|x| (x.0.clone())).collect(),
+			authorities: vec![],
 		},
 		pallet_grandpa: GrandpaConfig {
-			authorities: initial_authorities.iter().map(|x| (x.1.clone(), 1)).collect(),
+			authorities: vec![],
+		},
+		pallet_executors: ExecutorsConfig {
+			// Bond the initial executors with the minimum.
+			executors: initial_executors.into_iter()
+				.map(|(owner, executor)| (owner, executor, MinimumExecutorBond::get()))
+				.collect(),
 		},
-		pallet_sudo: SudoConfig {
-			// Assign network admin rights.
-			key: root_key,
+		// root calls go through the referenda of the democracy pallet.
+		pallet_collective_Instance1: CouncilConfig {
+			members: council,
+			phantom: Default::default(),
 		},
+		pallet_democracy: DemocracyConfig::default(),
 	}
 }
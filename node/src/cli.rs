@@ -1,5 +1,8 @@
 use structopt::StructOpt;
 use sc_cli::RunCmd;
+use crate::executor::{
+	BackpressureConfig, ExecutionConfig, ExecutionRole, InvalidExecution, InvalidExecutionConfig,
+};
 
 #[derive(Debug, StructOpt)]
 pub struct Cli {
@@ -8,6 +11,104 @@ pub struct Cli {
 
 	#[structopt(flatten)]
 	pub run: RunCmd,
+
+	#[structopt(flatten)]
+	pub execution: ExecutionParams,
+}
+
+/// Parameters of the decoupled execution pipeline.
+#[derive(Debug, StructOpt)]
+pub struct ExecutionParams {
+	/// The part of block processing this node takes on.
+	///
+	/// `coupled` orders and executes every block like a regular node, `consensus` only orders
+	/// blocks and `executor` executes the finalized chain.
+	#[structopt(
+		long = "execution-role",
+		value_name = "ROLE",
+		possible_values = &ExecutionRole::variants(),
+		case_insensitive = true,
+		default_value = "coupled",
+	)]
+	pub role: ExecutionRole,
+
+	/// Number of blocks executors can lag behind the chain head before authoring slows down.
+	///
+	/// Only used by `consensus` authorities, which learn how far the chain is executed from the
+	/// receipts on chain. Authoring never slows down when unset.
+	#[structopt(long = "max-execution-lag", value_name = "BLOCKS")]
+	pub max_execution_lag: Option<u32>,
+
+	/// Number of blocks of lag beyond `--max-execution-lag` adding a slot between authored blocks.
+	#[structopt(long = "execution-backoff-bias", value_name = "BLOCKS", default_value = "2")]
+	pub backoff_bias: u32,
+
+	/// Maximum number of slots between authored blocks when executors lag behind.
+	#[structopt(long = "execution-backoff-max-interval", value_name = "SLOTS", default_value = "100")]
+	pub backoff_max_interval: u32,
+
+	/// Sync the states at the latest block settled by receipts from other executors on startup.
+	///
+	/// Lets a new executor start executing from there instead of replaying the chain from
+	/// genesis. The execution state is checked against the state root executors agreed on, the
+	/// consensus state against the one of the block header.
+	#[structopt(long = "executor-state-sync")]
+	pub state_sync: bool,
+
+	/// Make this executor publish invalid receipts on purpose. For testing only.
+	///
+	/// `state-root` claims wrong state roots from some step of the block on, `drop-extrinsic`
+	/// claims the execution of the block without one of its steps, an extrinsic or a bundled
+	/// transaction. The node still executes and imports blocks correctly, and doesn't challenge
+	/// receipts itself.
+	#[structopt(
+		long = "invalid-execution",
+		value_name = "KIND",
+		possible_values = &InvalidExecution::variants(),
+		case_insensitive = true,
+	)]
+	pub invalid_execution: Option<InvalidExecution>,
+
+	/// Fraction of the blocks whose receipt is invalid with `--invalid-execution`.
+	#[structopt(
+		long = "invalid-execution-rate",
+		value_name = "RATE",
+		default_value = "1",
+		parse(try_from_str = parse_rate),
+	)]
+	pub invalid_execution_rate: f64,
+
+	/// Number of chunks of the bundles of each imported block to sample from peers, checking
+	/// that the bundles are available without downloading them. Zero disables sampling.
+	#[structopt(long = "availability-samples", value_name = "COUNT", default_value = "0")]
+	pub availability_samples: usize,
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+	match s.parse::<f64>() {
+		Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+		_ => Err(format!("`{}` is not a rate between 0 and 1", s)),
+	}
+}
+
+impl ExecutionParams {
+	/// Build the execution config of the service.
+	pub fn config(&self) -> ExecutionConfig {
+		ExecutionConfig {
+			role: self.role,
+			backpressure: self.max_execution_lag.map(|max_lag| BackpressureConfig {
+				max_lag,
+				authoring_bias: self.backoff_bias,
+				max_interval: self.backoff_max_interval,
+			}),
+			state_sync: self.state_sync,
+			invalid_execution: self.invalid_execution.map(|kind| InvalidExecutionConfig {
+				kind,
+				rate: self.invalid_execution_rate,
+			}),
+			availability_samples: self.availability_samples,
+		}
+	}
 }
 
 #[derive(Debug, StructOpt)]
@@ -0,0 +1,126 @@
+//! Reporting of Aura authorities sealing several blocks for the same slot.
+//!
+//! Aura's import queue only logs the equivocations it notices. Authorities keep the headers of
+//! the recent slots along with their author instead, and report on chain the authors sealing two
+//! different headers for the same slot, see `pallet_aura_equivocation`.
+
+use std::{collections::BTreeMap, sync::Arc};
+use futures::StreamExt;
+use sc_client_api::BlockchainEvents;
+use sp_api::ProvideRuntimeApi;
+use sp_aura_equivocation::{AuraEquivocationApi, AuthorityId, EquivocationProof, Slot, find_slot};
+use sp_blockchain::HeaderBackend;
+use sp_consensus_aura::AuraApi;
+use sp_runtime::{generic::BlockId, traits::{Block as BlockT, Header as HeaderT}};
+
+const LOG_TARGET: &str = "aura-equivocation";
+
+/// Number of slots behind the last one whose headers are kept.
+const SLOTS_KEPT: u64 = 1000;
+
+/// The headers of the recent slots, with their author.
+struct SeenHeaders<Header> {
+	slots: BTreeMap<Slot, Vec<(AuthorityId, Header)>>,
+}
+
+impl<Header: HeaderT> SeenHeaders<Header> {
+	/// Note that `author` sealed `header` for `slot`, returning another header it sealed for the
+	/// same slot, if any.
+	fn note(&mut self, slot: Slot, author: AuthorityId, header: Header) -> Option<Header> {
+		let headers = self.slots.entry(slot).or_default();
+		let hash = header.hash();
+		if let Some((_, other)) = headers.iter().find(|(a, h)| a == &author && h.hash() != hash) {
+			return Some(other.clone())
+		}
+		if !headers.iter().any(|(_, h)| h.hash() == hash) {
+			headers.push((author, header));
+		}
+
+		if let Some(last) = self.slots.keys().next_back().cloned() {
+			self.slots = self.slots.split_off(&Slot::from(last.saturating_sub(SLOTS_KEPT)));
+		}
+		None
+	}
+}
+
+/// The author of `header`: the authority whose turn it is at its slot.
+fn author_of<Block, Client>(client: &Client, header: &Block::Header, slot: Slot) -> Result<AuthorityId, String> where
+	Block: BlockT,
+	Client: ProvideRuntimeApi<Block>,
+	Client::Api: AuraApi<Block, AuthorityId>,
+{
+	let authorities = client.runtime_api()
+		.authorities(&BlockId::Hash(*header.parent_hash()))
+		.map_err(|e| format!("Unable to get the authorities of {}: {:?}", header.parent_hash(), e))?;
+	if authorities.is_empty() {
+		return Err(format!("No authorities at {}", header.parent_hash()))
+	}
+	Ok(authorities[(*slot % authorities.len() as u64) as usize].clone())
+}
+
+/// Report the equivocation of `equivocation_proof` on chain, with the proof that the offender is
+/// an authority of the session of the slot.
+fn report<Block, Client>(client: &Client, equivocation_proof: EquivocationProof<Block::Header>) -> Result<(), String> where
+	Block: BlockT,
+	Client: ProvideRuntimeApi<Block> + HeaderBackend<Block>,
+	Client::Api: AuraEquivocationApi<Block>,
+{
+	// the second header is imported, and its state is in the session of the slot.
+	let in_session = BlockId::Hash(equivocation_proof.second_header.hash());
+	let runtime_api = client.runtime_api();
+	let key_owner_proof = runtime_api
+		.generate_key_ownership_proof(&in_session, equivocation_proof.offender.clone())
+		.map_err(|e| format!("Unable to prove the key ownership: {:?}", e))?
+		.ok_or_else(|| format!("{:?} is no authority of the session of the slot", equivocation_proof.offender))?;
+	runtime_api
+		.submit_report_equivocation_unsigned_extrinsic(
+			&BlockId::Hash(client.info().best_hash),
+			equivocation_proof,
+			key_owner_proof,
+		)
+		.map_err(|e| format!("Unable to submit the report: {:?}", e))?
+		.ok_or_else(|| "The report was rejected by the transaction pool".to_string())
+}
+
+/// Watch the imported blocks for authorities sealing several of them for the same slot, and
+/// report them on chain.
+pub async fn start_equivocation_reporter<Block, Client>(client: Arc<Client>) where
+	Block: BlockT,
+	Client: ProvideRuntimeApi<Block> + HeaderBackend<Block> + BlockchainEvents<Block>,
+	Client::Api: AuraApi<Block, AuthorityId> + AuraEquivocationApi<Block>,
+{
+	let mut seen = SeenHeaders { slots: BTreeMap::new() };
+	let mut imports = client.import_notification_stream();
+
+	while let Some(notification) = imports.next().await {
+		let header = notification.header;
+		let slot = match find_slot(&header) {
+			Some(slot) => slot,
+			None => continue,
+		};
+		let author = match author_of::<Block, _>(&*client, &header, slot) {
+			Ok(author) => author,
+			Err(e) => {
+				log::debug!(target: LOG_TARGET, "{}", e);
+				continue
+			},
+		};
+
+		let first_header = match seen.note(slot, author.clone(), header.clone()) {
+			Some(first_header) => first_header,
+			None => continue,
+		};
+		log::warn!(target: LOG_TARGET, "{:?} sealed several blocks for slot {}", author, *slot);
+
+		let equivocation_proof = EquivocationProof {
+			offender: author,
+			slot,
+			first_header,
+			second_header: header,
+		};
+		match report::<Block, _>(&*client, equivocation_proof) {
+			Ok(()) => log::info!(target: LOG_TARGET, "Reported the equivocation at slot {}", *slot),
+			Err(e) => log::error!(target: LOG_TARGET, "Failed to report the equivocation at slot {}: {}", *slot, e),
+		}
+	}
+}
@@ -0,0 +1,161 @@
+//! Schema for the execution state kept in the client's auxiliary storage.
+
+use codec::{Decode, Encode};
+use sc_client_api::backend::AuxStore;
+use sp_blockchain::{Error as ClientError, Result as ClientResult};
+use sp_runtime::traits::{AtLeast32BitUnsigned, One};
+
+const STATE_ROOT_STATUS_KEY: &[u8] = b"execution_state_root_status";
+const EXECUTED_HEAD_KEY: &[u8] = b"execution_executed_head";
+const EXECUTION_ROOT_KEY: &[u8] = b"execution_root";
+const CONFIRMED_HEAD_KEY: &[u8] = b"execution_confirmed_head";
+const AVAILABILITY_KEY: &[u8] = b"execution_availability";
+const AVAILABILITY_PRUNED_KEY: &[u8] = b"execution_availability_pruned";
+
+/// What is known about the state root claimed in the header of a block.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum StateRootStatus<Hash> {
+	/// The block was imported without being executed, its state root is unchecked.
+	Pending,
+	/// The block was executed and produced the state root claimed in its header.
+	Confirmed,
+	/// The block was executed and produced the given state root instead of the claimed one.
+	Disputed(Hash),
+}
+
+fn load_decode<B: AuxStore, T: Decode>(backend: &B, key: &[u8]) -> ClientResult<Option<T>> {
+	match backend.get_aux(key)? {
+		None => Ok(None),
+		Some(t) => T::decode(&mut &t[..])
+			.map_err(|e| ClientError::Backend(
+				format!("Execution DB is corrupted. Decode error: {}", e),
+			))
+			.map(Some),
+	}
+}
+
+fn state_root_status_key<H: Encode>(block_hash: &H) -> Vec<u8> {
+	(STATE_ROOT_STATUS_KEY, block_hash).encode()
+}
+
+/// The aux entry recording the state root status of a block, to be written along with the block
+/// at import.
+pub fn state_root_status_entry<H: Encode>(
+	block_hash: &H,
+	status: &StateRootStatus<H>,
+) -> (Vec<u8>, Vec<u8>) {
+	(state_root_status_key(block_hash), status.encode())
+}
+
+/// Load the state root status of a block.
+pub fn load_state_root_status<B: AuxStore, H: Encode + Decode>(
+	backend: &B,
+	block_hash: &H,
+) -> ClientResult<Option<StateRootStatus<H>>> {
+	load_decode(backend, &state_root_status_key(block_hash))
+}
+
+/// The aux entry recording the last block executed by the executor.
+pub fn executed_head_entry<H: Encode, N: Encode>(block_hash: &H, number: &N) -> (Vec<u8>, Vec<u8>) {
+	(EXECUTED_HEAD_KEY.to_vec(), (block_hash, number).encode())
+}
+
+/// Load the last block executed by the executor.
+pub fn load_executed_head<B: AuxStore, H: Decode, N: Decode>(
+	backend: &B,
+) -> ClientResult<Option<(H, N)>> {
+	load_decode(backend, EXECUTED_HEAD_KEY)
+}
+
+fn execution_root_key<H: Encode>(block_hash: &H) -> Vec<u8> {
+	(EXECUTION_ROOT_KEY, block_hash).encode()
+}
+
+/// The aux entry recording the root of the execution state after a block, to be written along
+/// with its trie nodes.
+pub fn execution_root_entry<H: Encode>(block_hash: &H, root: &H) -> (Vec<u8>, Vec<u8>) {
+	(execution_root_key(block_hash), root.encode())
+}
+
+/// Load the root of the execution state after a block, if it was executed or synced here.
+pub fn load_execution_root<B: AuxStore, H: Encode + Decode>(
+	backend: &B,
+	block_hash: &H,
+) -> ClientResult<Option<H>> {
+	load_decode(backend, &execution_root_key(block_hash))
+}
+
+/// The aux entry recording the last execution-confirmed block.
+pub fn confirmed_head_entry<H: Encode, N: Encode>(block_hash: &H, number: &N) -> (Vec<u8>, Vec<u8>) {
+	(CONFIRMED_HEAD_KEY.to_vec(), (block_hash, number).encode())
+}
+
+/// Record the last execution-confirmed block.
+pub fn write_confirmed_head<B: AuxStore, H: Encode, N: Encode>(
+	backend: &B,
+	block_hash: &H,
+	number: &N,
+) -> ClientResult<()> {
+	let (key, value) = confirmed_head_entry(block_hash, number);
+	backend.insert_aux(&[(&key[..], &value[..])], &[])
+}
+
+/// Load the last execution-confirmed block.
+pub fn load_confirmed_head<B: AuxStore, H: Decode, N: Decode>(
+	backend: &B,
+) -> ClientResult<Option<(H, N)>> {
+	load_decode(backend, CONFIRMED_HEAD_KEY)
+}
+
+fn availability_key<N: Encode>(block_number: &N) -> Vec<u8> {
+	(AVAILABILITY_KEY, block_number).encode()
+}
+
+/// Record whether the bundles of a block were found available by sampling. The blocks of the same
+/// height are kept together, so that they can be pruned by number.
+pub fn write_availability<B: AuxStore, H: Encode + Decode + Clone + PartialEq, N: Encode>(
+	backend: &B,
+	block_number: &N,
+	block_hash: &H,
+	available: bool,
+) -> ClientResult<()> {
+	let key = availability_key(block_number);
+	let mut entries = load_decode::<_, Vec<(H, bool)>>(backend, &key)?.unwrap_or_default();
+	entries.retain(|(hash, _)| hash != block_hash);
+	entries.push((block_hash.clone(), available));
+	backend.insert_aux(&[(&key[..], &entries.encode()[..])], &[])
+}
+
+/// Load whether the bundles of a block were found available by sampling, if they were sampled and
+/// not pruned since.
+pub fn load_availability<B: AuxStore, H: Decode + PartialEq, N: Encode>(
+	backend: &B,
+	block_number: &N,
+	block_hash: &H,
+) -> ClientResult<Option<bool>> {
+	let entries = load_decode::<_, Vec<(H, bool)>>(backend, &availability_key(block_number))?;
+	Ok(entries.and_then(|entries| entries.into_iter()
+		.find(|(hash, _)| hash == block_hash)
+		.map(|(_, available)| available)
+	))
+}
+
+/// Forget whether the bundles of the blocks up to `block_number` were available, from the first
+/// height not pruned yet. Nothing is pruned the first time, as there is nothing recorded before.
+pub fn prune_availability<B: AuxStore, N: AtLeast32BitUnsigned + Encode + Decode + Copy>(
+	backend: &B,
+	block_number: N,
+) -> ClientResult<()> {
+	let mut next = match load_decode::<_, N>(backend, AVAILABILITY_PRUNED_KEY)? {
+		Some(pruned) if pruned >= block_number => return Ok(()),
+		Some(pruned) => pruned + One::one(),
+		None => block_number + One::one(),
+	};
+	let mut keys = Vec::new();
+	while next <= block_number {
+		keys.push(availability_key(&next));
+		next += One::one();
+	}
+	let keys = keys.iter().map(|key| &key[..]).collect::<Vec<_>>();
+	backend.insert_aux(&[(AVAILABILITY_PRUNED_KEY, &block_number.encode()[..])], &keys)
+}
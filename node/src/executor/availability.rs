@@ -0,0 +1,290 @@
+//! Availability of the bundles ordered in blocks.
+//!
+//! The runtime commits to the erasure coded bundles of each block in its header digest, see
+//! [`sp_executor::availability`]. Nodes importing blocks without executing them check that the
+//! commitment matches the bundles in the body, and full nodes serve the chunks of the blocks they
+//! have along with their proofs, on the `/bundles/availability/1` request-response protocol.
+//!
+//! Nodes started with `--availability-samples` check that the bundles of every block they import
+//! can be retrieved, without downloading them: they request that many random chunks from random
+//! peers and check them against the commitment. The outcome is kept in the auxiliary storage for
+//! [`AVAILABILITY_PRUNING_DEPTH`] blocks after their finalization.
+//!
+//! This is a prototype, with no data availability guarantee. Nodes serve chunks they compute from
+//! the whole body of the block, so a successful sample only shows that the peers asked have the
+//! bundles, not that the chunks are spread out enough for the bundles to be recovered if those
+//! peers withhold them. Serving chunks from nodes that only hold their own share of them is left
+//! for later.
+
+use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc, time::Duration};
+use codec::{Decode, Encode};
+use futures::{channel::mpsc, future, stream::FuturesUnordered, StreamExt};
+use rand::seq::{IteratorRandom, SliceRandom};
+use sc_client_api::{AuxStore, BlockBackend, BlockchainEvents};
+use sc_network::{
+	Event, IfDisconnected, NetworkService, PeerId,
+	config::{IncomingRequest, OutgoingResponse, RequestResponseConfig},
+};
+use sp_blockchain::HeaderBackend;
+use sp_executor::{
+	SignedOpaqueBundle,
+	availability::{self, AvailabilityCommitment, ChunkWithProof, TOTAL_CHUNKS},
+};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, Header as HeaderT, NumberFor, Saturating, Zero},
+};
+use super::{LOG_TARGET, aux_schema};
+
+/// Name of the request-response protocol full nodes serve the chunks of bundles on.
+pub const AVAILABILITY_PROTOCOL_NAME: &str = "/bundles/availability/1";
+
+/// Number of blocks whose chunks are kept around to answer requests.
+const CHUNKS_CACHE_SIZE: usize = 16;
+
+/// Number of peers a chunk is requested from before giving up on it.
+const MAX_ATTEMPTS: usize = 3;
+
+/// Number of blocks the availability of the bundles of a block is kept for after its
+/// finalization.
+const AVAILABILITY_PRUNING_DEPTH: u32 = 256;
+
+/// Finds the bundle submitted by an encoded extrinsic, if any, see
+/// [`sp_executor::ExtractBundle`].
+pub type ExtractBundleFn = fn(&[u8]) -> Option<SignedOpaqueBundle>;
+
+/// Request for a chunk of the bundles of a block.
+#[derive(Debug, Encode, Decode)]
+struct ChunkRequest<Hash> {
+	block: Hash,
+	index: u32,
+}
+
+/// The commitment to the bundles among `extrinsics` and their chunks, if there are any.
+fn commit_bundles<Block: BlockT>(
+	extrinsics: &[Block::Extrinsic],
+	extract_bundle: ExtractBundleFn,
+) -> Option<(AvailabilityCommitment, Vec<Vec<u8>>)> {
+	let bundles = extrinsics.iter()
+		.filter_map(|extrinsic| extract_bundle(&extrinsic.encode()))
+		.collect::<Vec<_>>();
+	availability::commit_bundles(&bundles)
+}
+
+/// Check that the availability commitment in `header` is the one to the bundles of `extrinsics`.
+pub fn check_commitment<Block: BlockT>(
+	header: &Block::Header,
+	extrinsics: &[Block::Extrinsic],
+	extract_bundle: ExtractBundleFn,
+) -> Result<(), String> {
+	let claimed = availability::find_commitment(header.digest().logs());
+	let expected = commit_bundles::<Block>(extrinsics, extract_bundle).map(|(commitment, _)| commitment);
+	if claimed != expected {
+		return Err(format!(
+			"Availability commitment mismatch: header has {:?}, bundles commit to {:?}",
+			claimed,
+			expected,
+		))
+	}
+	Ok(())
+}
+
+/// Returns the configuration value to put in
+/// [`sc_network::config::NetworkConfiguration::request_response_protocols`]. Incoming requests
+/// are sent to `inbound_queue`, to be answered by [`start_chunk_request_handler`]. Nodes that
+/// don't serve chunks, e.g. light clients, pass `None`.
+pub fn availability_protocol_config(inbound_queue: Option<mpsc::Sender<IncomingRequest>>) -> RequestResponseConfig {
+	RequestResponseConfig {
+		name: AVAILABILITY_PROTOCOL_NAME.into(),
+		max_request_size: 1024,
+		// a chunk is an eighth of the bundles of a block, plus its proof.
+		max_response_size: 4 * 1024 * 1024,
+		request_timeout: Duration::from_secs(10),
+		inbound_queue,
+	}
+}
+
+/// The chunks of the blocks requests were last answered for.
+struct ChunksCache<Hash> {
+	chunks: HashMap<Hash, Vec<Vec<u8>>>,
+	order: VecDeque<Hash>,
+}
+
+impl<Hash: Copy + Eq + std::hash::Hash> ChunksCache<Hash> {
+	fn get_or_insert_with(
+		&mut self,
+		block: Hash,
+		chunks: impl FnOnce() -> Result<Vec<Vec<u8>>, String>,
+	) -> Result<&Vec<Vec<u8>>, String> {
+		if !self.chunks.contains_key(&block) {
+			let chunks = chunks()?;
+			if self.order.len() == CHUNKS_CACHE_SIZE {
+				if let Some(oldest) = self.order.pop_front() {
+					self.chunks.remove(&oldest);
+				}
+			}
+			self.order.push_back(block);
+			self.chunks.insert(block, chunks);
+		}
+		Ok(&self.chunks[&block])
+	}
+}
+
+/// Answer the chunk requests of other nodes, for the blocks we have the body of.
+///
+/// The chunks are computed from the whole body, see the module documentation.
+pub async fn start_chunk_request_handler<Block, Client>(
+	client: Arc<Client>,
+	mut requests: mpsc::Receiver<IncomingRequest>,
+	extract_bundle: ExtractBundleFn,
+) where
+	Block: BlockT,
+	Client: BlockBackend<Block>,
+{
+	let mut cache = ChunksCache { chunks: HashMap::new(), order: VecDeque::new() };
+	while let Some(IncomingRequest { peer, payload, pending_response }) = requests.next().await {
+		let response = ChunkRequest::<Block::Hash>::decode(&mut &payload[..])
+			.map_err(|e| e.to_string())
+			.and_then(|ChunkRequest { block, index }| {
+				let chunks = cache.get_or_insert_with(block, || {
+					let extrinsics = client.block_body(&BlockId::Hash(block))
+						.map_err(|e| e.to_string())?
+						.ok_or_else(|| format!("No body for {}", block))?;
+					commit_bundles::<Block>(&extrinsics, extract_bundle)
+						.map(|(_, chunks)| chunks)
+						.ok_or_else(|| format!("No bundles in {}", block))
+				})?;
+				let chunk = chunks.get(index as usize)
+					.ok_or_else(|| format!("No chunk #{}", index))?
+					.clone();
+				let proof = availability::chunk_proof(chunks, index as usize)?;
+				Ok(ChunkWithProof { index, chunk, proof })
+			});
+
+		let result = match response {
+			Ok(response) => Ok(response.encode()),
+			Err(e) => {
+				log::debug!(target: LOG_TARGET, "Unable to answer the chunk request of {}: {}", peer, e);
+				Err(())
+			},
+		};
+		let _ = pending_response.send(OutgoingResponse {
+			result,
+			reputation_changes: Vec::new(),
+			sent_feedback: None,
+		});
+	}
+}
+
+/// Fetch the chunk at `index` of the bundles of `block` from up to [`MAX_ATTEMPTS`] of `peers`,
+/// returning whether one of them returned it with a valid proof.
+async fn fetch_chunk<Block: BlockT>(
+	network: &NetworkService<Block, Block::Hash>,
+	peers: &[PeerId],
+	block: Block::Hash,
+	commitment: &AvailabilityCommitment,
+	index: u32,
+) -> bool {
+	let candidates = peers.choose_multiple(&mut rand::thread_rng(), MAX_ATTEMPTS)
+		.cloned()
+		.collect::<Vec<_>>();
+	let request = ChunkRequest { block, index }.encode();
+	for peer in candidates {
+		let result = network.request(
+			peer.clone(),
+			AVAILABILITY_PROTOCOL_NAME,
+			request.clone(),
+			IfDisconnected::ImmediateError,
+		).await;
+		match result.map(|encoded| ChunkWithProof::decode(&mut &encoded[..])) {
+			Ok(Ok(chunk)) if chunk.index == index && chunk.verify(commitment) => return true,
+			Ok(_) => log::debug!(target: LOG_TARGET, "Bad chunk #{} of {} from {}", index, block, peer),
+			Err(e) => log::debug!(target: LOG_TARGET, "Chunk request to {} failed: {:?}", peer, e),
+		}
+	}
+	false
+}
+
+/// Sample `samples` distinct random chunks of the bundles of `block` from `peers`, returning
+/// whether all of them could be retrieved.
+async fn sample<Block: BlockT>(
+	network: Arc<NetworkService<Block, Block::Hash>>,
+	peers: Vec<PeerId>,
+	(number, block): (NumberFor<Block>, Block::Hash),
+	commitment: AvailabilityCommitment,
+	samples: usize,
+) -> (NumberFor<Block>, Block::Hash, bool) {
+	let indices = (0..TOTAL_CHUNKS as u32).choose_multiple(&mut rand::thread_rng(), samples);
+	let fetched = future::join_all(indices.into_iter().map(|index| {
+		fetch_chunk::<Block>(&network, &peers, block, &commitment, index)
+	})).await;
+	(number, block, fetched.into_iter().all(|fetched| fetched))
+}
+
+/// Check the availability of the bundles of every imported block by sampling `samples` of their
+/// chunks from the peers we are connected to, and record the outcome until the block is
+/// [`AVAILABILITY_PRUNING_DEPTH`] blocks behind the finalized head.
+pub async fn start_availability_sampler<Block, Client>(
+	client: Arc<Client>,
+	network: Arc<NetworkService<Block, Block::Hash>>,
+	samples: usize,
+) where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + BlockchainEvents<Block> + AuxStore,
+{
+	let mut events = network.event_stream("availability-sampler").fuse();
+	let mut imports = client.import_notification_stream().fuse();
+	let mut finality_notifications = client.finality_notification_stream().fuse();
+	let mut sampling = FuturesUnordered::new();
+	let mut peers = HashSet::new();
+
+	loop {
+		futures::select! {
+			event = events.next() => match event {
+				Some(Event::SyncConnected { remote }) => { peers.insert(remote); },
+				Some(Event::SyncDisconnected { remote }) => { peers.remove(&remote); },
+				Some(_) => {},
+				None => return,
+			},
+			notification = imports.next() => match notification {
+				Some(notification) => {
+					let commitment = match availability::find_commitment(notification.header.digest().logs()) {
+						Some(commitment) => commitment,
+						None => continue,
+					};
+					sampling.push(sample::<Block>(
+						network.clone(),
+						peers.iter().cloned().collect(),
+						(*notification.header.number(), notification.hash),
+						commitment,
+						samples.min(TOTAL_CHUNKS),
+					));
+				},
+				None => return,
+			},
+			notification = finality_notifications.next() => match notification {
+				Some(notification) => {
+					let pruned = notification.header.number().saturating_sub(AVAILABILITY_PRUNING_DEPTH.into());
+					if pruned.is_zero() {
+						continue
+					}
+					if let Err(e) = aux_schema::prune_availability(&*client, pruned) {
+						log::error!(target: LOG_TARGET, "Failed to prune the availability of old blocks: {:?}", e);
+					}
+				},
+				None => return,
+			},
+			sampled = sampling.select_next_some() => {
+				let (number, block, available) = sampled;
+				if available {
+					log::trace!(target: LOG_TARGET, "Bundles of {} are available", block);
+				} else {
+					log::warn!(target: LOG_TARGET, "Bundles of {} could not be sampled, they may be withheld", block);
+				}
+				if let Err(e) = aux_schema::write_availability(&*client, &number, &block, available) {
+					log::error!(target: LOG_TARGET, "Failed to record the availability of {}: {:?}", block, e);
+				}
+			},
+		}
+	}
+}
@@ -0,0 +1,87 @@
+//! Backpressure from executors on block authoring.
+//!
+//! Consensus nodes can order blocks much faster than executors can execute them. Authorities learn
+//! how far the chain is executed from the receipts on chain, and slow authoring down when
+//! executors fall too far behind. The chain counts as executed as far as a majority of the
+//! executors got without being proven wrong, so that a single executor racing ahead with made up
+//! receipts doesn't lift the backpressure.
+
+use std::{marker::PhantomData, sync::Arc};
+use sc_consensus_slots::BackoffAuthoringBlocksStrategy;
+use sp_api::ProvideRuntimeApi;
+use sp_consensus_slots::Slot;
+use sp_executor::ExecutorApi;
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, NumberFor, Saturating, UniqueSaturatedInto},
+};
+
+/// When and how much to slow authoring down.
+#[derive(Debug, Clone)]
+pub struct BackpressureConfig {
+	/// Number of blocks executors can lag behind the chain head before authoring slows down.
+	pub max_lag: u32,
+	/// The number of slots between authored blocks grows by one for every `authoring_bias`
+	/// blocks of lag beyond `max_lag`.
+	pub authoring_bias: u32,
+	/// Upper bound of the number of slots between authored blocks.
+	pub max_interval: u32,
+}
+
+/// Backs off authoring while executors lag behind the chain head.
+///
+/// Authoring never stops completely: receipts only make it on chain in new blocks, so the chain
+/// has to move on for executors to catch up.
+pub struct ExecutionBackoff<Block, Client> {
+	client: Arc<Client>,
+	config: BackpressureConfig,
+	_phantom: PhantomData<Block>,
+}
+
+impl<Block, Client> ExecutionBackoff<Block, Client> {
+	/// Create a new strategy reading the majority executed head from the state of `client`.
+	pub fn new(client: Arc<Client>, config: BackpressureConfig) -> Self {
+		ExecutionBackoff { client, config, _phantom: PhantomData }
+	}
+}
+
+impl<Block, Client> BackoffAuthoringBlocksStrategy<NumberFor<Block>> for ExecutionBackoff<Block, Client> where
+	Block: BlockT,
+	Client: ProvideRuntimeApi<Block>,
+	Client::Api: ExecutorApi<Block>,
+{
+	fn should_backoff(
+		&self,
+		chain_head_number: NumberFor<Block>,
+		chain_head_slot: Slot,
+		_finalized_number: NumberFor<Block>,
+		slot_now: Slot,
+		logging_target: &str,
+	) -> bool {
+		let at = BlockId::Number(chain_head_number);
+		let executed = match self.client.runtime_api().majority_executed_head(&at) {
+			Ok(executed) => executed,
+			Err(e) => {
+				log::warn!(target: logging_target, "Unable to read the majority executed head: {:?}", e);
+				return false
+			},
+		};
+
+		let lag: u64 = chain_head_number.saturating_sub(executed).unique_saturated_into();
+		let excess = lag.saturating_sub(self.config.max_lag.into());
+		let interval = (excess / u64::from(self.config.authoring_bias.max(1)))
+			.min(self.config.max_interval.into());
+
+		if *slot_now <= *chain_head_slot + interval {
+			log::debug!(
+				target: logging_target,
+				"Backing off authoring, executors are {} blocks behind #{}",
+				lag,
+				chain_head_number,
+			);
+			return true
+		}
+
+		false
+	}
+}
@@ -0,0 +1,253 @@
+//! Bundling of user transactions by executors.
+//!
+//! Executors take the user transactions out of their pool and submit them to the chain as a
+//! signed bundle. Consensus nodes author blocks out of an [`OrderingPool`], which keeps user
+//! transactions out of blocks unless they come in a bundle.
+
+use std::{collections::{HashMap, HashSet}, pin::Pin, sync::Arc};
+use codec::{Decode, Encode};
+use futures::{Future, FutureExt, StreamExt};
+use sc_client_api::{BlockBackend, BlockchainEvents};
+use sp_api::ProvideRuntimeApi;
+use sp_core::H256;
+use sp_executor::{Bundle, BundleApi, SignedBundle};
+use sp_keystore::SyncCryptoStorePtr;
+use sp_runtime::{
+	OpaqueExtrinsic,
+	generic::BlockId,
+	traits::{Block as BlockT, Header as HeaderT, NumberFor, Saturating},
+	transaction_validity::TransactionSource,
+};
+use sp_transaction_pool::{
+	ImportNotificationStream, InPoolTransaction, PoolFuture, PoolStatus, TransactionFor,
+	TransactionPool, TransactionStatusStreamFor, TxHash,
+};
+use super::{LOG_TARGET, availability::ExtractBundleFn, gossip::{ExecutorGossip, GossipMessage}, keys};
+
+/// Maximum number of transactions in a bundle. The runtime skips the ones that don't fit in the
+/// weight of a bundle anyway.
+const MAX_BUNDLE_EXTRINSICS: usize = 256;
+
+/// Number of blocks after which the transactions of a bundle no finalized block included are
+/// bundled again.
+const BUNDLE_TIMEOUT: u32 = 16;
+
+/// A bundle we submitted, waiting for a finalized block to include it.
+struct PendingBundle<Number, Hash> {
+	/// Number of the best block the bundle was submitted at.
+	submitted_at: Number,
+	/// Hashes of the transactions of the bundle in the local pool.
+	transactions: Vec<Hash>,
+}
+
+/// The view of the transaction pool consensus nodes author blocks from.
+///
+/// Transactions for which `is_bundled` holds are left out, they only make it into blocks inside
+/// the bundle of an executor.
+pub struct OrderingPool<Pool: TransactionPool> {
+	inner: Arc<Pool>,
+	is_bundled: fn(&TransactionFor<Pool>) -> bool,
+}
+
+impl<Pool: TransactionPool> OrderingPool<Pool> {
+	/// Wrap the given pool.
+	pub fn new(inner: Arc<Pool>, is_bundled: fn(&TransactionFor<Pool>) -> bool) -> Self {
+		OrderingPool { inner, is_bundled }
+	}
+}
+
+impl<Pool: TransactionPool> TransactionPool for OrderingPool<Pool> {
+	type Block = Pool::Block;
+	type Hash = Pool::Hash;
+	type InPoolTransaction = Pool::InPoolTransaction;
+	type Error = Pool::Error;
+
+	fn submit_at(
+		&self,
+		at: &BlockId<Self::Block>,
+		source: TransactionSource,
+		xts: Vec<TransactionFor<Self>>,
+	) -> PoolFuture<Vec<Result<TxHash<Self>, Self::Error>>, Self::Error> {
+		self.inner.submit_at(at, source, xts)
+	}
+
+	fn submit_one(
+		&self,
+		at: &BlockId<Self::Block>,
+		source: TransactionSource,
+		xt: TransactionFor<Self>,
+	) -> PoolFuture<TxHash<Self>, Self::Error> {
+		self.inner.submit_one(at, source, xt)
+	}
+
+	fn submit_and_watch(
+		&self,
+		at: &BlockId<Self::Block>,
+		source: TransactionSource,
+		xt: TransactionFor<Self>,
+	) -> PoolFuture<Box<TransactionStatusStreamFor<Self>>, Self::Error> {
+		self.inner.submit_and_watch(at, source, xt)
+	}
+
+	fn ready_at(
+		&self,
+		at: NumberFor<Self::Block>,
+	) -> Pin<Box<dyn Future<Output = Box<dyn Iterator<Item = Arc<Self::InPoolTransaction>> + Send>> + Send>> {
+		let is_bundled = self.is_bundled;
+		self.inner.ready_at(at)
+			.map(move |ready| -> Box<dyn Iterator<Item = _> + Send> {
+				Box::new(ready.filter(move |tx| !is_bundled(tx.data())))
+			})
+			.boxed()
+	}
+
+	fn ready(&self) -> Box<dyn Iterator<Item = Arc<Self::InPoolTransaction>> + Send> {
+		let is_bundled = self.is_bundled;
+		Box::new(self.inner.ready().filter(move |tx| !is_bundled(tx.data())))
+	}
+
+	fn remove_invalid(&self, hashes: &[TxHash<Self>]) -> Vec<Arc<Self::InPoolTransaction>> {
+		self.inner.remove_invalid(hashes)
+	}
+
+	fn status(&self) -> PoolStatus {
+		self.inner.status()
+	}
+
+	fn import_notification_stream(&self) -> ImportNotificationStream<TxHash<Self>> {
+		self.inner.import_notification_stream()
+	}
+
+	fn on_broadcasted(&self, propagations: HashMap<TxHash<Self>, Vec<String>>) {
+		self.inner.on_broadcasted(propagations)
+	}
+
+	fn hash_of(&self, xt: &TransactionFor<Self>) -> TxHash<Self> {
+		self.inner.hash_of(xt)
+	}
+
+	fn ready_transaction(&self, hash: &TxHash<Self>) -> Option<Arc<Self::InPoolTransaction>> {
+		self.inner.ready_transaction(hash)
+	}
+}
+
+/// Start bundling the user transactions of the pool, whenever a new best block is imported.
+///
+/// Bundled transactions stay in the local pool until a finalized block includes their bundle, they
+/// are only left out of the next bundles meanwhile. The bundle is gossiped to the other executors.
+pub async fn start_bundle_producer<Block, Client, Pool>(
+	client: Arc<Client>,
+	transaction_pool: Arc<Pool>,
+	keystore: SyncCryptoStorePtr,
+	gossip: ExecutorGossip<Block>,
+	is_bundled: fn(&Block::Extrinsic) -> bool,
+	extract_bundle: ExtractBundleFn,
+) where
+	Block: BlockT,
+	Client: BlockchainEvents<Block> + BlockBackend<Block> + ProvideRuntimeApi<Block>,
+	Client::Api: BundleApi<Block>,
+	Pool: TransactionPool<Block = Block>,
+{
+	let signer = match keys::executor_key(&keystore) {
+		Some(signer) => signer,
+		None => {
+			log::warn!(target: LOG_TARGET, "No executor key in the keystore, not producing bundles");
+			return
+		},
+	};
+
+	let mut imports = client.import_notification_stream().fuse();
+	let mut finality_notifications = client.finality_notification_stream().fuse();
+	let mut pending = HashMap::<H256, PendingBundle<NumberFor<Block>, TxHash<Pool>>>::new();
+	let mut bundled = HashSet::<TxHash<Pool>>::new();
+
+	loop {
+		futures::select! {
+			notification = imports.next() => {
+				let notification = match notification {
+					Some(notification) => notification,
+					None => return,
+				};
+				if !notification.is_new_best {
+					continue
+				}
+
+				let (hashes, extrinsics): (Vec<_>, Vec<_>) = transaction_pool.ready()
+					.filter(|tx| is_bundled(tx.data()) && !bundled.contains(tx.hash()))
+					.take(MAX_BUNDLE_EXTRINSICS)
+					.map(|tx| (tx.hash().clone(), tx.data().clone()))
+					.unzip();
+				if extrinsics.is_empty() {
+					continue
+				}
+
+				let extrinsics = extrinsics.iter()
+					.map(|xt| OpaqueExtrinsic::decode(&mut &xt.encode()[..]))
+					.collect::<Result<Vec<_>, _>>();
+				let bundle = match extrinsics {
+					Ok(extrinsics) => Bundle { extrinsics },
+					Err(e) => {
+						log::error!(target: LOG_TARGET, "Bundle not submitted, failed to encode transactions: {:?}", e);
+						continue
+					},
+				};
+				let signature = match keys::sign(&keystore, &signer, &bundle.hash()) {
+					Ok(signature) => signature,
+					Err(e) => {
+						log::error!(target: LOG_TARGET, "Bundle not submitted: {}", e);
+						continue
+					},
+				};
+				let signed_bundle = SignedBundle { bundle, signer: signer.clone(), signature };
+
+				// the best block of executors is the executed head, its state is there.
+				let at = BlockId::Hash(notification.hash);
+				match client.runtime_api().submit_bundle_unsigned(&at, signed_bundle.clone()) {
+					Ok(Some(())) => {
+						log::debug!(target: LOG_TARGET, "Submitted a bundle of {} transactions", hashes.len());
+						bundled.extend(hashes.iter().cloned());
+						pending.insert(
+							signed_bundle.bundle.hash(),
+							PendingBundle { submitted_at: *notification.header.number(), transactions: hashes },
+						);
+						gossip.gossip(GossipMessage::Bundle(signed_bundle));
+					},
+					Ok(None) => log::warn!(target: LOG_TARGET, "Bundle rejected by the pool"),
+					Err(e) => log::error!(target: LOG_TARGET, "Failed to submit bundle: {:?}", e),
+				}
+			},
+			notification = finality_notifications.next() => {
+				let notification = match notification {
+					Some(notification) => notification,
+					None => return,
+				};
+
+				let extrinsics = match client.block_body(&BlockId::Hash(notification.hash)) {
+					Ok(Some(extrinsics)) => extrinsics,
+					Ok(None) => Vec::new(),
+					Err(e) => {
+						log::error!(target: LOG_TARGET, "Failed to read the body of {}: {:?}", notification.hash, e);
+						Vec::new()
+					},
+				};
+				// the transactions of our included bundles are applied now, they can go.
+				for signed_bundle in extrinsics.iter().filter_map(|xt| extract_bundle(&xt.encode())) {
+					if let Some(included) = pending.remove(&signed_bundle.bundle.hash()) {
+						transaction_pool.remove_invalid(&included.transactions);
+						included.transactions.iter().for_each(|hash| { bundled.remove(hash); });
+					}
+				}
+
+				// the others may never make it, give their transactions another chance.
+				let finalized = *notification.header.number();
+				pending.retain(|_, bundle| {
+					let timed_out = bundle.submitted_at.saturating_add(BUNDLE_TIMEOUT.into()) < finalized;
+					if timed_out {
+						bundle.transactions.iter().for_each(|hash| { bundled.remove(hash); });
+					}
+					!timed_out
+				});
+			},
+		}
+	}
+}
@@ -0,0 +1,178 @@
+//! Tracking of the execution-confirmed head.
+//!
+//! GRANDPA finalizes the order of blocks, not their state: the receipts of a finalized block can
+//! still be challenged for the challenge period. A finalized block is execution-confirmed once
+//! the runtime settled its receipts, in agreement with the execution state root of the block on
+//! executors, and the state root wasn't disputed locally, which is when consumers needing settled
+//! state can rely on it.
+
+use std::sync::Arc;
+use futures::StreamExt;
+use sc_client_api::{AuxStore, BlockchainEvents};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::{HeaderBackend, Result as ClientResult};
+use sp_executor::ExecutorApi;
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, Header as HeaderT, NumberFor, One},
+};
+use super::{ExecutionRole, LOG_TARGET, aux_schema::{self, StateRootStatus}, execution_state};
+
+/// The last execution-confirmed block, the genesis block until another one is confirmed.
+pub fn confirmed_head<Block, Client>(client: &Client) -> ClientResult<(Block::Hash, NumberFor<Block>)> where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + AuxStore,
+{
+	match aux_schema::load_confirmed_head(client)? {
+		Some(head) => Ok(head),
+		None => Ok((client.info().genesis_hash, 0u32.into())),
+	}
+}
+
+/// Whether a block with the given state root status can be confirmed.
+///
+/// Blocks imported without execution are confirmed by their challenge period running out,
+/// except on executors, which vouch for the blocks they executed only.
+fn is_confirmable<Hash>(status: Option<StateRootStatus<Hash>>, require_execution: bool) -> bool {
+	match status {
+		// executed on import.
+		None | Some(StateRootStatus::Confirmed) => true,
+		Some(StateRootStatus::Pending) => !require_execution,
+		Some(StateRootStatus::Disputed(_)) => false,
+	}
+}
+
+/// The last block executed by the executor, the genesis block until it executes one. Blocks past
+/// it are imported without their state.
+fn executed_state_at<Block, Client>(client: &Client) -> ClientResult<BlockId<Block>> where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + AuxStore,
+{
+	let head = aux_schema::load_executed_head::<_, Block::Hash, NumberFor<Block>>(client)?
+		.map_or(client.info().genesis_hash, |(hash, _)| hash);
+	Ok(BlockId::Hash(head))
+}
+
+/// The most recent finalized block whose state is available locally, if any: the finalized block
+/// on nodes executing blocks on import, or the executed head on executors, which lags behind it.
+/// Other nodes import blocks without their state.
+pub(crate) fn settled_state_at<Block, Client>(
+	client: &Client,
+	execution_role: ExecutionRole,
+	is_authority: bool,
+) -> ClientResult<Option<BlockId<Block>>> where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + AuxStore,
+{
+	if !execution_role.defers_execution(is_authority) {
+		Ok(Some(BlockId::Hash(client.info().finalized_hash)))
+	} else if execution_role.is_executor() {
+		executed_state_at(client).map(Some)
+	} else {
+		Ok(None)
+	}
+}
+
+/// Move the execution-confirmed head forward along the finalized chain, up to the block the
+/// runtime confirmed at `at`.
+fn advance<Block, Client>(client: &Client, at: &BlockId<Block>, require_execution: bool) -> ClientResult<()> where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + AuxStore + ProvideRuntimeApi<Block>,
+	Client::Api: ExecutorApi<Block>,
+{
+	let settled = match client.runtime_api().confirmed_head(at)? {
+		Some(settled) => settled,
+		None => return Ok(()),
+	};
+	let header = match client.header(BlockId::Number(settled.number))? {
+		Some(header) if header.hash() == settled.hash => header,
+		_ => {
+			log::warn!(target: LOG_TARGET, "Runtime confirmed #{} ({}), which isn't finalized here", settled.number, settled.hash);
+			return Ok(())
+		},
+	};
+	// only executors keep the execution state the receipts are about.
+	match execution_state::execution_root(client, header.hash())? {
+		Some(root) if root != settled.state_root => {
+			log::warn!(
+				target: LOG_TARGET,
+				"Receipts of #{} settled on state root {}, we executed it to {}",
+				settled.number,
+				settled.state_root,
+				root,
+			);
+			return Ok(())
+		},
+		_ => {},
+	}
+	let target = settled.number;
+
+	let (confirmed_hash, confirmed_number) = confirmed_head::<Block, _>(client)?;
+	let (mut hash, mut number) = (confirmed_hash, confirmed_number);
+
+	while number < target {
+		let next_number = number + One::one();
+		let next_hash = match client.hash(next_number)? {
+			Some(next_hash) => next_hash,
+			None => break,
+		};
+		if !is_confirmable(aux_schema::load_state_root_status(client, &next_hash)?, require_execution) {
+			break
+		}
+
+		hash = next_hash;
+		number = next_number;
+	}
+
+	if number != confirmed_number {
+		aux_schema::write_confirmed_head(client, &hash, &number)?;
+		log::debug!(target: LOG_TARGET, "Execution-confirmed head is now #{} ({})", number, hash);
+	}
+
+	Ok(())
+}
+
+/// The number of blocks during which receipts can be challenged, as configured in the runtime at
+/// the executed head of the executor, whose later blocks have no state.
+pub(crate) fn challenge_period<Block, Client>(client: &Client) -> ClientResult<NumberFor<Block>> where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + AuxStore + ProvideRuntimeApi<Block>,
+	Client::Api: ExecutorApi<Block>,
+{
+	Ok(client.runtime_api().challenge_period(&executed_state_at(client)?)?)
+}
+
+/// Track the execution-confirmed head of a node with the given role, following finality
+/// notifications.
+///
+/// The confirmed head of the runtime is read at the last finalized block with state on every
+/// notification. Executors only confirm the blocks they executed. Nodes importing blocks without
+/// their state and without executing them can't read it, and don't track anything.
+pub async fn start_confirmation_tracker<Block, Client>(
+	client: Arc<Client>,
+	execution_role: ExecutionRole,
+	is_authority: bool,
+) where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + BlockchainEvents<Block> + AuxStore + ProvideRuntimeApi<Block>,
+	Client::Api: ExecutorApi<Block>,
+{
+	let mut finality_notifications = client.finality_notification_stream();
+	while finality_notifications.next().await.is_some() {
+		let at = match settled_state_at::<Block, _>(&*client, execution_role, is_authority) {
+			Ok(Some(at)) => at,
+			Ok(None) => {
+				log::debug!(target: LOG_TARGET, "No state to read the execution-confirmed head at");
+				return
+			},
+			Err(e) => {
+				log::error!(target: LOG_TARGET, "Failed to find the last block with state: {:?}", e);
+				continue
+			},
+		};
+		if let Err(e) = advance::<Block, _>(&*client, &at, execution_role.is_executor()) {
+			log::error!(target: LOG_TARGET, "Failed to advance the execution-confirmed head: {:?}", e);
+		}
+	}
+}
+
@@ -0,0 +1,274 @@
+//! The execution state, which executors keep next to the consensus state.
+//!
+//! Consensus only orders bundles: the state block headers commit to, the consensus state, goes
+//! through the extrinsics of blocks, and the ones submitting bundles merely record them. Executors
+//! also apply the transactions of the bundles, to an execution state of their own which goes
+//! through the [steps](ExecutionStep) of blocks. Receipts, fraud proofs and stateless execution
+//! are all about the execution state.
+//!
+//! The trie nodes of the execution state after a block are stored in the state database along
+//! with the ones of the consensus state of the block, and its root in the auxiliary storage. Both
+//! states start out as the genesis state.
+
+use std::{marker::PhantomData, sync::Arc};
+use codec::{Decode, Encode};
+use hash_db::{Hasher, Prefix};
+use sc_client_api::backend::AuxStore;
+use sp_blockchain::{Error as ClientError, HeaderBackend, Result as ClientResult};
+use sp_core::traits::{CodeExecutor, RuntimeCode, SpawnNamed};
+use sp_executor::{ExecutionLayout, ExecutionStep, FraudProofExtension, VerifyFraudProof};
+use sp_externalities::Extensions;
+use sp_runtime::{
+	ApplyExtrinsicResult,
+	generic::BlockId,
+	traits::{Block as BlockT, HashFor, Header as HeaderT, NumberFor},
+	transaction_validity::TransactionValidityError,
+};
+use sp_state_machine::{
+	Backend as StateBackend, ExecutionStrategy, OverlayedChanges, StateMachine, StorageChanges,
+	StorageTransactionCache, TrieBackend, TrieBackendStorage, backend::BackendRuntimeCode,
+};
+use sp_trie::{DBValue, PrefixedMemoryDB};
+use super::{availability::ExtractBundleFn, aux_schema, pre_seal_header};
+
+/// Runtime method initializing a block.
+pub(super) const INITIALIZE_BLOCK: &str = "Core_initialize_block";
+/// Runtime method applying an extrinsic of the block.
+pub(super) const APPLY_EXTRINSIC: &str = "BlockBuilder_apply_extrinsic";
+/// Runtime method applying a transaction of a bundle.
+pub(super) const APPLY_BUNDLED_EXTRINSIC: &str = "BundleApi_apply_bundled_extrinsic";
+/// Runtime method finalizing a block.
+pub(super) const FINALIZE_BLOCK: &str = "BlockBuilder_finalize_block";
+
+/// The root of the execution state after the block with the given hash, if it is kept here.
+pub(crate) fn execution_root<Block, Client>(
+	client: &Client,
+	hash: Block::Hash,
+) -> ClientResult<Option<Block::Hash>> where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + AuxStore,
+{
+	if let Some(root) = aux_schema::load_execution_root(client, &hash)? {
+		return Ok(Some(root))
+	}
+
+	if hash != client.info().genesis_hash {
+		return Ok(None)
+	}
+	let genesis = client.header(BlockId::Hash(hash))?
+		.ok_or_else(|| ClientError::UnknownBlock(format!("{}", hash)))?;
+	Ok(Some(*genesis.state_root()))
+}
+
+/// Trie storage of the state database, without the root of any particular state.
+pub(crate) struct StateStorage<'a, S>(&'a S);
+
+impl<'a, S, H> TrieBackendStorage<H> for StateStorage<'a, S> where
+	S: TrieBackendStorage<H>,
+	H: Hasher,
+{
+	type Overlay = PrefixedMemoryDB<H>;
+
+	fn get(&self, key: &H::Out, prefix: Prefix) -> Result<Option<DBValue>, String> {
+		self.0.get(key, prefix)
+	}
+}
+
+/// The execution state with the given root, read from the database `state` comes from. `state`
+/// is the consensus state of a block the execution state is kept along with, e.g. the same block.
+pub(crate) fn execution_state<'a, Block, S>(
+	state: &'a S,
+	root: Block::Hash,
+) -> ClientResult<TrieBackend<StateStorage<'a, S::TrieBackendStorage>, HashFor<Block>>> where
+	Block: BlockT,
+	S: StateBackend<HashFor<Block>>,
+{
+	let trie_backend = state.as_trie_backend()
+		.ok_or_else(|| ClientError::Msg("The execution state is only kept along trie backends".into()))?;
+	Ok(TrieBackend::new(StateStorage(trie_backend.backend_storage()), root))
+}
+
+/// What `step` of the block with the given header and extrinsics is applied with: the encoded
+/// header, seal included, to initialize the block, the encoded extrinsic at
+/// `step.extrinsic_index()` to apply it or its bundle, and nothing to finalize the block.
+pub(crate) fn step_data<Block: BlockT>(
+	header: &Block::Header,
+	extrinsics: &[Block::Extrinsic],
+	step: ExecutionStep,
+) -> Vec<u8> {
+	match step.extrinsic_index() {
+		Some(index) => extrinsics[index as usize].encode(),
+		None if step == ExecutionStep::InitializeBlock => header.encode(),
+		None => Vec::new(),
+	}
+}
+
+/// Apply `step` of a block, `data` being what the step is applied with, see [`step_data`], by
+/// calling the runtime with `call`.
+///
+/// Extrinsics of the block the runtime rejects are left out of the execution state, as they
+/// would be out of a block being built: the consensus state accepted them, but their signer may
+/// e.g. not afford their fees on this side. Bundled transactions the runtime rejects are skipped
+/// by the runtime itself.
+pub(crate) fn apply_step<Block: BlockT>(
+	overlay: &mut OverlayedChanges,
+	step: ExecutionStep,
+	data: &[u8],
+	call: impl Fn(&mut OverlayedChanges, &str, &[u8]) -> Result<Vec<u8>, String>,
+) -> Result<(), String> {
+	match step {
+		ExecutionStep::InitializeBlock => {
+			let header = Block::Header::decode(&mut &data[..])
+				.map_err(|e| format!("Failed to decode header: {}", e))?;
+			call(overlay, INITIALIZE_BLOCK, &pre_seal_header::<Block>(&header).encode()).map(|_| ())
+		},
+		ExecutionStep::ApplyExtrinsic(index) => {
+			overlay.start_transaction();
+			let result = call(overlay, APPLY_EXTRINSIC, data).and_then(|result| {
+				ApplyExtrinsicResult::decode(&mut &result[..])
+					.map_err(|e| format!("Failed to decode result of extrinsic #{}: {}", index, e))
+			});
+			let closed = match result {
+				Ok(Ok(_)) => overlay.commit_transaction(),
+				Ok(Err(_)) | Err(_) => overlay.rollback_transaction(),
+			};
+			closed.map_err(|_| "No transaction open".to_string())?;
+			result.map(|_| ())
+		},
+		ExecutionStep::ApplyBundledExtrinsic(index, position) => {
+			let result = call(overlay, APPLY_BUNDLED_EXTRINSIC, &(data, position).encode())?;
+			Result::<(), TransactionValidityError>::decode(&mut &result[..])
+				.map(|_| ())
+				.map_err(|e| format!("Failed to decode result of bundled extrinsic #{}.{}: {}", index, position, e))
+		},
+		ExecutionStep::FinalizeBlock => call(overlay, FINALIZE_BLOCK, &[]).map(|_| ()),
+	}
+}
+
+/// Runs blocks step by step on the execution state.
+#[derive(Clone)]
+pub struct StepExecutor<Block, Exec, Spawn> {
+	executor: Exec,
+	spawn_handle: Spawn,
+	fraud_proof_verifier: Arc<dyn VerifyFraudProof + Send + Sync>,
+	extract_bundle: ExtractBundleFn,
+	_phantom: PhantomData<Block>,
+}
+
+impl<Block, Exec, Spawn> StepExecutor<Block, Exec, Spawn> where
+	Block: BlockT,
+	Exec: CodeExecutor + Clone + 'static,
+	Spawn: SpawnNamed + Clone + Send + 'static,
+{
+	/// Create a new step executor. `extract_bundle` finds the bundles of blocks, fraud proofs
+	/// submitted by blocks are checked with `fraud_proof_verifier`.
+	pub fn new(
+		executor: Exec,
+		spawn_handle: Spawn,
+		fraud_proof_verifier: Arc<dyn VerifyFraudProof + Send + Sync>,
+		extract_bundle: ExtractBundleFn,
+	) -> Self {
+		StepExecutor { executor, spawn_handle, fraud_proof_verifier, extract_bundle, _phantom: PhantomData }
+	}
+
+	/// The steps of a block with the given extrinsics.
+	pub fn layout(&self, extrinsics: &[Block::Extrinsic]) -> ExecutionLayout {
+		let encoded = extrinsics.iter().map(Encode::encode).collect::<Vec<_>>();
+		ExecutionLayout::new(&encoded, self.extract_bundle)
+	}
+
+	/// Call `method` of the runtime on top of `backend` and `overlay`.
+	pub(crate) fn call<B: StateBackend<HashFor<Block>>>(
+		&self,
+		backend: &B,
+		overlay: &mut OverlayedChanges,
+		runtime_code: &RuntimeCode,
+		method: &str,
+		call_data: &[u8],
+	) -> Result<Vec<u8>, String> {
+		let mut extensions = Extensions::new();
+		extensions.register(FraudProofExtension(self.fraud_proof_verifier.clone()));
+
+		StateMachine::<_, HashFor<Block>, NumberFor<Block>, _>::new(
+			backend,
+			None,
+			overlay,
+			&self.executor,
+			method,
+			call_data,
+			extensions,
+			runtime_code,
+			self.spawn_handle.clone(),
+		)
+			.execute(ExecutionStrategy::NativeElseWasm)
+			.map_err(|e| format!("{} failed: {}", method, e))
+	}
+
+	/// Apply `step` on top of `backend` and `overlay`, see [`apply_step`].
+	pub(crate) fn apply_step<B: StateBackend<HashFor<Block>>>(
+		&self,
+		backend: &B,
+		overlay: &mut OverlayedChanges,
+		runtime_code: &RuntimeCode,
+		step: ExecutionStep,
+		data: &[u8],
+	) -> Result<(), String> {
+		apply_step::<Block>(overlay, step, data, |overlay, method, call_data| {
+			self.call(backend, overlay, runtime_code, method, call_data)
+		})
+	}
+
+	/// Run `steps` of the block with the given header and extrinsics on top of its parent
+	/// execution state `backend`, leaving out the step at index `skipped`, if any. Returns the
+	/// roots of the state before the steps and after each of them, the skipped one included.
+	pub(crate) fn run_steps<B: StateBackend<HashFor<Block>>>(
+		&self,
+		backend: &B,
+		overlay: &mut OverlayedChanges,
+		header: &Block::Header,
+		extrinsics: &[Block::Extrinsic],
+		steps: &[ExecutionStep],
+		skipped: Option<usize>,
+	) -> Result<Vec<Block::Hash>, String> {
+		let backend_runtime_code = BackendRuntimeCode::new(backend);
+		let runtime_code = backend_runtime_code.runtime_code()?;
+		let root = |overlay: &OverlayedChanges| {
+			let mut cache = StorageTransactionCache::<_, HashFor<Block>, NumberFor<Block>>::default();
+			overlay.storage_root(backend, &mut cache)
+		};
+
+		let mut trace = Vec::with_capacity(steps.len() + 1);
+		trace.push(root(overlay));
+		for (index, step) in steps.iter().enumerate() {
+			if skipped != Some(index) {
+				let data = step_data::<Block>(header, extrinsics, *step);
+				self.apply_step(backend, overlay, &runtime_code, *step, &data)?;
+			}
+			trace.push(root(overlay));
+		}
+		Ok(trace)
+	}
+
+	/// Execute the block with the given header and extrinsics on top of its parent execution
+	/// state `backend`, leaving out the step at index `skipped`, if any. Returns the changes of the
+	/// execution state, along with the trace of the execution, which ends with their root.
+	pub(crate) fn execute<B: StateBackend<HashFor<Block>>>(
+		&self,
+		backend: &B,
+		header: &Block::Header,
+		extrinsics: &[Block::Extrinsic],
+		skipped: Option<usize>,
+	) -> Result<(StorageChanges<B::Transaction, HashFor<Block>, NumberFor<Block>>, Vec<Block::Hash>), String> {
+		let steps = self.layout(extrinsics).steps();
+		let mut overlay = OverlayedChanges::default();
+		let trace = self.run_steps(backend, &mut overlay, header, extrinsics, &steps, skipped)?;
+
+		let changes = overlay.drain_storage_changes(
+			backend,
+			None,
+			*header.parent_hash(),
+			&mut StorageTransactionCache::default(),
+		)?;
+		Ok((changes, trace))
+	}
+}
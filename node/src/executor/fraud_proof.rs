@@ -0,0 +1,266 @@
+//! Generation and verification of fraud proofs.
+//!
+//! A fraud proof disputes a single step of a block: it carries the part of the execution state
+//! the step touches, proven against the pre state root claimed by the receipt. Verifiers apply
+//! the step on top of that partial state and compare the resulting state root with the claimed
+//! post state root.
+//!
+//! The runtime code is part of the proven state, so that every verifier applies the step with
+//! the same code, whatever it has kept of the chain state.
+
+use std::{marker::PhantomData, sync::Arc};
+use codec::{Decode, Encode};
+use hash_db::{HashDB, Prefix};
+use sc_client_api::{
+	AuxStore,
+	backend::Backend as BackendT,
+	execution_extensions::ExtensionsFactory,
+};
+use sp_api::StateBackend;
+use sp_blockchain::{Error as ClientError, HeaderBackend, Result as ClientResult};
+use sp_core::{
+	offchain::Capabilities,
+	storage::well_known_keys,
+	traits::{CodeExecutor, SpawnNamed},
+};
+use sp_executor::{
+	ExecuteBlockStateless, ExecutorId, FraudProof, FraudProofExtension, StatelessExecutionExtension,
+	VerifyFraudProof,
+};
+use sp_externalities::Extensions;
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, HashFor, Header as HeaderT, NumberFor},
+};
+use sp_state_machine::{
+	backend::BackendRuntimeCode, ExecutionStrategy, OverlayedChanges, ProvingBackend, StateMachine,
+	StorageTransactionCache, TrieBackend, TrieBackendStorage,
+};
+use sp_trie::{DBValue, PrefixedMemoryDB};
+use super::{
+	execution_state::{self, StepExecutor},
+	stateless::proof_post_state_root,
+};
+
+/// Trie storage made of the nodes written by a partial execution of a block, on top of the
+/// storage of the parent state.
+struct DeltaBackend<'a, S, H: hash_db::Hasher> {
+	backend: &'a S,
+	delta: &'a PrefixedMemoryDB<H>,
+}
+
+impl<'a, S, H> TrieBackendStorage<H> for DeltaBackend<'a, S, H> where
+	S: TrieBackendStorage<H>,
+	H: hash_db::Hasher,
+{
+	type Overlay = S::Overlay;
+
+	fn get(&self, key: &H::Out, prefix: Prefix) -> Result<Option<DBValue>, String> {
+		match HashDB::get(self.delta, key, prefix) {
+			Some(value) => Ok(Some(value)),
+			None => self.backend.get(key, prefix),
+		}
+	}
+}
+
+/// Builds fraud proofs from the execution state kept locally.
+pub struct FraudProofGenerator<Block, Client, Backend, Exec, Spawn> {
+	client: Arc<Client>,
+	backend: Arc<Backend>,
+	steps: StepExecutor<Block, Exec, Spawn>,
+}
+
+impl<Block, Client, Backend, Exec, Spawn> FraudProofGenerator<Block, Client, Backend, Exec, Spawn> where
+	Block: BlockT,
+	Backend: BackendT<Block>,
+	Client: HeaderBackend<Block> + sc_client_api::BlockBackend<Block> + AuxStore,
+	Exec: CodeExecutor + Clone + 'static,
+	Spawn: SpawnNamed + Clone + Send + 'static,
+{
+	/// Create a new generator on top of the given client, running the steps of blocks with
+	/// `steps`.
+	pub fn new(client: Arc<Client>, backend: Arc<Backend>, steps: StepExecutor<Block, Exec, Spawn>) -> Self {
+		FraudProofGenerator { client, backend, steps }
+	}
+
+	/// Prove that `executor` got the execution state root after the step at `step_index` of the
+	/// block with the given hash wrong, by claiming `post_state_root`.
+	///
+	/// The parent execution state of the block must be available locally. The pre state is
+	/// rebuilt by running the steps preceding the disputed one, the proof records the trie nodes
+	/// read while running the disputed step and computing the resulting state root.
+	pub fn prove(
+		&self,
+		hash: Block::Hash,
+		step_index: u32,
+		executor: ExecutorId,
+		post_state_root: Block::Hash,
+	) -> ClientResult<FraudProof<NumberFor<Block>, Block::Hash>> {
+		let id = BlockId::Hash(hash);
+		let header = self.client.header(id)?
+			.ok_or_else(|| ClientError::UnknownBlock(format!("{}", hash)))?;
+		let extrinsics = self.client.block_body(&id)?
+			.ok_or_else(|| ClientError::UnknownBlock(format!("Missing body of {}", hash)))?;
+		let steps = self.steps.layout(&extrinsics).steps();
+		let step = *steps.get(step_index as usize)
+			.ok_or_else(|| ClientError::Msg(format!("Block {} has no step #{}", hash, step_index)))?;
+		let extrinsic = execution_state::step_data::<Block>(&header, &extrinsics, step);
+
+		let parent_hash = *header.parent_hash();
+		let parent_root = execution_state::execution_root::<Block, _>(&*self.client, parent_hash)?
+			.ok_or_else(|| ClientError::Msg(format!("No execution state at {}", parent_hash)))?;
+		let state = self.backend.state_at(BlockId::Hash(parent_hash))?;
+		let parent_state = execution_state::execution_state::<Block, _>(&state, parent_root)?;
+
+		let mut overlay = OverlayedChanges::default();
+		self.steps.run_steps(
+			&parent_state,
+			&mut overlay,
+			&header,
+			&extrinsics,
+			&steps[..step_index as usize],
+			None,
+		).map_err(|e| ClientError::Msg(format!("Failed to rebuild the pre state of {}: {}", hash, e)))?;
+		let delta = overlay
+			.drain_storage_changes(
+				&parent_state,
+				None,
+				parent_hash,
+				&mut StorageTransactionCache::<_, _, NumberFor<Block>>::default(),
+			)
+			.map_err(ClientError::Msg)?;
+		let pre_state_root = delta.transaction_storage_root;
+
+		let delta_backend = TrieBackend::new(
+			DeltaBackend { backend: parent_state.backend_storage(), delta: &delta.transaction },
+			pre_state_root,
+		);
+		let proving_backend = ProvingBackend::new(&delta_backend);
+
+		// the code is read through the proving backend so that it ends up in the proof, whether
+		// the executor has it cached or not.
+		proving_backend.storage(well_known_keys::CODE)
+			.map_err(|e| ClientError::Msg(format!("Failed to read the runtime code: {}", e)))?
+			.ok_or_else(|| ClientError::Msg(format!("No runtime code in the execution state of {}", parent_hash)))?;
+		let runtime_code = BackendRuntimeCode::new(&proving_backend);
+		let runtime_code = runtime_code.runtime_code().map_err(|e| ClientError::Msg(e.into()))?;
+
+		let mut overlay = OverlayedChanges::default();
+		self.steps.apply_step(&proving_backend, &mut overlay, &runtime_code, step, &extrinsic)
+			.map_err(|e| ClientError::Msg(format!("Failed to run step #{} of {}: {}", step_index, hash, e)))?;
+
+		let mut cache = StorageTransactionCache::<_, _, NumberFor<Block>>::default();
+		if overlay.storage_root(&proving_backend, &mut cache) == post_state_root {
+			return Err(ClientError::Msg(format!(
+				"Step #{} of {} does lead to {}, there is no fraud to prove",
+				step_index,
+				hash,
+				post_state_root,
+			)))
+		}
+
+		// the header the block is initialized with is checked against its hash instead.
+		let extrinsic_proof = match step.extrinsic_index() {
+			Some(extrinsic_index) => {
+				let encoded_extrinsics = extrinsics.iter().map(Encode::encode).collect::<Vec<_>>();
+				sp_executor::prove_extrinsic::<HashFor<Block>>(&encoded_extrinsics, extrinsic_index)
+					.ok_or_else(|| ClientError::Msg(format!("Failed to prove extrinsic #{} of {}", extrinsic_index, hash)))?
+			},
+			None => Vec::new(),
+		};
+
+		Ok(FraudProof {
+			primary_number: *header.number(),
+			primary_hash: hash,
+			executor,
+			step,
+			extrinsic,
+			extrinsic_proof,
+			pre_state_root,
+			post_state_root,
+			proof: proving_backend.extract_proof(),
+		})
+	}
+}
+
+/// Verifies fraud proofs on behalf of the runtime, see [`sp_executor::executor_ext`].
+pub struct FraudProofVerifier<Block, Exec, Spawn> {
+	executor: Exec,
+	spawn_handle: Spawn,
+	_phantom: PhantomData<Block>,
+}
+
+impl<Block, Exec, Spawn> FraudProofVerifier<Block, Exec, Spawn> {
+	/// Create a new verifier. The runtime code is read from the fraud proofs themselves.
+	pub fn new(executor: Exec, spawn_handle: Spawn) -> Self {
+		FraudProofVerifier { executor, spawn_handle, _phantom: PhantomData }
+	}
+}
+
+impl<Block, Exec, Spawn> VerifyFraudProof for FraudProofVerifier<Block, Exec, Spawn> where
+	Block: BlockT,
+	Exec: CodeExecutor + Clone + 'static,
+	Spawn: SpawnNamed + Clone + Send + 'static,
+{
+	fn verify_fraud_proof(&self, encoded_fraud_proof: &[u8]) -> Result<bool, String> {
+		let fraud_proof = FraudProof::<NumberFor<Block>, Block::Hash>::decode(&mut &encoded_fraud_proof[..])
+			.map_err(|e| format!("Failed to decode fraud proof: {}", e))?;
+
+		let proof_backend = sp_state_machine::create_proof_check_backend::<HashFor<Block>>(
+			fraud_proof.pre_state_root,
+			fraud_proof.proof,
+		).map_err(|e| e.to_string())?;
+
+		// the step is run with the runtime of the pre-state, as proven.
+		let runtime_code = BackendRuntimeCode::new(&proof_backend);
+		let runtime_code = runtime_code.runtime_code()?;
+
+		let mut overlay = OverlayedChanges::default();
+		execution_state::apply_step::<Block>(&mut overlay, fraud_proof.step, &fraud_proof.extrinsic, |overlay, method, call_data| {
+			StateMachine::<_, HashFor<Block>, NumberFor<Block>, _>::new(
+				&proof_backend,
+				None,
+				overlay,
+				&self.executor,
+				method,
+				call_data,
+				Extensions::default(),
+				&runtime_code,
+				self.spawn_handle.clone(),
+			)
+				.execute(ExecutionStrategy::NativeElseWasm)
+				.map_err(|e| e.to_string())
+		})?;
+
+		// an incomplete proof must not pass for fraud.
+		let post_state_root = proof_post_state_root::<Block>(&proof_backend, &overlay)?;
+
+		Ok(post_state_root != fraud_proof.post_state_root)
+	}
+}
+
+/// Registers the [`FraudProofExtension`] and the [`StatelessExecutionExtension`] for every
+/// runtime call.
+pub struct ExecutorExtensionsFactory {
+	fraud_proof_verifier: Arc<dyn VerifyFraudProof + Send + Sync>,
+	stateless_executor: Arc<dyn ExecuteBlockStateless + Send + Sync>,
+}
+
+impl ExecutorExtensionsFactory {
+	/// Create a new factory handing out the given implementations.
+	pub fn new(
+		fraud_proof_verifier: Arc<dyn VerifyFraudProof + Send + Sync>,
+		stateless_executor: Arc<dyn ExecuteBlockStateless + Send + Sync>,
+	) -> Self {
+		ExecutorExtensionsFactory { fraud_proof_verifier, stateless_executor }
+	}
+}
+
+impl ExtensionsFactory for ExecutorExtensionsFactory {
+	fn extensions_for(&self, _capabilities: Capabilities) -> Extensions {
+		let mut extensions = Extensions::new();
+		extensions.register(FraudProofExtension(self.fraud_proof_verifier.clone()));
+		extensions.register(StatelessExecutionExtension(self.stateless_executor.clone()));
+		extensions
+	}
+}
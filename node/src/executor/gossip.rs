@@ -0,0 +1,181 @@
+//! Gossip of receipts, bundles and fraud proofs among executors.
+//!
+//! Executors compare their results off chain: receipts only need to go on chain when they are
+//! challenged. The protocol is only registered by executors, other nodes never see it.
+
+use std::{borrow::Cow, collections::HashSet, sync::Arc};
+use codec::{Decode, Encode};
+use futures::{future, FutureExt, Stream, StreamExt};
+use parking_lot::{Mutex, RwLock};
+use sc_network::{ObservedRole, PeerId};
+use sc_network_gossip::{
+	GossipEngine, Network as GossipNetwork, ValidationResult, Validator, ValidatorContext,
+};
+use sp_executor::{FraudProof, SignedExecutionReceipt, SignedOpaqueBundle};
+use sp_runtime::traits::{Block as BlockT, Hash as HashT, NumberFor, Saturating};
+use super::LOG_TARGET;
+
+/// Name of the notifications protocol executors gossip on.
+pub const EXECUTOR_PROTOCOL_NAME: &str = "/executor/1";
+
+/// Number of blocks after their execution during which receipts and fraud proofs are gossiped.
+const GOSSIP_LIFETIME: u32 = 256;
+
+/// Returns the configuration value to put in
+/// [`sc_network::config::NetworkConfiguration::extra_sets`].
+pub fn executor_peers_set_config() -> sc_network::config::NonDefaultSetConfig {
+	sc_network::config::NonDefaultSetConfig {
+		notifications_protocol: EXECUTOR_PROTOCOL_NAME.into(),
+		// receipts carry one root per extrinsic and fraud proofs a storage proof.
+		max_notification_size: 4 * 1024 * 1024,
+		set_config: sc_network::config::SetConfig {
+			in_peers: 0,
+			out_peers: 0,
+			reserved_nodes: Vec::new(),
+			non_reserved_mode: sc_network::config::NonReservedPeerMode::Deny,
+		},
+	}
+}
+
+/// A message gossiped among executors.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum GossipMessage<Number, Hash> {
+	/// The receipt of an executor.
+	Receipt(SignedExecutionReceipt<Number, Hash>),
+	/// A bundle submitted by an executor.
+	Bundle(SignedOpaqueBundle),
+	/// A fraud proof against the receipt of an executor.
+	FraudProof(FraudProof<Number, Hash>),
+}
+
+impl<Number: Copy, Hash> GossipMessage<Number, Hash> {
+	/// Number of the block the message is about, if any.
+	fn block_number(&self) -> Option<Number> {
+		match self {
+			GossipMessage::Receipt(receipt) => Some(receipt.execution_receipt.primary_number),
+			GossipMessage::Bundle(_) => None,
+			GossipMessage::FraudProof(fraud_proof) => Some(fraud_proof.primary_number),
+		}
+	}
+}
+
+/// The single topic of the protocol.
+fn topic<Block: BlockT>() -> Block::Hash {
+	<<Block::Header as sp_runtime::traits::Header>::Hashing as HashT>::hash(b"executor")
+}
+
+/// Checks the signatures of gossiped messages and expires the ones about blocks executed long
+/// ago. Also keeps track of the executors connected to us.
+struct GossipValidator<Block: BlockT> {
+	executed_number: RwLock<NumberFor<Block>>,
+	peers: RwLock<HashSet<PeerId>>,
+}
+
+impl<Block: BlockT> GossipValidator<Block> {
+	fn is_expired(&self, message: &GossipMessage<NumberFor<Block>, Block::Hash>) -> bool {
+		let oldest = self.executed_number.read().saturating_sub(GOSSIP_LIFETIME.into());
+		message.block_number().map_or(false, |number| number < oldest)
+	}
+}
+
+impl<Block: BlockT> Validator<Block> for GossipValidator<Block> {
+	fn new_peer(&self, _context: &mut dyn ValidatorContext<Block>, who: &PeerId, _role: ObservedRole) {
+		self.peers.write().insert(who.clone());
+	}
+
+	fn peer_disconnected(&self, _context: &mut dyn ValidatorContext<Block>, who: &PeerId) {
+		self.peers.write().remove(who);
+	}
+
+	fn validate(
+		&self,
+		_context: &mut dyn ValidatorContext<Block>,
+		sender: &PeerId,
+		mut data: &[u8],
+	) -> ValidationResult<Block::Hash> {
+		let message = match GossipMessage::<NumberFor<Block>, Block::Hash>::decode(&mut data) {
+			Ok(message) => message,
+			Err(e) => {
+				log::debug!(target: LOG_TARGET, "Undecodable gossip message from {}: {:?}", sender, e);
+				return ValidationResult::Discard
+			},
+		};
+
+		match &message {
+			GossipMessage::Receipt(receipt) if !receipt.verify() => ValidationResult::Discard,
+			GossipMessage::Bundle(bundle) if !bundle.verify() => ValidationResult::Discard,
+			_ if self.is_expired(&message) => ValidationResult::Discard,
+			// bundles are submitted on chain as soon as they are made, no need to keep them around.
+			GossipMessage::Bundle(_) => ValidationResult::ProcessAndDiscard(topic::<Block>()),
+			_ => ValidationResult::ProcessAndKeep(topic::<Block>()),
+		}
+	}
+
+	fn message_expired<'a>(&'a self) -> Box<dyn FnMut(Block::Hash, &[u8]) -> bool + 'a> {
+		Box::new(move |_topic, mut data| {
+			GossipMessage::<NumberFor<Block>, Block::Hash>::decode(&mut data)
+				.map_or(true, |message| self.is_expired(&message))
+		})
+	}
+}
+
+/// Handle to the executor gossip. Clones share the same engine.
+pub struct ExecutorGossip<Block: BlockT> {
+	engine: Arc<Mutex<GossipEngine<Block>>>,
+	validator: Arc<GossipValidator<Block>>,
+}
+
+impl<Block: BlockT> Clone for ExecutorGossip<Block> {
+	fn clone(&self) -> Self {
+		ExecutorGossip { engine: self.engine.clone(), validator: self.validator.clone() }
+	}
+}
+
+impl<Block: BlockT> ExecutorGossip<Block> {
+	/// Start gossiping on the executor protocol of `network`. The returned handle must be
+	/// [`run`](Self::run) for messages to go anywhere.
+	pub fn new<N>(network: N, registry: Option<&prometheus_endpoint::Registry>) -> Self where
+		N: GossipNetwork<Block> + Clone + Send + 'static,
+	{
+		let validator = Arc::new(GossipValidator {
+			executed_number: RwLock::new(0u32.into()),
+			peers: RwLock::new(HashSet::new()),
+		});
+		let engine = GossipEngine::new(
+			network,
+			Cow::Borrowed(EXECUTOR_PROTOCOL_NAME),
+			validator.clone(),
+			registry,
+		);
+
+		ExecutorGossip { engine: Arc::new(Mutex::new(engine)), validator }
+	}
+
+	/// Send `message` to the other executors.
+	pub fn gossip(&self, message: GossipMessage<NumberFor<Block>, Block::Hash>) {
+		self.engine.lock().gossip_message(topic::<Block>(), message.encode(), false);
+	}
+
+	/// The messages received from other executors, starting with the ones still kept around.
+	pub fn messages(&self) -> impl Stream<Item = GossipMessage<NumberFor<Block>, Block::Hash>> {
+		self.engine.lock().messages_for(topic::<Block>()).filter_map(|notification| {
+			future::ready(GossipMessage::decode(&mut &notification.message[..]).ok())
+		})
+	}
+
+	/// The executors we are connected to.
+	pub fn peers(&self) -> Vec<PeerId> {
+		self.validator.peers.read().iter().cloned().collect()
+	}
+
+	/// Note that the block with the given number has been executed, messages about blocks far
+	/// enough behind it are dropped.
+	pub fn note_executed(&self, number: NumberFor<Block>) {
+		*self.validator.executed_number.write() = number;
+	}
+
+	/// Drive the gossip engine. Resolves when the network goes away.
+	pub async fn run(self) {
+		future::poll_fn(|cx| self.engine.lock().poll_unpin(cx)).await
+	}
+}
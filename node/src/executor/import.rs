@@ -0,0 +1,133 @@
+//! Block import that orders blocks without executing them.
+
+use std::{collections::HashMap, marker::PhantomData};
+use codec::Encode;
+use sp_consensus::{
+	BlockCheckParams, BlockImport, BlockImportParams, Error as ConsensusError, ForkChoiceStrategy,
+	ImportResult,
+};
+use sp_runtime::traits::{Block as BlockT, Hash as HashT, HashFor, Header as HeaderT};
+use super::{
+	ExecutionRole, LOG_TARGET,
+	availability::{self, ExtractBundleFn},
+	aux_schema::{self, StateRootStatus},
+};
+
+/// A block import that accepts blocks without executing them.
+///
+/// By the time a block reaches this import its Aura seal, signature and slot have already been
+/// checked by the import queue's verifier. On top of that we only check that the body matches the
+/// extrinsics root in the header, and that the bundles in it match the availability commitment
+/// in the header digest. The claimed state root is recorded as
+/// [`StateRootStatus::Pending`] and left for executors to confirm or dispute.
+///
+/// Blocks that come with their storage changes, e.g. the ones we authored, are imported as usual.
+///
+/// On executors, blocks imported without execution never become the best block. The best block
+/// is the last one executed instead, which keeps the transaction pool on top of a known state.
+pub struct OrderingBlockImport<Block, I> {
+	inner: I,
+	defer_execution: bool,
+	best_is_executed: bool,
+	extract_bundle: ExtractBundleFn,
+	_phantom: PhantomData<Block>,
+}
+
+impl<Block, I: Clone> Clone for OrderingBlockImport<Block, I> {
+	fn clone(&self) -> Self {
+		OrderingBlockImport {
+			inner: self.inner.clone(),
+			defer_execution: self.defer_execution,
+			best_is_executed: self.best_is_executed,
+			extract_bundle: self.extract_bundle,
+			_phantom: PhantomData,
+		}
+	}
+}
+
+impl<Block, I> OrderingBlockImport<Block, I> {
+	/// Wrap the given block import for a node with the given role. Blocks are passed through
+	/// untouched unless the role defers execution. `extract_bundle` finds the bundles in block
+	/// bodies.
+	pub fn new(
+		inner: I,
+		execution_role: ExecutionRole,
+		is_authority: bool,
+		extract_bundle: ExtractBundleFn,
+	) -> Self {
+		OrderingBlockImport {
+			inner,
+			defer_execution: execution_role.defers_execution(is_authority),
+			best_is_executed: execution_role.is_executor(),
+			extract_bundle,
+			_phantom: PhantomData,
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl<Block, I, Transaction> BlockImport<Block> for OrderingBlockImport<Block, I> where
+	Block: BlockT,
+	I: BlockImport<Block, Transaction = Transaction> + Send + Sync,
+	I::Error: Into<ConsensusError>,
+	Transaction: Default + Send + 'static,
+{
+	type Error = ConsensusError;
+	type Transaction = Transaction;
+
+	async fn check_block(
+		&mut self,
+		block: BlockCheckParams<Block>,
+	) -> Result<ImportResult, Self::Error> {
+		self.inner.check_block(block).await.map_err(Into::into)
+	}
+
+	async fn import_block(
+		&mut self,
+		mut block: BlockImportParams<Block, Self::Transaction>,
+		new_cache: HashMap<sp_consensus::import_queue::CacheKeyId, Vec<u8>>,
+	) -> Result<ImportResult, Self::Error> {
+		if !self.defer_execution || block.storage_changes.is_some() {
+			return self.inner.import_block(block, new_cache).await.map_err(Into::into)
+		}
+
+		let hash = block.post_hash();
+		let body = block.body.as_ref().ok_or_else(|| ConsensusError::ClientImport(
+			format!("Block {} is missing its body", hash)
+		))?;
+
+		let extrinsics_root = HashFor::<Block>::ordered_trie_root(
+			body.iter().map(Encode::encode).collect(),
+		);
+		if &extrinsics_root != block.header.extrinsics_root() {
+			return Err(ConsensusError::ClientImport(format!(
+				"Extrinsics root mismatch for {}: header has {}, body hashes to {}",
+				hash,
+				block.header.extrinsics_root(),
+				extrinsics_root,
+			)))
+		}
+		availability::check_commitment::<Block>(&block.header, body, self.extract_bundle)
+			.map_err(|e| ConsensusError::ClientImport(format!("{} for {}", e, hash)))?;
+
+		log::trace!(
+			target: LOG_TARGET,
+			"Importing {} without execution, state root {} pending",
+			hash,
+			block.header.state_root(),
+		);
+
+		// Empty storage changes keep the client from executing the block. The parent may itself
+		// have been imported without its state, which is fine as we don't need it.
+		block.storage_changes = Some(Default::default());
+		block.allow_missing_state = true;
+		if self.best_is_executed {
+			block.fork_choice = Some(ForkChoiceStrategy::Custom(false));
+		}
+
+		let (key, value) = aux_schema::state_root_status_entry(&hash, &StateRootStatus::Pending);
+		block.auxiliary.push((key, Some(value)));
+
+		self.inner.import_block(block, new_cache).await.map_err(Into::into)
+	}
+}
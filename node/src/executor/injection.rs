@@ -0,0 +1,95 @@
+//! Invalid execution injection, for testing.
+//!
+//! An executor configured with an [`InvalidExecutionConfig`] still executes and imports every
+//! block honestly, so that it can keep following the chain, but the receipts it gossips and
+//! submits on chain claim a wrong execution for some of the blocks. This is the local adversary
+//! that fraud proofs, slashing and receipt invalidation are exercised against.
+//!
+//! Which blocks are tampered with, and from which step on, is drawn from the block hash, so
+//! that runs are reproducible.
+
+use std::{fmt, str::FromStr};
+use codec::Encode;
+use sp_core::hashing::blake2_256;
+use sp_executor::ExecutionReceipt;
+use sp_runtime::traits::Hash as HashT;
+
+/// How an executor gets the execution of a block wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidExecution {
+	/// Claim wrong state roots after some step of the block, and for the block itself.
+	StateRoot,
+	/// Leave some step of the block out of the execution, as if it had no effect.
+	DropExtrinsic,
+}
+
+impl InvalidExecution {
+	/// All the values accepted by `FromStr`.
+	pub fn variants() -> [&'static str; 2] {
+		["state-root", "drop-extrinsic"]
+	}
+}
+
+impl FromStr for InvalidExecution {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"state-root" => Ok(InvalidExecution::StateRoot),
+			"drop-extrinsic" => Ok(InvalidExecution::DropExtrinsic),
+			other => Err(format!("Unknown invalid execution `{}`", other)),
+		}
+	}
+}
+
+impl fmt::Display for InvalidExecution {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			InvalidExecution::StateRoot => write!(f, "state-root"),
+			InvalidExecution::DropExtrinsic => write!(f, "drop-extrinsic"),
+		}
+	}
+}
+
+/// Which receipts an executor gets wrong, and how.
+#[derive(Debug, Clone)]
+pub struct InvalidExecutionConfig {
+	/// How the execution is wrong.
+	pub kind: InvalidExecution,
+	/// The fraction of the blocks whose receipt is wrong, between 0 and 1.
+	pub rate: f64,
+}
+
+impl InvalidExecutionConfig {
+	/// The index of the step the execution of the block `hash` goes wrong at, if it does. `steps`
+	/// is the number of steps of the block.
+	pub fn pick<Hash: Encode>(&self, hash: &Hash, steps: usize) -> Option<usize> {
+		if steps == 0 {
+			return None
+		}
+
+		let draw = blake2_256(&(b"invalid-execution", hash).encode());
+		let mut bytes = [0u8; 8];
+		bytes.copy_from_slice(&draw[..8]);
+		if u64::from_le_bytes(bytes) as f64 >= self.rate * u64::MAX as f64 {
+			return None
+		}
+
+		bytes.copy_from_slice(&draw[8..16]);
+		Some((u64::from_le_bytes(bytes) % steps as u64) as usize)
+	}
+}
+
+/// Corrupt the state roots `receipt` claims from the one after the step at `index` on, the
+/// state root of the block included.
+pub fn corrupt_state_roots<Hashing: HashT, Number>(
+	receipt: &mut ExecutionReceipt<Number, Hashing::Output>,
+	index: usize,
+) {
+	// `trace[0]` is the parent execution root, `trace[i + 1]` the one after step i, the last one
+	// being the state root.
+	for root in receipt.trace.iter_mut().skip(index + 1) {
+		*root = Hashing::hash(root.as_ref());
+	}
+	receipt.state_root = Hashing::hash(receipt.state_root.as_ref());
+}
@@ -0,0 +1,30 @@
+//! Access to the executor key of the node.
+
+use std::convert::TryInto;
+use sp_core::{H256, crypto::Public};
+use sp_executor::{ExecutorId, ExecutorSignature, KEY_TYPE};
+use sp_keystore::{SyncCryptoStore, SyncCryptoStorePtr};
+
+/// The executor key of this node, if any.
+pub fn executor_key(keystore: &SyncCryptoStorePtr) -> Option<ExecutorId> {
+	SyncCryptoStore::sr25519_public_keys(&**keystore, KEY_TYPE)
+		.into_iter()
+		.next()
+		.map(Into::into)
+}
+
+/// Sign `hash` with the key of `signer`.
+pub fn sign(
+	keystore: &SyncCryptoStorePtr,
+	signer: &ExecutorId,
+	hash: &H256,
+) -> Result<ExecutorSignature, String> {
+	let signature = SyncCryptoStore::sign_with(
+		&**keystore,
+		KEY_TYPE,
+		&signer.to_public_crypto_pair(),
+		hash.as_ref(),
+	).map_err(|e| format!("Failed to sign: {}", e))?;
+
+	signature.try_into().map_err(|_| "Keystore returned an invalid signature".to_string())
+}
@@ -0,0 +1,115 @@
+//! Prometheus metrics of the decoupled execution pipeline.
+//!
+//! Blocks go through three heads: they are ordered by consensus and finalized, then executed, and
+//! eventually execution-confirmed. The metrics report the height of each head and the number of
+//! blocks between them.
+
+use std::sync::Arc;
+use futures::{StreamExt, stream};
+use prometheus_endpoint::{register, Gauge, GaugeVec, Opts, PrometheusError, Registry, U64};
+use sc_client_api::{AuxStore, BlockchainEvents};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_executor::ExecutorApi;
+use sp_runtime::traits::{Block as BlockT, NumberFor, UniqueSaturatedInto};
+use super::{ExecutionRole, aux_schema, confirmation};
+
+/// Heights of the execution heads and the lag between them.
+#[derive(Clone)]
+pub struct ExecutionMetrics {
+	head: GaugeVec<U64>,
+	execution_lag: Gauge<U64>,
+	confirmation_lag: Gauge<U64>,
+}
+
+impl ExecutionMetrics {
+	/// Register the metrics to `registry`.
+	pub fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(ExecutionMetrics {
+			head: register(
+				GaugeVec::new(
+					Opts::new(
+						"execution_head",
+						"Block height of the ordered (finalized), executed and confirmed heads",
+					),
+					&["status"],
+				)?,
+				registry,
+			)?,
+			execution_lag: register(
+				Gauge::new("execution_lag", "Number of finalized blocks not executed yet")?,
+				registry,
+			)?,
+			confirmation_lag: register(
+				Gauge::new("confirmation_lag", "Number of executed blocks not confirmed yet")?,
+				registry,
+			)?,
+		})
+	}
+
+	fn report(&self, ordered: u64, executed: Option<u64>, confirmed: u64) {
+		self.head.with_label_values(&["ordered"]).set(ordered);
+		self.head.with_label_values(&["confirmed"]).set(confirmed);
+		if let Some(executed) = executed {
+			self.head.with_label_values(&["executed"]).set(executed);
+			self.execution_lag.set(ordered.saturating_sub(executed));
+			self.confirmation_lag.set(executed.saturating_sub(confirmed));
+		}
+	}
+}
+
+/// The executed head as seen by this node: the one of its own executor on executors, the highest
+/// block with a receipt on chain otherwise. `None` when unknown, e.g. on nodes without the state
+/// of the finalized blocks.
+fn executed_head<Block, Client>(
+	client: &Client,
+	execution_role: ExecutionRole,
+	is_authority: bool,
+) -> Option<NumberFor<Block>> where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + AuxStore + ProvideRuntimeApi<Block>,
+	Client::Api: ExecutorApi<Block>,
+{
+	if execution_role.is_executor() {
+		return aux_schema::load_executed_head::<_, Block::Hash, NumberFor<Block>>(client)
+			.ok()
+			.flatten()
+			.map(|(_, number)| number)
+	}
+
+	let at = confirmation::settled_state_at::<Block, _>(client, execution_role, is_authority).ok().flatten()?;
+	client.runtime_api().executed_head(&at).ok()
+}
+
+/// Report the execution heads of a node with the given role to `metrics` as blocks are imported
+/// and finalized.
+pub async fn start_metrics_reporter<Block, Client>(
+	client: Arc<Client>,
+	metrics: ExecutionMetrics,
+	execution_role: ExecutionRole,
+	is_authority: bool,
+) where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + BlockchainEvents<Block> + AuxStore + ProvideRuntimeApi<Block>,
+	Client::Api: ExecutorApi<Block>,
+{
+	let mut events = stream::select(
+		client.import_notification_stream().map(|_| ()),
+		client.finality_notification_stream().map(|_| ()),
+	);
+
+	while events.next().await.is_some() {
+		let ordered = client.info().finalized_number;
+		let executed = executed_head::<Block, _>(&*client, execution_role, is_authority);
+		let confirmed = match confirmation::confirmed_head::<Block, _>(&*client) {
+			Ok((_, confirmed)) => confirmed,
+			Err(_) => continue,
+		};
+
+		metrics.report(
+			ordered.unique_saturated_into(),
+			executed.map(UniqueSaturatedInto::unique_saturated_into),
+			confirmed.unique_saturated_into(),
+		);
+	}
+}
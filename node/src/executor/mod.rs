@@ -0,0 +1,548 @@
+//! Executor side of the decoupled execution experiment.
+//!
+//! In the coupled setup every full node runs the runtime as part of block import. With decoupled
+//! execution consensus nodes only order blocks, and a separate set of executor nodes follows
+//! GRANDPA finality and runs the runtime over every finalized block.
+//!
+//! The user transactions of decoupled blocks come in bundles, which consensus only records.
+//! Executors apply them to an [execution state](execution_state) of their own, which is what
+//! their receipts are about.
+
+use std::{collections::HashMap, fmt, marker::PhantomData, str::FromStr, sync::Arc};
+use futures::{channel::mpsc, StreamExt};
+use sc_client_api::{
+	AuxStore, BlockBackend, BlockchainEvents,
+	backend::{self, Backend as BackendT, StateBackendFor, TransactionFor},
+};
+use sp_api::{ApiExt, Core, ProvideRuntimeApi, StorageChanges};
+use sp_block_builder::BlockBuilder;
+use sp_blockchain::{Error as ClientError, HeaderBackend, Result as ClientResult};
+use sp_consensus::{
+	BlockImport, BlockImportParams, BlockOrigin, Error as ConsensusError, ForkChoiceStrategy,
+};
+use sp_core::traits::{CodeExecutor, SpawnNamed};
+use sp_executor::{ExecutionReceipt, ExecutorApi};
+use sp_keystore::SyncCryptoStorePtr;
+use sp_runtime::{
+	generic::BlockId,
+	generic::DigestItem,
+	traits::{Block as BlockT, HashFor, Header as HeaderT, NumberFor},
+};
+use sp_trie::PrefixedMemoryDB;
+use aux_schema::StateRootStatus;
+
+mod availability;
+pub mod aux_schema;
+mod backpressure;
+mod confirmation;
+mod execution_state;
+mod fraud_proof;
+mod bundles;
+mod gossip;
+mod import;
+mod injection;
+mod keys;
+mod metrics;
+mod pool;
+mod receipts;
+pub mod rpc;
+mod state_sync;
+mod stateless;
+mod watchdog;
+
+pub use availability::{
+	ExtractBundleFn, availability_protocol_config, start_availability_sampler,
+	start_chunk_request_handler,
+};
+pub use backpressure::{BackpressureConfig, ExecutionBackoff};
+pub use fraud_proof::{ExecutorExtensionsFactory, FraudProofGenerator, FraudProofVerifier};
+pub use bundles::{OrderingPool, start_bundle_producer};
+pub use confirmation::start_confirmation_tracker;
+pub use execution_state::StepExecutor;
+pub use gossip::{ExecutorGossip, GossipMessage, executor_peers_set_config};
+pub use import::OrderingBlockImport;
+pub use injection::{InvalidExecution, InvalidExecutionConfig};
+pub use metrics::{ExecutionMetrics, start_metrics_reporter};
+pub use pool::{ExecutionChainApi, LocalPool};
+pub use state_sync::{
+	ExtractFraudProofFn, ExtractReceiptFn, start_state_request_handler, state_request_protocol_config,
+	sync_state,
+};
+pub use stateless::{ExecutionProver, ProveExecution, StatelessBlockExecutor};
+pub use watchdog::start_watchdog;
+
+/// Log target for the executor.
+pub const LOG_TARGET: &str = "executor";
+
+/// The part of block processing a node takes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionRole {
+	/// Order and execute every block on import, like a regular Substrate node.
+	Coupled,
+	/// Author, import and finalize blocks. Authorities still execute on import since they need
+	/// the parent state to author on top of it, other nodes don't. Either way, the transactions
+	/// of bundles are left to executors.
+	Consensus,
+	/// Follow the finalized chain and execute every block, the transactions of its bundles
+	/// included.
+	Executor,
+}
+
+impl ExecutionRole {
+	/// All the values accepted by `FromStr`.
+	pub fn variants() -> [&'static str; 3] {
+		["coupled", "consensus", "executor"]
+	}
+
+	/// Whether the node takes part in block authoring and finality voting.
+	pub fn is_consensus(&self) -> bool {
+		matches!(self, ExecutionRole::Coupled | ExecutionRole::Consensus)
+	}
+
+	/// Whether the node runs the executor over finalized blocks.
+	pub fn is_executor(&self) -> bool {
+		matches!(self, ExecutionRole::Executor)
+	}
+
+	/// Whether blocks are imported without being executed.
+	pub fn defers_execution(&self, is_authority: bool) -> bool {
+		match self {
+			ExecutionRole::Coupled => false,
+			ExecutionRole::Consensus => !is_authority,
+			ExecutionRole::Executor => true,
+		}
+	}
+}
+
+impl Default for ExecutionRole {
+	fn default() -> Self {
+		ExecutionRole::Coupled
+	}
+}
+
+impl FromStr for ExecutionRole {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"coupled" => Ok(ExecutionRole::Coupled),
+			"consensus" => Ok(ExecutionRole::Consensus),
+			"executor" => Ok(ExecutionRole::Executor),
+			other => Err(format!("Unknown execution role `{}`", other)),
+		}
+	}
+}
+
+impl fmt::Display for ExecutionRole {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ExecutionRole::Coupled => write!(f, "coupled"),
+			ExecutionRole::Consensus => write!(f, "consensus"),
+			ExecutionRole::Executor => write!(f, "executor"),
+		}
+	}
+}
+
+/// Execution related configuration of a node.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionConfig {
+	/// The part of block processing this node takes on.
+	pub role: ExecutionRole,
+	/// How `consensus` authorities slow authoring down when executors fall behind, if at all.
+	pub backpressure: Option<BackpressureConfig>,
+	/// Whether executors sync the state at the latest execution-confirmed block from other
+	/// executors before executing, rather than executing from where they stand.
+	pub state_sync: bool,
+	/// Whether executors publish invalid receipts on purpose, to test fraud proofs.
+	pub invalid_execution: Option<InvalidExecutionConfig>,
+	/// Number of chunks of the bundles of each imported block sampled to check that they are
+	/// available, none when zero.
+	pub availability_samples: usize,
+}
+
+/// The header of a block as the runtime built it, i.e. without the seal its author added
+/// afterwards. That is the header blocks are executed with.
+pub(crate) fn pre_seal_header<Block: BlockT>(header: &Block::Header) -> Block::Header {
+	let mut header = header.clone();
+	if let Some(DigestItem::Seal(..)) = header.digest().logs().last() {
+		header.digest_mut().pop();
+	}
+	header
+}
+
+/// A block executed on top of its parent states.
+pub struct ExecutedBlock<Block: BlockT, StateBackend: sp_api::StateBackend<sp_api::HashFor<Block>>> {
+	/// Header of the block, as imported.
+	pub header: Block::Header,
+	/// Body of the block.
+	pub extrinsics: Vec<Block::Extrinsic>,
+	/// The root of the execution state the execution produced.
+	pub state_root: Block::Hash,
+	/// The roots of the execution state before the block and after each of its steps.
+	pub trace: Vec<Block::Hash>,
+	/// The changes of the consensus state, whose root is the one the header claims, along with the
+	/// trie nodes of the execution state.
+	pub storage_changes: StorageChanges<StateBackend, Block>,
+}
+
+impl<Block, StateBackend> ExecutedBlock<Block, StateBackend> where
+	Block: BlockT,
+	StateBackend: sp_api::StateBackend<sp_api::HashFor<Block>>,
+{
+	/// The receipt claiming the outcome of this execution.
+	pub fn receipt(&self) -> ExecutionReceipt<NumberFor<Block>, Block::Hash> {
+		ExecutionReceipt {
+			primary_number: *self.header.number(),
+			primary_hash: self.header.hash(),
+			state_root: self.state_root,
+			trace: self.trace.clone(),
+		}
+	}
+}
+
+/// Runs the runtime over blocks of the local chain and commits the resulting states.
+pub struct BlockExecutor<Block: BlockT, Client, Backend, Exec, Spawn> {
+	client: Arc<Client>,
+	backend: Arc<Backend>,
+	steps: StepExecutor<Block, Exec, Spawn>,
+	_phantom: PhantomData<Block>,
+}
+
+impl<Block, Client, Backend, Exec, Spawn> BlockExecutor<Block, Client, Backend, Exec, Spawn> where
+	Block: BlockT,
+	Backend: BackendT<Block> + 'static,
+	StateBackendFor<Backend, Block>: sp_api::StateBackend<HashFor<Block>, Transaction = PrefixedMemoryDB<HashFor<Block>>>,
+	Exec: CodeExecutor + Clone + 'static,
+	Spawn: SpawnNamed + Clone + Send + 'static,
+	Client: HeaderBackend<Block> + BlockBackend<Block> + AuxStore + ProvideRuntimeApi<Block>,
+	Client::Api: Core<Block> + BlockBuilder<Block> + ExecutorApi<Block>
+		+ ApiExt<Block, StateBackend = StateBackendFor<Backend, Block>>,
+	for<'a> &'a Client: BlockImport<
+		Block,
+		Transaction = TransactionFor<Backend, Block>,
+		Error = ConsensusError,
+	>,
+{
+	/// Create a new executor on top of the given client, running the steps of blocks with
+	/// `steps`.
+	pub fn new(
+		client: Arc<Client>,
+		backend: Arc<Backend>,
+		steps: StepExecutor<Block, Exec, Spawn>,
+	) -> Self {
+		BlockExecutor { client, backend, steps, _phantom: PhantomData }
+	}
+
+	fn block(&self, hash: Block::Hash) -> ClientResult<(Block::Header, Vec<Block::Extrinsic>)> {
+		let id = BlockId::Hash(hash);
+		let header = self.client.header(id)?
+			.ok_or_else(|| ClientError::UnknownBlock(format!("{}", hash)))?;
+		let extrinsics = self.client.block_body(&id)?
+			.ok_or_else(|| ClientError::UnknownBlock(format!("Missing body of {}", hash)))?;
+		Ok((header, extrinsics))
+	}
+
+	/// Execute the block with the given hash on top of its parent states.
+	pub fn execute(
+		&self,
+		hash: Block::Hash,
+	) -> ClientResult<ExecutedBlock<Block, StateBackendFor<Backend, Block>>> {
+		let (header, extrinsics) = self.block(hash)?;
+		let mut storage_changes = self.execute_consensus(&header, &extrinsics)?;
+		let (state_root, trace, transaction) = self.execute_steps(&header, &extrinsics, None)?;
+		storage_changes.transaction.consolidate(transaction);
+
+		Ok(ExecutedBlock { header, extrinsics, state_root, trace, storage_changes })
+	}
+
+	/// Build the consensus state of the block on top of the one of its parent.
+	fn execute_consensus(
+		&self,
+		header: &Block::Header,
+		extrinsics: &[Block::Extrinsic],
+	) -> ClientResult<StorageChanges<StateBackendFor<Backend, Block>, Block>> {
+		let hash = header.hash();
+
+		// `Core_execute_block` asserts that the computed state root matches the one in the header,
+		// the block is re-built extrinsic by extrinsic to read back our own result instead.
+		let parent_hash = *header.parent_hash();
+		let parent = BlockId::Hash(parent_hash);
+		let runtime_api = self.client.runtime_api();
+		runtime_api.initialize_block(&parent, &pre_seal_header::<Block>(header))?;
+		for (index, extrinsic) in extrinsics.iter().enumerate() {
+			runtime_api.apply_extrinsic(&parent, extrinsic.clone())?.map_err(|e| ClientError::Msg(
+				format!("Extrinsic #{} of {} could not be applied: {:?}", index, hash, e)
+			))?;
+		}
+		runtime_api.finalize_block(&parent)?;
+
+		let state = self.backend.state_at(parent)?;
+		let changes_trie_state = backend::changes_tries_state_at_block(
+			&parent,
+			self.backend.changes_trie_storage(),
+		)?;
+		runtime_api
+			.into_storage_changes(&state, changes_trie_state.as_ref(), parent_hash)
+			.map_err(ClientError::StorageChanges)
+	}
+
+	/// Run the steps of the block on top of the execution state of its parent, leaving out the
+	/// step at index `skipped`, if any. Returns the root of the resulting execution state, the
+	/// trace of the execution and the trie nodes of the state.
+	fn execute_steps(
+		&self,
+		header: &Block::Header,
+		extrinsics: &[Block::Extrinsic],
+		skipped: Option<usize>,
+	) -> ClientResult<(Block::Hash, Vec<Block::Hash>, PrefixedMemoryDB<HashFor<Block>>)> {
+		let parent_hash = *header.parent_hash();
+		let parent_root = execution_state::execution_root::<Block, _>(&*self.client, parent_hash)?
+			.ok_or_else(|| ClientError::Msg(format!("No execution state at {}", parent_hash)))?;
+		let state = self.backend.state_at(BlockId::Hash(parent_hash))?;
+		let parent_state = execution_state::execution_state::<Block, _>(&state, parent_root)?;
+
+		let (changes, trace) = self.steps.execute(&parent_state, header, extrinsics, skipped)
+			.map_err(|e| ClientError::Msg(format!("Failed to execute the steps of {}: {}", header.hash(), e)))?;
+		Ok((changes.transaction_storage_root, trace, changes.transaction))
+	}
+
+	/// The receipt to publish in place of our own `receipt` when injecting invalid executions
+	/// with `config`, which is `receipt` itself for the blocks left alone.
+	fn invalid_receipt(
+		&self,
+		config: &InvalidExecutionConfig,
+		receipt: ExecutionReceipt<NumberFor<Block>, Block::Hash>,
+	) -> ExecutionReceipt<NumberFor<Block>, Block::Hash> {
+		let hash = receipt.primary_hash;
+		// blocks are never executed without their initialization or finalization.
+		let steps = receipt.trace.len().saturating_sub(1);
+		let index = match config.kind {
+			InvalidExecution::StateRoot => config.pick(&hash, steps),
+			InvalidExecution::DropExtrinsic => config.pick(&hash, steps.saturating_sub(2)).map(|index| index + 1),
+		};
+		let index = match index {
+			Some(index) => index,
+			None => return receipt,
+		};
+
+		let invalid = match config.kind {
+			InvalidExecution::StateRoot => {
+				let mut invalid = receipt.clone();
+				injection::corrupt_state_roots::<HashFor<Block>, _>(&mut invalid, index);
+				invalid
+			},
+			InvalidExecution::DropExtrinsic => {
+				let executed = self.block(hash)
+					.and_then(|(header, extrinsics)| self.execute_steps(&header, &extrinsics, Some(index)));
+				match executed {
+					Ok((state_root, trace, _)) => ExecutionReceipt { state_root, trace, ..receipt.clone() },
+					// e.g. blocks can't be finalized without their inherents.
+					Err(e) => {
+						log::debug!(
+							target: LOG_TARGET,
+							"Can't execute {} without step #{}: {:?}",
+							hash,
+							index,
+							e,
+						);
+						return receipt
+					},
+				}
+			},
+		};
+
+		log::warn!(
+			target: LOG_TARGET,
+			"Publishing an invalid receipt for #{} ({}): {} from step #{}",
+			receipt.primary_number,
+			hash,
+			config.kind,
+			index,
+		);
+		invalid
+	}
+
+	/// Record the outcome of an execution.
+	///
+	/// When the consensus state agrees with the header both states are imported into the client,
+	/// and the block becomes the new executed head. Otherwise the block is marked as disputed and
+	/// nothing is committed, since no state can be built on top of it.
+	pub async fn commit(
+		&self,
+		executed: ExecutedBlock<Block, StateBackendFor<Backend, Block>>,
+	) -> ClientResult<StateRootStatus<Block::Hash>> {
+		let hash = executed.header.hash();
+		let number = *executed.header.number();
+
+		let consensus_root = executed.storage_changes.transaction_storage_root;
+		if &consensus_root != executed.header.state_root() {
+			let status = StateRootStatus::Disputed(consensus_root);
+			let (key, value) = aux_schema::state_root_status_entry(&hash, &status);
+			self.client.insert_aux(&[(&key[..], &value[..])], &[])?;
+			return Ok(status)
+		}
+
+		let status = StateRootStatus::Confirmed;
+		let mut import = BlockImportParams::new(BlockOrigin::Own, executed.header);
+		import.body = Some(executed.extrinsics);
+		import.storage_changes = Some(executed.storage_changes);
+		import.import_existing = true;
+		// the executed head is the best block of executors.
+		import.fork_choice = Some(ForkChoiceStrategy::Custom(true));
+		import.auxiliary = vec![
+			aux_schema::state_root_status_entry(&hash, &status),
+			aux_schema::execution_root_entry(&hash, &executed.state_root),
+			aux_schema::executed_head_entry(&hash, &number),
+		].into_iter().map(|(key, value)| (key, Some(value))).collect();
+
+		let mut client = &*self.client;
+		client.import_block(import, HashMap::new()).await
+			.map_err(|e| ClientError::Msg(format!("Failed to import executed block {}: {:?}", hash, e)))?;
+
+		Ok(status)
+	}
+}
+
+/// Start the executor. It follows finality notifications and executes every finalized block, in
+/// order, exactly once, with `steps` running the steps of blocks on the execution state. It
+/// gossips and submits on chain a receipt for each block it could confirm. The receipts are also
+/// sent to `own_receipts`, for the [watchdog](start_watchdog) to compare with the others.
+///
+/// With `invalid_execution`, the receipts gossiped and submitted on chain are wrong for some of
+/// the blocks, while the ones sent to `own_receipts` and the imported state stay correct.
+pub async fn start_executor<Block, Client, Backend, Exec, Spawn>(
+	client: Arc<Client>,
+	backend: Arc<Backend>,
+	steps: StepExecutor<Block, Exec, Spawn>,
+	keystore: SyncCryptoStorePtr,
+	gossip: ExecutorGossip<Block>,
+	own_receipts: mpsc::UnboundedSender<ExecutionReceipt<NumberFor<Block>, Block::Hash>>,
+	invalid_execution: Option<InvalidExecutionConfig>,
+) where
+	Block: BlockT,
+	Backend: BackendT<Block> + 'static,
+	StateBackendFor<Backend, Block>: sp_api::StateBackend<HashFor<Block>, Transaction = PrefixedMemoryDB<HashFor<Block>>>,
+	Exec: CodeExecutor + Clone + 'static,
+	Spawn: SpawnNamed + Clone + Send + 'static,
+	Client: HeaderBackend<Block> + BlockBackend<Block> + BlockchainEvents<Block> + AuxStore,
+	Client: ProvideRuntimeApi<Block> + Send + Sync + 'static,
+	Client::Api: Core<Block> + BlockBuilder<Block> + ExecutorApi<Block>
+		+ ApiExt<Block, StateBackend = StateBackendFor<Backend, Block>>,
+	for<'a> &'a Client: BlockImport<
+		Block,
+		Transaction = TransactionFor<Backend, Block>,
+		Error = ConsensusError,
+	>,
+{
+	let executor = BlockExecutor::new(client.clone(), backend, steps);
+	let mut finality_notifications = client.finality_notification_stream();
+
+	let info = client.info();
+	let (_, mut executed_number) = match aux_schema::load_executed_head::<_, Block::Hash, NumberFor<Block>>(&*client) {
+		Ok(Some(head)) => head,
+		// nothing executed yet, the execution state starts out as the genesis state.
+		Ok(None) => (info.genesis_hash, 0u32.into()),
+		Err(e) => {
+			log::error!(target: LOG_TARGET, "Failed to load the executed head: {:?}", e);
+			return
+		},
+	};
+
+	let signer = keys::executor_key(&keystore);
+	if signer.is_none() {
+		log::warn!(target: LOG_TARGET, "No executor key in the keystore, receipts won't be gossiped");
+	}
+
+	log::info!(target: LOG_TARGET, "👷 Starting executor at #{}", executed_number);
+
+	while let Some(notification) = finality_notifications.next().await {
+		if *notification.header.number() <= executed_number {
+			continue
+		}
+
+		// GRANDPA may finalize several blocks at once, collect them back to the last one we
+		// executed.
+		let mut to_execute = Vec::new();
+		let mut header = notification.header;
+		while *header.number() > executed_number {
+			let parent_hash = *header.parent_hash();
+			to_execute.push(header.hash());
+			header = match client.header(BlockId::Hash(parent_hash)) {
+				Ok(Some(header)) => header,
+				Ok(None) | Err(_) => {
+					log::error!(target: LOG_TARGET, "Header of finalized block {} is missing", parent_hash);
+					break
+				},
+			};
+		}
+
+		for hash in to_execute.into_iter().rev() {
+			// a disputed block has no state to build on, wait for it to be reverted.
+			if let Ok(Some(StateRootStatus::Disputed(_))) =
+				aux_schema::load_state_root_status(&*client, &hash)
+			{
+				break
+			}
+
+			let executed = match executor.execute(hash) {
+				Ok(executed) => executed,
+				Err(e) => {
+					log::error!(target: LOG_TARGET, "Failed to execute block {}: {:?}", hash, e);
+					break
+				},
+			};
+			let number = *executed.header.number();
+			let state_root = executed.state_root;
+			let receipt = executed.receipt();
+
+			match executor.commit(executed).await {
+				Ok(StateRootStatus::Confirmed) => {
+					executed_number = number;
+					log::debug!(
+						target: LOG_TARGET,
+						"Executed #{} ({}), execution state root: {}, trace root: {}",
+						number,
+						hash,
+						state_root,
+						receipt.trace_root(),
+					);
+					gossip.note_executed(number);
+					let _ = own_receipts.unbounded_send(receipt.clone());
+					let receipt = match &invalid_execution {
+						Some(config) => executor.invalid_receipt(config, receipt),
+						None => receipt,
+					};
+					if let Some(signer) = &signer {
+						match receipts::sign_receipt(&keystore, signer, receipt) {
+							Ok(receipt) => {
+								// on chain, receipts tell consensus nodes how far the chain is executed.
+								let at = BlockId::Hash(hash);
+								match client.runtime_api().submit_execution_receipt_unsigned(&at, receipt.clone()) {
+									Ok(Some(())) => {},
+									Ok(None) => log::debug!(target: LOG_TARGET, "Receipt of #{} rejected by the pool", number),
+									Err(e) => log::error!(target: LOG_TARGET, "Failed to submit receipt of #{}: {:?}", number, e),
+								}
+								gossip.gossip(GossipMessage::Receipt(receipt));
+							},
+							Err(e) => log::error!(target: LOG_TARGET, "Receipt of #{} not gossiped: {}", number, e),
+						}
+					}
+				},
+				Ok(status) => {
+					log::warn!(
+						target: LOG_TARGET,
+						"State root mismatch for #{} ({}): {:?}",
+						number,
+						hash,
+						status,
+					);
+					break
+				},
+				Err(e) => {
+					log::error!(target: LOG_TARGET, "Failed to commit block {}: {:?}", hash, e);
+					break
+				},
+			}
+		}
+	}
+}
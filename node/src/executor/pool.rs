@@ -0,0 +1,191 @@
+//! Validation of user transactions against the execution state.
+//!
+//! The consensus state doesn't go through the transactions of bundles, so the nonce and balance
+//! of an account there say nothing about its next user transactions. The pool of executors
+//! validates user transactions against their execution state instead, at the block it is asked
+//! about or, if they didn't execute it yet, at their executed head. Other transactions, and all
+//! the transactions of nodes without an execution state, are validated the usual way.
+
+use std::{pin::Pin, sync::Arc};
+use codec::{Decode, Encode};
+use futures::{Future, FutureExt, channel::oneshot};
+use sc_client_api::{AuxStore, BlockBackend, backend::Backend as BackendT};
+use sc_transaction_graph::{BlockHash, ChainApi, ExtrinsicFor, ExtrinsicHash};
+use sc_transaction_pool::{FullChainApi, error::Error};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::traits::{CodeExecutor, SpawnNamed};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, BlockIdTo, NumberFor},
+	transaction_validity::{TransactionSource, TransactionValidity},
+};
+use sp_state_machine::{OverlayedChanges, backend::BackendRuntimeCode};
+use sp_transaction_pool::{
+	LocalTransactionFor, LocalTransactionPool, TransactionPool, TxHash,
+	runtime_api::TaggedTransactionQueue,
+};
+use super::{aux_schema, execution_state::{self, StepExecutor}};
+
+/// Runtime method validating a transaction.
+const VALIDATE_TRANSACTION: &str = "TaggedTransactionQueue_validate_transaction";
+
+/// Validates user transactions against the execution state.
+struct ExecutionValidator<Block: BlockT, Client, Backend, Exec, Spawn> {
+	client: Arc<Client>,
+	backend: Arc<Backend>,
+	steps: StepExecutor<Block, Exec, Spawn>,
+	is_bundled: fn(&Block::Extrinsic) -> bool,
+}
+
+impl<Block, Client, Backend, Exec, Spawn> ExecutionValidator<Block, Client, Backend, Exec, Spawn> where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + AuxStore,
+	Backend: BackendT<Block>,
+	Exec: CodeExecutor + Clone + 'static,
+	Spawn: SpawnNamed + Clone + Send + 'static,
+{
+	fn validate(
+		&self,
+		at: &BlockId<Block>,
+		source: TransactionSource,
+		uxt: Block::Extrinsic,
+	) -> Result<TransactionValidity, Error> {
+		let hash = self.client.expect_block_hash_from_id(at)?;
+		let (hash, root) = match execution_state::execution_root(&*self.client, hash)? {
+			Some(root) => (hash, root),
+			None => {
+				let head = aux_schema::load_executed_head::<_, _, NumberFor<Block>>(&*self.client)?
+					.map(|(head, _)| head)
+					.unwrap_or(self.client.info().genesis_hash);
+				let root = execution_state::execution_root(&*self.client, head)?
+					.ok_or_else(|| Error::RuntimeApi(format!("No execution state at the executed head {}", head)))?;
+				(head, root)
+			},
+		};
+
+		let state = self.backend.state_at(BlockId::Hash(hash))?;
+		let state = execution_state::execution_state::<Block, _>(&state, root)?;
+		let backend_runtime_code = BackendRuntimeCode::new(&state);
+		let runtime_code = backend_runtime_code.runtime_code().map_err(|e| Error::RuntimeApi(e.into()))?;
+		let result = self.steps.call(
+			&state,
+			&mut OverlayedChanges::default(),
+			&runtime_code,
+			VALIDATE_TRANSACTION,
+			&(source, uxt, hash).encode(),
+		).map_err(Error::RuntimeApi)?;
+		TransactionValidity::decode(&mut &result[..])
+			.map_err(|e| Error::RuntimeApi(format!("Failed to decode the transaction validity: {}", e)))
+	}
+}
+
+/// The chain API of the transaction pool, validating user transactions against the execution
+/// state on executors.
+pub struct ExecutionChainApi<Block: BlockT, Client, Backend, Exec, Spawn> {
+	inner: FullChainApi<Client, Block>,
+	execution: Option<Arc<ExecutionValidator<Block, Client, Backend, Exec, Spawn>>>,
+	spawn_handle: Spawn,
+}
+
+impl<Block, Client, Backend, Exec, Spawn> ExecutionChainApi<Block, Client, Backend, Exec, Spawn> where
+	Block: BlockT,
+{
+	/// Validate transactions with `inner`, except the ones for which `is_bundled` holds, which are
+	/// validated against the execution state kept by `backend` if `steps` are given. They are
+	/// only given on executors.
+	pub fn new(
+		inner: FullChainApi<Client, Block>,
+		client: Arc<Client>,
+		backend: Arc<Backend>,
+		steps: Option<StepExecutor<Block, Exec, Spawn>>,
+		is_bundled: fn(&Block::Extrinsic) -> bool,
+		spawn_handle: Spawn,
+	) -> Self {
+		let execution = steps.map(|steps| Arc::new(ExecutionValidator { client, backend, steps, is_bundled }));
+		ExecutionChainApi { inner, execution, spawn_handle }
+	}
+}
+
+impl<Block, Client, Backend, Exec, Spawn> ChainApi for ExecutionChainApi<Block, Client, Backend, Exec, Spawn> where
+	Block: BlockT,
+	Client: ProvideRuntimeApi<Block> + BlockBackend<Block> + BlockIdTo<Block>,
+	Client: HeaderBackend<Block> + AuxStore + Send + Sync + 'static,
+	Client::Api: TaggedTransactionQueue<Block>,
+	Backend: BackendT<Block> + 'static,
+	Exec: CodeExecutor + Clone + 'static,
+	Spawn: SpawnNamed + Clone + Send + Sync + 'static,
+{
+	type Block = Block;
+	type Error = Error;
+	type ValidationFuture = Pin<Box<dyn Future<Output = Result<TransactionValidity, Error>> + Send>>;
+	type BodyFuture = <FullChainApi<Client, Block> as ChainApi>::BodyFuture;
+
+	fn validate_transaction(
+		&self,
+		at: &BlockId<Self::Block>,
+		source: TransactionSource,
+		uxt: ExtrinsicFor<Self>,
+	) -> Self::ValidationFuture {
+		let validator = match &self.execution {
+			Some(validator) if (validator.is_bundled)(&uxt) => validator.clone(),
+			_ => return Box::pin(self.inner.validate_transaction(at, source, uxt)),
+		};
+
+		let (sender, receiver) = oneshot::channel();
+		let at = at.clone();
+		self.spawn_handle.spawn_blocking("executor-validate-transaction", Box::pin(async move {
+			let _ = sender.send(validator.validate(&at, source, uxt));
+		}));
+		Box::pin(receiver.map(|result| {
+			result.unwrap_or_else(|_| Err(Error::RuntimeApi("Validation task dropped".into())))
+		}))
+	}
+
+	fn block_id_to_number(
+		&self,
+		at: &BlockId<Self::Block>,
+	) -> Result<Option<sc_transaction_graph::NumberFor<Self>>, Self::Error> {
+		self.inner.block_id_to_number(at)
+	}
+
+	fn block_id_to_hash(&self, at: &BlockId<Self::Block>) -> Result<Option<BlockHash<Self>>, Self::Error> {
+		self.inner.block_id_to_hash(at)
+	}
+
+	fn hash_and_length(&self, uxt: &ExtrinsicFor<Self>) -> (ExtrinsicHash<Self>, usize) {
+		self.inner.hash_and_length(uxt)
+	}
+
+	fn block_body(&self, at: &BlockId<Self::Block>) -> Self::BodyFuture {
+		self.inner.block_body(at)
+	}
+}
+
+/// Submits the transactions of the runtime, e.g. receipts and bundles, to a transaction pool.
+///
+/// Register it with the execution extensions of the client, pools of a chain API of their own
+/// don't submit local transactions themselves.
+pub struct LocalPool<Pool>(Arc<Pool>);
+
+impl<Pool> LocalPool<Pool> {
+	/// Submit to the given pool.
+	pub fn new(pool: Arc<Pool>) -> Self {
+		LocalPool(pool)
+	}
+}
+
+impl<Pool: TransactionPool> LocalTransactionPool for LocalPool<Pool> {
+	type Block = Pool::Block;
+	type Hash = TxHash<Pool>;
+	type Error = Pool::Error;
+
+	fn submit_local(
+		&self,
+		at: &BlockId<Self::Block>,
+		xt: LocalTransactionFor<Self>,
+	) -> Result<Self::Hash, Self::Error> {
+		// the runtime waits for the transaction to be validated, on the threads of the pool.
+		futures::executor::block_on(self.0.submit_one(at, TransactionSource::Local, xt))
+	}
+}
@@ -0,0 +1,19 @@
+//! Signing of execution receipts.
+
+use sp_executor::{ExecutionReceipt, ExecutorId, SignedExecutionReceipt};
+use sp_keystore::SyncCryptoStorePtr;
+use super::keys;
+
+/// Sign `execution_receipt` with the key of `signer`.
+pub fn sign_receipt<Number, Hash>(
+	keystore: &SyncCryptoStorePtr,
+	signer: &ExecutorId,
+	execution_receipt: ExecutionReceipt<Number, Hash>,
+) -> Result<SignedExecutionReceipt<Number, Hash>, String> where
+	Number: codec::Encode,
+	Hash: codec::Encode,
+{
+	let signature = keys::sign(keystore, signer, &execution_receipt.hash())?;
+
+	Ok(SignedExecutionReceipt { execution_receipt, signer: signer.clone(), signature })
+}
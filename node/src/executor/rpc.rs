@@ -0,0 +1,82 @@
+//! RPC methods of the decoupled execution experiment.
+
+use std::{marker::PhantomData, sync::Arc};
+use codec::Encode;
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result as RpcResult};
+use jsonrpc_derive::rpc;
+use sc_client_api::AuxStore;
+use sc_rpc_api::DenyUnsafe;
+use sp_blockchain::HeaderBackend;
+use sp_core::Bytes;
+use sp_runtime::traits::Block as BlockT;
+use super::{confirmation, stateless::ProveExecution};
+
+/// Execution RPC methods.
+#[rpc(server)]
+pub trait ExecutionApi<Hash> {
+	/// Hash of the last execution-confirmed block: finalized, and out of the challenge period
+	/// without its state root being disputed.
+	///
+	/// The counterpart of `chain_getFinalizedHead` for consumers that need settled state.
+	#[rpc(name = "chain_getConfirmedHead")]
+	fn confirmed_head(&self) -> RpcResult<Hash>;
+
+	/// The encoded compact proof of the part of the parent execution state the given block
+	/// touches, to be handed to the `StatelessExecutionApi`. Only available on executors, for
+	/// blocks whose parent execution state is kept locally.
+	#[rpc(name = "chain_getExecutionProof")]
+	fn execution_proof(&self, hash: Hash) -> RpcResult<Bytes>;
+}
+
+/// Implements the [`ExecutionApi`] on top of the client.
+pub struct Execution<Block: BlockT, Client> {
+	client: Arc<Client>,
+	prover: Option<Arc<dyn ProveExecution<Block::Hash>>>,
+	deny_unsafe: DenyUnsafe,
+	_phantom: PhantomData<Block>,
+}
+
+impl<Block: BlockT, Client> Execution<Block, Client> {
+	/// Create a new instance reading from the given client. Executions are proven with `prover`,
+	/// on executors.
+	pub fn new(
+		client: Arc<Client>,
+		prover: Option<Arc<dyn ProveExecution<Block::Hash>>>,
+		deny_unsafe: DenyUnsafe,
+	) -> Self {
+		Execution { client, prover, deny_unsafe, _phantom: PhantomData }
+	}
+}
+
+fn internal_error(message: &str, e: impl std::fmt::Debug) -> RpcError {
+	RpcError {
+		code: ErrorCode::InternalError,
+		message: message.into(),
+		data: Some(format!("{:?}", e).into()),
+	}
+}
+
+impl<Block, Client> ExecutionApi<Block::Hash> for Execution<Block, Client> where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + AuxStore + Send + Sync + 'static,
+{
+	fn confirmed_head(&self) -> RpcResult<Block::Hash> {
+		confirmation::confirmed_head::<Block, _>(&*self.client)
+			.map(|(hash, _)| hash)
+			.map_err(|e| internal_error("Unable to load the execution-confirmed head.", e))
+	}
+
+	fn execution_proof(&self, hash: Block::Hash) -> RpcResult<Bytes> {
+		// re-executes the block.
+		self.deny_unsafe.check_if_safe()?;
+
+		let prover = self.prover.as_ref().ok_or_else(|| RpcError {
+			code: ErrorCode::MethodNotFound,
+			message: "Only executors keep the execution state.".into(),
+			data: None,
+		})?;
+		prover.prove_execution(hash)
+			.map(|proof| proof.encode().into())
+			.map_err(|e| internal_error("Unable to prove the execution of the block.", e))
+	}
+}
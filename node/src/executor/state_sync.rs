@@ -0,0 +1,461 @@
+//! State sync of executors from execution-confirmed checkpoints.
+//!
+//! Executors import blocks without their states, which they only get by executing the chain. A
+//! new executor can instead download both states at the latest execution-confirmed block from the
+//! executors it is connected to, on the `/executor/state/1` request-response protocol. The
+//! execution state is only imported if it hashes to the state root the receipts of the block agree
+//! on, once they were included in the finalized chain for the challenge period without being
+//! challenged, and the consensus state if it hashes to the state root of the block header.
+//! Execution then goes on from there.
+//!
+//! Executors serve the states of any block they still have them for, so serving executors need to
+//! keep states older than the challenge period, e.g. with `--pruning archive`.
+
+use std::{collections::{BTreeMap, HashSet}, sync::Arc, time::Duration};
+use codec::{Decode, Encode};
+use futures::{channel::mpsc, StreamExt};
+use sc_client_api::{
+	AuxStore, BlockBackend, BlockchainEvents,
+	backend::{Backend as BackendT, BlockImportOperation, LockImportRun, NewBlockState},
+};
+use sc_network::{
+	IfDisconnected, NetworkService, PeerId,
+	config::{IncomingRequest, OutgoingResponse, RequestResponseConfig},
+};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::{Error as ClientError, HeaderBackend, Result as ClientResult};
+use sp_core::storage::{Storage, StorageMap, well_known_keys::CHILD_STORAGE_KEY_PREFIX};
+use sp_executor::{ExecutorApi, FraudProof, SignedExecutionReceipt};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, HashFor, Header as HeaderT, NumberFor, One, Saturating, Zero},
+};
+use sp_state_machine::Backend as StateBackend;
+use sp_trie::{Layout, PrefixedMemoryDB, TrieDBMut, TrieMut};
+use super::{
+	LOG_TARGET, ExecutorGossip, aux_schema::{self, StateRootStatus}, confirmation, execution_state,
+};
+
+/// Name of the request-response protocol executors serve their state on.
+pub const STATE_PROTOCOL_NAME: &str = "/executor/state/1";
+
+/// Size of the keys and values in a response past which the rest of the state is left for
+/// another request.
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// How many finalized blocks before the last ones still in their challenge period are searched
+/// for receipts.
+const RECEIPT_SCAN_DEPTH: u32 = 256;
+
+/// How many times syncing the state is attempted before executing from the executed head instead.
+/// Attempt `n` waits for `2^n` more blocks to be finalized.
+const MAX_SYNC_ATTEMPTS: u32 = 8;
+
+/// Finds the execution receipt an encoded extrinsic submits, see
+/// [`sp_executor::ExtractExecution`].
+pub type ExtractReceiptFn<Block> =
+	fn(&[u8]) -> Option<SignedExecutionReceipt<NumberFor<Block>, <Block as BlockT>::Hash>>;
+
+/// Finds the fraud proof an encoded extrinsic submits, see [`sp_executor::ExtractExecution`].
+pub type ExtractFraudProofFn<Block> =
+	fn(&[u8]) -> Option<FraudProof<NumberFor<Block>, <Block as BlockT>::Hash>>;
+
+/// A block whose receipts settled on a state root: its number, hash and state root.
+type SettledBlock<Block> = (NumberFor<Block>, <Block as BlockT>::Hash, <Block as BlockT>::Hash);
+
+/// Request for a chunk of one of the states at a block.
+#[derive(Debug, Encode, Decode)]
+struct StateRequest<Hash> {
+	/// The block to get the state at.
+	block: Hash,
+	/// Whether to get the execution state instead of the consensus state.
+	execution: bool,
+	/// Only return the keys after this one.
+	start: Option<Vec<u8>>,
+}
+
+/// A chunk of the state, in key order.
+#[derive(Debug, Encode, Decode)]
+struct StateResponse {
+	entries: Vec<(Vec<u8>, Vec<u8>)>,
+	/// Whether the entries go up to the end of the state.
+	complete: bool,
+}
+
+/// Returns the configuration value to put in
+/// [`sc_network::config::NetworkConfiguration::request_response_protocols`]. Incoming requests
+/// are sent to `inbound_queue`, to be answered by [`start_state_request_handler`].
+pub fn state_request_protocol_config(inbound_queue: mpsc::Sender<IncomingRequest>) -> RequestResponseConfig {
+	RequestResponseConfig {
+		name: STATE_PROTOCOL_NAME.into(),
+		max_request_size: 1024 * 1024,
+		// a chunk stops after the entry that makes it reach `CHUNK_SIZE`.
+		max_response_size: 16 * 1024 * 1024,
+		request_timeout: Duration::from_secs(30),
+		inbound_queue: Some(inbound_queue),
+	}
+}
+
+fn state_chunk<Block, Client, Backend>(
+	client: &Client,
+	backend: &Backend,
+	request: StateRequest<Block::Hash>,
+) -> Result<StateResponse, String> where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + AuxStore,
+	Backend: BackendT<Block>,
+{
+	let state = backend.state_at(BlockId::Hash(request.block)).map_err(|e| e.to_string())?;
+	let start = request.start.unwrap_or_default();
+	if !request.execution {
+		return chunk_of(&state, start)
+	}
+
+	let root = execution_state::execution_root(client, request.block)
+		.map_err(|e| e.to_string())?
+		.ok_or_else(|| format!("No execution state at {}", request.block))?;
+	let state = execution_state::execution_state::<Block, _>(&state, root).map_err(|e| e.to_string())?;
+	chunk_of(&state, start)
+}
+
+/// The chunk of `state` starting after `key`.
+fn chunk_of<H: hash_db::Hasher, S: StateBackend<H>>(
+	state: &S,
+	mut key: Vec<u8>,
+) -> Result<StateResponse, String> {
+	let (mut entries, mut size) = (Vec::new(), 0);
+	loop {
+		let next = match state.next_storage_key(&key).map_err(|e| e.to_string())? {
+			Some(next) => next,
+			None => return Ok(StateResponse { entries, complete: true }),
+		};
+		if size >= CHUNK_SIZE {
+			return Ok(StateResponse { entries, complete: false })
+		}
+
+		let value = state.storage(&next)
+			.map_err(|e| e.to_string())?
+			.ok_or_else(|| format!("Key {:?} vanished from the state", next))?;
+		size += next.len() + value.len();
+		entries.push((next.clone(), value));
+		key = next;
+	}
+}
+
+/// Answer the state requests of other executors, for the blocks we have the states of.
+pub async fn start_state_request_handler<Block, Client, Backend>(
+	client: Arc<Client>,
+	backend: Arc<Backend>,
+	mut requests: mpsc::Receiver<IncomingRequest>,
+) where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + AuxStore,
+	Backend: BackendT<Block>,
+{
+	while let Some(IncomingRequest { peer, payload, pending_response }) = requests.next().await {
+		let response = StateRequest::<Block::Hash>::decode(&mut &payload[..])
+			.map_err(|e| e.to_string())
+			.and_then(|request| state_chunk::<Block, _, _>(&*client, &*backend, request));
+
+		let result = match response {
+			Ok(response) => Ok(response.encode()),
+			Err(e) => {
+				log::debug!(target: LOG_TARGET, "Unable to answer the state request of {}: {}", peer, e);
+				Err(())
+			},
+		};
+		let _ = pending_response.send(OutgoingResponse {
+			result,
+			reputation_changes: Vec::new(),
+			sent_feedback: None,
+		});
+	}
+}
+
+/// Download the execution or consensus state at `block`, chunk by chunk, each from the first of
+/// `peers` returning it.
+async fn download_state<Block: BlockT>(
+	network: &NetworkService<Block, Block::Hash>,
+	peers: &[PeerId],
+	block: Block::Hash,
+	execution: bool,
+) -> Result<StorageMap, String> {
+	let mut top = StorageMap::new();
+	let mut start = None;
+	loop {
+		let request = StateRequest { block, execution, start: start.clone() }.encode();
+
+		let mut response = None;
+		for peer in peers {
+			let result = network.request(
+				peer.clone(),
+				STATE_PROTOCOL_NAME,
+				request.clone(),
+				IfDisconnected::ImmediateError,
+			).await;
+			match result.map(|encoded| StateResponse::decode(&mut &encoded[..])) {
+				Ok(Ok(decoded)) => {
+					response = Some(decoded);
+					break
+				},
+				Ok(Err(e)) => log::debug!(target: LOG_TARGET, "Bad state response from {}: {:?}", peer, e),
+				Err(e) => log::debug!(target: LOG_TARGET, "State request to {} failed: {:?}", peer, e),
+			}
+		}
+		let StateResponse { entries, complete } = response
+			.ok_or_else(|| "None of the executors returned the state".to_string())?;
+
+		let last = entries.last().map(|(key, _)| key.clone());
+		top.extend(entries);
+		if complete {
+			return Ok(top)
+		}
+		match last {
+			Some(last) if Some(&last) > start.as_ref() => start = Some(last),
+			_ => return Err("State chunks don't move forward".into()),
+		}
+	}
+}
+
+/// The root and the trie nodes of the state with the given keys and values.
+fn trie_nodes<Block: BlockT>(
+	top: &StorageMap,
+) -> Result<(Block::Hash, PrefixedMemoryDB<HashFor<Block>>), String> {
+	let (mut nodes, mut root) = (PrefixedMemoryDB::default(), Default::default());
+	{
+		let mut trie = TrieDBMut::<Layout<HashFor<Block>>>::new(&mut nodes, &mut root);
+		for (key, value) in top {
+			trie.insert(key, value).map_err(|e| format!("Failed to build the state trie: {:?}", e))?;
+		}
+	}
+	Ok((root, nodes))
+}
+
+/// Import `top` as the consensus state of the finalized block with the given header, along with
+/// the trie `nodes` of both its states, which becomes the executed and execution-confirmed head.
+fn import_state<Block, Client, Backend>(
+	client: &Client,
+	header: Block::Header,
+	top: StorageMap,
+	nodes: PrefixedMemoryDB<HashFor<Block>>,
+	execution_root: Block::Hash,
+) -> ClientResult<()> where
+	Block: BlockT,
+	Backend: BackendT<Block>,
+	Client: BlockBackend<Block> + LockImportRun<Block, Backend>,
+{
+	let hash = header.hash();
+	let number = *header.number();
+	let state_root = *header.state_root();
+	let body = client.block_body(&BlockId::Hash(hash))?;
+
+	client.lock_import_and_run(|operation| {
+		operation.op.set_block_data(header, body, None, NewBlockState::Final)?;
+		let root = operation.op.reset_storage(Storage { top, children_default: Default::default() })?;
+		if root != state_root {
+			return Err(ClientError::Msg(format!("Imported state root {} instead of {}", root, state_root)))
+		}
+		// the nodes of the consensus state only, from `reset_storage`, are replaced by the ones of
+		// both states.
+		operation.op.update_db_storage(nodes)?;
+
+		operation.op.insert_aux(vec![
+			aux_schema::execution_root_entry(&hash, &execution_root),
+			aux_schema::state_root_status_entry(&hash, &StateRootStatus::Confirmed),
+			aux_schema::executed_head_entry(&hash, &number),
+			aux_schema::confirmed_head_entry(&hash, &number),
+		].into_iter().map(|(key, value)| (key, Some(value))))?;
+		Ok(())
+	})
+}
+
+/// The most recent block whose receipts, included in the chain finalized up to `finalized_number`
+/// at least `challenge_period` blocks ago and not challenged, agree on its state root.
+///
+/// Executors syncing their state can't ask the runtime, so the bodies of the last finalized
+/// blocks are searched for receipts and fraud proofs instead. A receipt is left out if any fraud
+/// proof disputes it, whether the proof succeeded or not, or if it builds on another parent state
+/// than the one settled for the parent block, and a block whose receipts disagree is skipped, the
+/// way the runtime settles them. Whether the signers of the
+/// receipts are registered can't be checked without the state either, which is left to the block
+/// authors: they only include receipts passing `validate_unsigned`.
+fn settled_block<Block, Client>(
+	client: &Client,
+	extract_receipt: ExtractReceiptFn<Block>,
+	extract_fraud_proof: ExtractFraudProofFn<Block>,
+	finalized_number: NumberFor<Block>,
+	challenge_period: NumberFor<Block>,
+) -> Result<Option<SettledBlock<Block>>, String> where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + BlockBackend<Block>,
+{
+	let canonical_hash = |number| client.hash(number)
+		.map_err(|e| e.to_string())?
+		.ok_or_else(|| format!("No block #{} in the chain", number));
+
+	// receipts included since then are still in their challenge period.
+	let last_inclusion = finalized_number.saturating_sub(challenge_period);
+	let mut number = last_inclusion.saturating_sub(RECEIPT_SCAN_DEPTH.into());
+	let (mut receipts, mut disputed) = (Vec::new(), HashSet::new());
+	while number <= finalized_number {
+		let body = client.block_body(&BlockId::Hash(canonical_hash(number)?))
+			.map_err(|e| e.to_string())?
+			.ok_or_else(|| format!("Missing body of #{}", number))?;
+		for extrinsic in body.iter().map(Encode::encode) {
+			if let Some(fraud_proof) = extract_fraud_proof(&extrinsic) {
+				disputed.insert((fraud_proof.primary_hash, fraud_proof.executor));
+			} else if let Some(receipt) = extract_receipt(&extrinsic) {
+				if number <= last_inclusion {
+					receipts.push(receipt);
+				}
+			}
+		}
+		number += One::one();
+	}
+
+	let mut state_roots = BTreeMap::<_, (Block::Hash, Vec<(Option<Block::Hash>, Block::Hash)>)>::new();
+	for signed_receipt in receipts {
+		let receipt = &signed_receipt.execution_receipt;
+		if !signed_receipt.verify() ||
+			disputed.contains(&(receipt.primary_hash, signed_receipt.signer.clone())) ||
+			canonical_hash(receipt.primary_number)? != receipt.primary_hash
+		{
+			continue
+		}
+		state_roots.entry(receipt.primary_number)
+			.or_insert_with(|| (receipt.primary_hash, Vec::new()))
+			.1
+			.push((receipt.trace.first().copied(), receipt.state_root));
+	}
+
+	let mut settled = None;
+	for (number, (hash, state_roots)) in state_roots {
+		let parent_state_root = settled
+			.filter(|(settled_number, _, _)| *settled_number + One::one() == number)
+			.map(|(_, _, state_root)| state_root);
+		let state_roots = state_roots.into_iter()
+			.filter(|(parent, _)| parent_state_root.map_or(true, |root| *parent == Some(root)))
+			.map(|(_, state_root)| state_root)
+			.collect::<Vec<_>>();
+		if state_roots.is_empty() || state_roots.windows(2).any(|pair| pair[0] != pair[1]) {
+			continue
+		}
+		settled = Some((number, hash, state_roots[0]));
+	}
+	Ok(settled)
+}
+
+/// Sync the states at the given settled block.
+async fn sync_state_at<Block, Client, Backend>(
+	client: &Client,
+	network: &NetworkService<Block, Block::Hash>,
+	gossip: &ExecutorGossip<Block>,
+	(number, hash, state_root): SettledBlock<Block>,
+) -> Result<(), String> where
+	Block: BlockT,
+	Backend: BackendT<Block>,
+	Client: HeaderBackend<Block> + BlockBackend<Block> + AuxStore + LockImportRun<Block, Backend>,
+{
+	if let Some(StateRootStatus::Disputed(_)) = aux_schema::load_state_root_status(client, &hash)
+		.map_err(|e| e.to_string())?
+	{
+		return Err(format!("The state root of #{} is disputed", number))
+	}
+	let header = client.header(BlockId::Hash(hash))
+		.map_err(|e| e.to_string())?
+		.ok_or_else(|| format!("Missing header of #{}", number))?;
+
+	let peers = gossip.peers();
+	if peers.is_empty() {
+		return Err("Not connected to any executor".into())
+	}
+	let execution_top = download_state::<Block>(network, &peers, hash, true).await?;
+	let consensus_top = download_state::<Block>(network, &peers, hash, false).await?;
+
+	// only the top tries are synced, the runtime keeps nothing in child tries.
+	if execution_top.keys().chain(consensus_top.keys()).any(|key| key.starts_with(CHILD_STORAGE_KEY_PREFIX)) {
+		return Err("The state has child tries, which aren't synced".into())
+	}
+	let (execution_root, mut nodes) = trie_nodes::<Block>(&execution_top)?;
+	if execution_root != state_root {
+		return Err(format!("Downloaded execution state has root {} instead of {}", execution_root, state_root))
+	}
+	let (consensus_root, consensus_nodes) = trie_nodes::<Block>(&consensus_top)?;
+	if &consensus_root != header.state_root() {
+		return Err(format!(
+			"Downloaded consensus state has root {} instead of {}",
+			consensus_root,
+			header.state_root(),
+		))
+	}
+	nodes.consolidate(consensus_nodes);
+
+	import_state::<Block, _, _>(client, header, consensus_top, nodes, execution_root).map_err(|e| e.to_string())
+}
+
+/// Sync the states at the latest settled block from the executors we are connected to, once the
+/// node caught up with the chain.
+///
+/// The latest settled block is found in the finalized chain with [`settled_block`]. Without any
+/// state yet, the challenge period is the one of the genesis state. Failed attempts are repeated
+/// on finality notifications, backing off exponentially, up to [`MAX_SYNC_ATTEMPTS`] times after
+/// which the executor executes the chain from its executed head instead.
+pub async fn sync_state<Block, Client, Backend>(
+	client: Arc<Client>,
+	network: Arc<NetworkService<Block, Block::Hash>>,
+	gossip: ExecutorGossip<Block>,
+	extract_receipt: ExtractReceiptFn<Block>,
+	extract_fraud_proof: ExtractFraudProofFn<Block>,
+) where
+	Block: BlockT,
+	Backend: BackendT<Block>,
+	Client: HeaderBackend<Block> + BlockBackend<Block> + BlockchainEvents<Block> + AuxStore,
+	Client: LockImportRun<Block, Backend> + ProvideRuntimeApi<Block>,
+	Client::Api: ExecutorApi<Block>,
+{
+	let mut finality_notifications = client.finality_notification_stream();
+	let (mut attempts, mut next_attempt) = (0, Zero::zero());
+	while let Some(notification) = finality_notifications.next().await {
+		let finalized_number = *notification.header.number();
+		if network.is_major_syncing() || finalized_number < next_attempt {
+			continue
+		}
+
+		let challenge_period = match confirmation::challenge_period::<Block, _>(&*client) {
+			Ok(challenge_period) => challenge_period,
+			Err(e) => {
+				log::warn!(target: LOG_TARGET, "Failed to read the challenge period: {:?}", e);
+				continue
+			},
+		};
+		let settled = settled_block::<Block, _>(
+			&*client,
+			extract_receipt,
+			extract_fraud_proof,
+			finalized_number,
+			challenge_period,
+		);
+		let result = match settled {
+			Ok(Some(settled)) => sync_state_at::<Block, _, _>(&*client, &network, &gossip, settled)
+				.await
+				.map(|()| settled.0),
+			Ok(None) => Err("No block settled yet".into()),
+			Err(e) => Err(e),
+		};
+		match result {
+			Ok(number) => {
+				log::info!(target: LOG_TARGET, "📥 Synced the states at #{}", number);
+				return
+			},
+			Err(e) => {
+				attempts += 1;
+				log::warn!(target: LOG_TARGET, "Failed to sync the state, attempt {}/{}: {}", attempts, MAX_SYNC_ATTEMPTS, e);
+				if attempts == MAX_SYNC_ATTEMPTS {
+					log::warn!(target: LOG_TARGET, "Giving up on state sync, executing from the executed head");
+					return
+				}
+				next_attempt = finalized_number.saturating_add((1u32 << attempts).into());
+			},
+		}
+	}
+}
@@ -0,0 +1,149 @@
+//! Execution of blocks on top of a proof of their pre-state.
+//!
+//! Executing a block only needs the part of the parent execution state it touches. Nodes keeping
+//! the execution state prove it with an [`ExecutionProver`], anyone can then get the resulting
+//! state root out of the proof through the `StatelessExecutionApi`, without any database.
+//!
+//! The runtime code is part of the proven state, so that the block is executed with the runtime
+//! of its parent execution state rather than with the one of the state the API is called at.
+
+use std::sync::Arc;
+use codec::{Decode, Encode};
+use sc_client_api::{AuxStore, BlockBackend, backend::Backend as BackendT};
+use sp_blockchain::{Error as ClientError, HeaderBackend, Result as ClientResult};
+use sp_core::{storage::well_known_keys, traits::{CodeExecutor, SpawnNamed}};
+use sp_executor::{CompactProof, ExecuteBlockStateless};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, HashFor, Header as HeaderT},
+};
+use sp_state_machine::{
+	Backend as StateBackend, OverlayedChanges, ProvingBackend, TrieBackend, backend::BackendRuntimeCode,
+};
+use sp_trie::{Layout, MemoryDB};
+use super::execution_state::{self, StepExecutor};
+
+/// Proves the part of the parent execution state blocks touch.
+pub trait ProveExecution<Hash>: Send + Sync {
+	/// Prove the part of the parent execution state the block with the given hash touches.
+	fn prove_execution(&self, hash: Hash) -> ClientResult<CompactProof>;
+}
+
+/// Proves the execution of the blocks whose parent execution state is kept locally.
+pub struct ExecutionProver<Block, Client, Backend, Exec, Spawn> {
+	client: Arc<Client>,
+	backend: Arc<Backend>,
+	steps: StepExecutor<Block, Exec, Spawn>,
+}
+
+impl<Block, Client, Backend, Exec, Spawn> ExecutionProver<Block, Client, Backend, Exec, Spawn> {
+	/// Create a new prover on top of the given client, running the steps of blocks with `steps`.
+	pub fn new(client: Arc<Client>, backend: Arc<Backend>, steps: StepExecutor<Block, Exec, Spawn>) -> Self {
+		ExecutionProver { client, backend, steps }
+	}
+}
+
+impl<Block, Client, Backend, Exec, Spawn> ProveExecution<Block::Hash>
+	for ExecutionProver<Block, Client, Backend, Exec, Spawn>
+where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + BlockBackend<Block> + AuxStore + Send + Sync,
+	Backend: BackendT<Block>,
+	Exec: CodeExecutor + Clone + 'static,
+	Spawn: SpawnNamed + Clone + Send + Sync + 'static,
+{
+	fn prove_execution(&self, hash: Block::Hash) -> ClientResult<CompactProof> {
+		let id = BlockId::Hash(hash);
+		let header = self.client.header(id)?
+			.ok_or_else(|| ClientError::UnknownBlock(format!("{}", hash)))?;
+		let extrinsics = self.client.block_body(&id)?
+			.ok_or_else(|| ClientError::UnknownBlock(format!("Missing body of {}", hash)))?;
+
+		let parent_hash = *header.parent_hash();
+		let parent_root = execution_state::execution_root::<Block, _>(&*self.client, parent_hash)?
+			.ok_or_else(|| ClientError::Msg(format!("No execution state at {}", parent_hash)))?;
+		let state = self.backend.state_at(BlockId::Hash(parent_hash))?;
+		let parent_state = execution_state::execution_state::<Block, _>(&state, parent_root)?;
+		let proving_backend = ProvingBackend::new(&parent_state);
+
+		// the code is read through the proving backend so that it ends up in the proof, whether
+		// the executor has it cached or not.
+		proving_backend.storage(well_known_keys::CODE)
+			.map_err(|e| ClientError::Msg(format!("Failed to read the runtime code: {}", e)))?
+			.ok_or_else(|| ClientError::Msg(format!("No runtime code in the execution state of {}", parent_hash)))?;
+
+		let steps = self.steps.layout(&extrinsics).steps();
+		let mut overlay = OverlayedChanges::default();
+		self.steps.run_steps(&proving_backend, &mut overlay, &header, &extrinsics, &steps, None)
+			.map_err(|e| ClientError::Msg(format!("Failed to execute {}: {}", hash, e)))?;
+
+		proving_backend.extract_proof().into_compact_proof::<HashFor<Block>>(parent_root)
+			.map_err(|e| ClientError::Msg(format!("Failed to compact the proof of {}: {:?}", hash, e)))
+	}
+}
+
+/// The state root after applying `overlay` on top of the proof backed `backend`.
+///
+/// `TrieBackend::storage_root` falls back to the previous root when trie nodes are missing, which
+/// would make any incomplete proof look like a valid execution. Compute the root on our own so
+/// that a missing node is an error instead.
+pub(super) fn proof_post_state_root<Block: BlockT>(
+	backend: &TrieBackend<MemoryDB<HashFor<Block>>, HashFor<Block>>,
+	overlay: &OverlayedChanges,
+) -> Result<Block::Hash, String> {
+	let mut db = backend.backend_storage().clone();
+	let delta = overlay.changes().map(|(key, value)| (key.clone(), value.value().cloned()));
+	sp_trie::delta_trie_root::<Layout<HashFor<Block>>, _, _, _, _, _>(&mut db, *backend.root(), delta)
+		.map_err(|e| format!("Incomplete proof: {}", e))
+}
+
+/// Executes blocks on top of a proof of their pre-state, see
+/// [`sp_executor::executor_ext::execute_block_stateless`].
+pub struct StatelessBlockExecutor<Block, Exec, Spawn> {
+	steps: StepExecutor<Block, Exec, Spawn>,
+}
+
+impl<Block, Exec, Spawn> StatelessBlockExecutor<Block, Exec, Spawn> {
+	/// Create a new stateless executor, running the steps of blocks with `steps`.
+	pub fn new(steps: StepExecutor<Block, Exec, Spawn>) -> Self {
+		StatelessBlockExecutor { steps }
+	}
+}
+
+impl<Block, Exec, Spawn> ExecuteBlockStateless for StatelessBlockExecutor<Block, Exec, Spawn> where
+	Block: BlockT,
+	Exec: CodeExecutor + Clone + 'static,
+	Spawn: SpawnNamed + Clone + Send + 'static,
+{
+	fn execute_block_stateless(
+		&self,
+		encoded_block: &[u8],
+		pre_state_root: &[u8],
+		encoded_proof: &[u8],
+	) -> Result<Vec<u8>, String> {
+		let (header, extrinsics) = <(Block::Header, Vec<Block::Extrinsic>)>::decode(&mut &encoded_block[..])
+			.map_err(|e| format!("Failed to decode block: {}", e))?;
+		let pre_state_root = Block::Hash::decode(&mut &pre_state_root[..])
+			.map_err(|e| format!("Failed to decode pre state root: {}", e))?;
+		let proof = CompactProof::decode(&mut &encoded_proof[..])
+			.map_err(|e| format!("Failed to decode proof: {}", e))?;
+
+		let (proof, _) = proof.to_storage_proof::<HashFor<Block>>(Some(&pre_state_root))
+			.map_err(|e| format!("Invalid proof: {:?}", e))?;
+		let backend = sp_state_machine::create_proof_check_backend::<HashFor<Block>>(pre_state_root, proof)
+			.map_err(|e| e.to_string())?;
+
+		// the block is executed with the runtime of the pre-state, as proven.
+		let runtime_code = BackendRuntimeCode::new(&backend);
+		let runtime_code = runtime_code.runtime_code()?;
+
+		// extrinsics and bundled transactions the runtime rejects are skipped, as by executors.
+		let mut overlay = OverlayedChanges::default();
+		for step in self.steps.layout(&extrinsics).steps() {
+			let data = execution_state::step_data::<Block>(&header, &extrinsics, step);
+			self.steps.apply_step(&backend, &mut overlay, &runtime_code, step, &data)?;
+		}
+
+		proof_post_state_root::<Block>(&backend, &overlay).map(|root| root.encode())
+	}
+}
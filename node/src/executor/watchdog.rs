@@ -0,0 +1,320 @@
+//! Watchdog challenging the receipts that disagree with our own execution.
+//!
+//! The receipts of other executors come from the gossip or from the chain. Whenever one of them
+//! diverges from ours, the first diverging step is proven wrong with a fraud proof, which is
+//! submitted through the transaction pool along with the receipt it challenges.
+
+use std::{collections::{BTreeMap, HashSet}, sync::Arc};
+use futures::{channel::mpsc, StreamExt};
+use sc_client_api::{AuxStore, backend::Backend as BackendT};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::traits::{CodeExecutor, SpawnNamed};
+use sp_executor::{
+	ExecutionReceipt, ExecutorApi, ExecutorId, FraudProof, FraudProofApi, SignedExecutionReceipt,
+};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, NumberFor, Saturating},
+};
+use super::{
+	FraudProofGenerator, LOG_TARGET,
+	gossip::{ExecutorGossip, GossipMessage},
+};
+
+/// Number of blocks after their execution during which receipts are compared with ours.
+const WATCH_DEPTH: u32 = 256;
+
+/// Number of blocks before the executed head whose on-chain receipts are checked.
+const ON_CHAIN_CHECK_DEPTH: u32 = 16;
+
+/// Number of blocks past our best block whose receipts are kept until we execute them.
+const MAX_EARLY_RECEIPTS_AHEAD: u32 = 8;
+
+/// Maximum number of receipts kept for a single block we haven't executed yet.
+const MAX_EARLY_RECEIPTS_PER_BLOCK: usize = 32;
+
+/// Maximum number of receipts kept for the blocks we haven't executed yet.
+const MAX_EARLY_RECEIPTS: usize = 256;
+
+type Receipt<Block> = ExecutionReceipt<NumberFor<Block>, <Block as BlockT>::Hash>;
+type SignedReceipt<Block> = SignedExecutionReceipt<NumberFor<Block>, <Block as BlockT>::Hash>;
+
+/// Where the trace of a receipt diverges from ours.
+enum Divergence<Hash> {
+	/// The receipt agrees with ours.
+	None,
+	/// The state root after the step at the given index differs, as claimed by the receipt.
+	Step(u32, Hash),
+	/// The receipt executes the block on top of another parent execution state, which is for
+	/// the receipts of the parent block to settle.
+	ParentState,
+	/// The trace of the receipt doesn't go through the steps of the block or doesn't end with
+	/// its state root, the runtime doesn't accept it anyway.
+	Malformed,
+}
+
+/// Find where `theirs` diverges from `ours`. Both are about the same block.
+fn divergence<Number, Hash: Copy + PartialEq>(
+	ours: &ExecutionReceipt<Number, Hash>,
+	theirs: &ExecutionReceipt<Number, Hash>,
+) -> Divergence<Hash> {
+	if ours.trace == theirs.trace && ours.state_root == theirs.state_root {
+		return Divergence::None
+	}
+
+	if theirs.trace.len() != ours.trace.len() || theirs.trace.last() != Some(&theirs.state_root) {
+		return Divergence::Malformed
+	}
+
+	// `trace[0]` is the parent execution root, `trace[i + 1]` the one after step i.
+	match ours.trace.iter().zip(&theirs.trace).position(|(a, b)| a != b) {
+		Some(0) => Divergence::ParentState,
+		Some(index) => Divergence::Step(index as u32 - 1, theirs.trace[index]),
+		None => Divergence::Malformed,
+	}
+}
+
+/// State of the watchdog.
+struct Watchdog<Block: BlockT, Client, Backend, Exec, Spawn> {
+	client: Arc<Client>,
+	generator: FraudProofGenerator<Block, Client, Backend, Exec, Spawn>,
+	gossip: ExecutorGossip<Block>,
+	/// The last block we executed.
+	executed_head: Option<(Block::Hash, NumberFor<Block>)>,
+	/// Our receipts of the recently executed blocks.
+	own_receipts: BTreeMap<NumberFor<Block>, Receipt<Block>>,
+	/// Receipts of other executors about blocks we haven't executed yet.
+	early_receipts: BTreeMap<NumberFor<Block>, Vec<(ExecutorId, Receipt<Block>, Option<SignedReceipt<Block>>)>>,
+	/// Fraud proofs waiting for the receipt they challenge to be on chain.
+	pending_fraud_proofs: Vec<FraudProof<NumberFor<Block>, Block::Hash>>,
+	/// Receipts already challenged, by block number and executor.
+	challenged: HashSet<(NumberFor<Block>, ExecutorId)>,
+}
+
+impl<Block, Client, Backend, Exec, Spawn> Watchdog<Block, Client, Backend, Exec, Spawn> where
+	Block: BlockT,
+	Backend: BackendT<Block>,
+	Client: HeaderBackend<Block> + sc_client_api::BlockBackend<Block> + AuxStore + ProvideRuntimeApi<Block>,
+	Client::Api: ExecutorApi<Block> + FraudProofApi<Block>,
+	Exec: CodeExecutor + Clone + 'static,
+	Spawn: SpawnNamed + Clone + Send + 'static,
+{
+	/// We executed a block and came up with `receipt`.
+	fn on_executed(&mut self, receipt: Receipt<Block>) {
+		let number = receipt.primary_number;
+		let at = BlockId::Hash(receipt.primary_hash);
+		self.executed_head = Some((receipt.primary_hash, number));
+		self.own_receipts.insert(number, receipt);
+
+		let oldest = number.saturating_sub(WATCH_DEPTH.into());
+		self.own_receipts = self.own_receipts.split_off(&oldest);
+		self.challenged.retain(|(challenged_number, _)| *challenged_number >= oldest);
+		self.pending_fraud_proofs.retain(|fraud_proof| fraud_proof.primary_number >= oldest);
+
+		for (executor, receipt, signed) in self.early_receipts.remove(&number).unwrap_or_default() {
+			self.check(executor, receipt, signed);
+		}
+		// receipts of blocks we won't execute anymore.
+		self.early_receipts = self.early_receipts.split_off(&number);
+
+		// receipts put on chain by others, e.g. along with their own challenges.
+		let from = number.saturating_sub(ON_CHAIN_CHECK_DEPTH.into());
+		let on_chain = self.own_receipts.range(from..).map(|(number, _)| *number).collect::<Vec<_>>();
+		for receipt_number in on_chain {
+			match self.client.runtime_api().execution_receipts(&at, receipt_number) {
+				Ok(receipts) => for (executor, receipt) in receipts {
+					self.check(executor, receipt, None);
+				},
+				Err(e) => log::error!(target: LOG_TARGET, "Failed to read receipts of #{:?}: {:?}", receipt_number, e),
+			}
+		}
+
+		self.submit_pending_fraud_proofs();
+	}
+
+	/// Compare the receipt of `executor` with ours. `signed` is the receipt as the executor
+	/// signed it, if it is not on chain yet.
+	fn check(&mut self, executor: ExecutorId, receipt: Receipt<Block>, signed: Option<SignedReceipt<Block>>) {
+		let number = receipt.primary_number;
+		if self.challenged.contains(&(number, executor.clone())) {
+			return
+		}
+
+		let ours = match self.own_receipts.get(&number) {
+			Some(ours) => ours,
+			None => {
+				if self.executed_head.map_or(true, |(_, executed)| number > executed) {
+					self.note_early_receipt(executor, receipt, signed);
+				}
+				return
+			},
+		};
+		// not the block we executed.
+		if ours.primary_hash != receipt.primary_hash {
+			return
+		}
+
+		let (step_index, post_state_root) = match divergence(ours, &receipt) {
+			Divergence::None => return,
+			Divergence::Step(index, post_state_root) => (index, post_state_root),
+			Divergence::ParentState => {
+				log::warn!(
+					target: LOG_TARGET,
+					"Receipt of {:?} for #{:?} builds on another parent execution state than ours",
+					executor,
+					number,
+				);
+				return
+			},
+			Divergence::Malformed => {
+				log::debug!(target: LOG_TARGET, "Malformed receipt of {:?} for #{:?}", executor, number);
+				return
+			},
+		};
+
+		log::info!(
+			target: LOG_TARGET,
+			"🚨 Receipt of {:?} for #{:?} diverges at step #{}, challenging it",
+			executor,
+			number,
+			step_index,
+		);
+		self.challenged.insert((number, executor.clone()));
+
+		let fraud_proof = match self.generator.prove(
+			receipt.primary_hash,
+			step_index,
+			executor,
+			post_state_root,
+		) {
+			Ok(fraud_proof) => fraud_proof,
+			Err(e) => {
+				log::error!(target: LOG_TARGET, "Failed to prove fraud in #{:?}: {:?}", number, e);
+				return
+			},
+		};
+
+		if let (Some(signed), Some((head, _))) = (signed, self.executed_head) {
+			// the challenged receipt has to be on chain for the fraud proof to be checked.
+			match self.client.runtime_api().submit_execution_receipt_unsigned(&BlockId::Hash(head), signed) {
+				Ok(Some(())) => {},
+				Ok(None) => log::debug!(target: LOG_TARGET, "Challenged receipt of #{:?} rejected by the pool", number),
+				Err(e) => log::error!(target: LOG_TARGET, "Failed to submit challenged receipt: {:?}", e),
+			}
+		}
+
+		self.gossip.gossip(GossipMessage::FraudProof(fraud_proof.clone()));
+		self.pending_fraud_proofs.push(fraud_proof);
+		self.submit_pending_fraud_proofs();
+	}
+
+	/// Keep the receipt of a block we haven't executed yet, to check it once we have.
+	///
+	/// Anyone can gossip receipts, so they are only kept for the blocks up to
+	/// `MAX_EARLY_RECEIPTS_AHEAD` past our best block, one per executor, and within
+	/// `MAX_EARLY_RECEIPTS_PER_BLOCK` per block and `MAX_EARLY_RECEIPTS` overall.
+	fn note_early_receipt(&mut self, executor: ExecutorId, receipt: Receipt<Block>, signed: Option<SignedReceipt<Block>>) {
+		let number = receipt.primary_number;
+		let best_number = self.client.info().best_number;
+		if number > best_number.saturating_add(MAX_EARLY_RECEIPTS_AHEAD.into()) {
+			log::debug!(target: LOG_TARGET, "Dropped receipt of {:?} for #{:?}, too far ahead", executor, number);
+			return
+		}
+
+		let total = self.early_receipts.values().map(Vec::len).sum::<usize>();
+		let receipts = self.early_receipts.get(&number).map_or(&[][..], Vec::as_slice);
+		if receipts.iter().any(|(known, _, _)| *known == executor) {
+			return
+		}
+		if total >= MAX_EARLY_RECEIPTS || receipts.len() >= MAX_EARLY_RECEIPTS_PER_BLOCK {
+			log::debug!(target: LOG_TARGET, "Dropped receipt of {:?} for #{:?}, too many pending", executor, number);
+			return
+		}
+
+		self.early_receipts.entry(number).or_default().push((executor, receipt, signed));
+	}
+
+	/// Submit the fraud proofs whose receipt made it on chain, keep the others for later.
+	fn submit_pending_fraud_proofs(&mut self) {
+		let at = match self.executed_head {
+			Some((head, _)) => BlockId::Hash(head),
+			None => return,
+		};
+
+		let client = &self.client;
+		self.pending_fraud_proofs.retain(|fraud_proof| {
+			match client.runtime_api().submit_fraud_proof_unsigned(&at, fraud_proof.clone()) {
+				Ok(Some(())) => {
+					log::info!(
+						target: LOG_TARGET,
+						"Submitted fraud proof against {:?} for #{:?}",
+						fraud_proof.executor,
+						fraud_proof.primary_number,
+					);
+					false
+				},
+				// most likely the receipt isn't on chain yet.
+				Ok(None) => true,
+				Err(e) => {
+					log::error!(target: LOG_TARGET, "Failed to submit fraud proof: {:?}", e);
+					true
+				},
+			}
+		});
+	}
+}
+
+/// Start the watchdog. It is fed the receipts of the blocks we executed through `own_receipts`,
+/// and those of other executors through the gossip and the chain.
+pub async fn start_watchdog<Block, Client, Backend, Exec, Spawn>(
+	client: Arc<Client>,
+	generator: FraudProofGenerator<Block, Client, Backend, Exec, Spawn>,
+	gossip: ExecutorGossip<Block>,
+	mut own_receipts: mpsc::UnboundedReceiver<Receipt<Block>>,
+) where
+	Block: BlockT,
+	Backend: BackendT<Block>,
+	Client: HeaderBackend<Block> + sc_client_api::BlockBackend<Block> + AuxStore + ProvideRuntimeApi<Block>,
+	Client::Api: ExecutorApi<Block> + FraudProofApi<Block>,
+	Exec: CodeExecutor + Clone + 'static,
+	Spawn: SpawnNamed + Clone + Send + 'static,
+{
+	let mut gossiped = gossip.messages().fuse();
+	let mut watchdog = Watchdog {
+		client,
+		generator,
+		gossip,
+		executed_head: None,
+		own_receipts: BTreeMap::new(),
+		early_receipts: BTreeMap::new(),
+		pending_fraud_proofs: Vec::new(),
+		challenged: HashSet::new(),
+	};
+
+	loop {
+		futures::select! {
+			receipt = own_receipts.next() => match receipt {
+				Some(receipt) => watchdog.on_executed(receipt),
+				// the executor is gone.
+				None => return,
+			},
+			message = gossiped.next() => match message {
+				Some(GossipMessage::Receipt(signed)) => {
+					let executor = signed.signer.clone();
+					let receipt = signed.execution_receipt.clone();
+					watchdog.check(executor, receipt, Some(signed));
+				},
+				// someone else challenged a receipt, help getting the proof on chain.
+				Some(GossipMessage::FraudProof(fraud_proof)) => {
+					if watchdog.challenged.insert((fraud_proof.primary_number, fraud_proof.executor.clone())) {
+						watchdog.pending_fraud_proofs.push(fraud_proof);
+					}
+				},
+				Some(GossipMessage::Bundle(_)) => {},
+				None => return,
+			},
+		}
+	}
+}
+
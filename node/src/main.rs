@@ -6,7 +6,9 @@ mod chain_spec;
 mod service;
 mod cli;
 mod command;
+mod equivocation;
 mod rpc;
+mod executor;
 
 fn main() -> sc_cli::Result<()> {
 	command::run()
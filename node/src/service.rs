@@ -2,9 +2,10 @@
 
 use std::sync::Arc;
 use std::time::Duration;
+use codec::{Decode, Encode};
 use sc_client_api::{ExecutorProvider, RemoteBackend};
-use node_template_runtime::{self, opaque::Block, RuntimeApi};
-use sc_service::{error::Error as ServiceError, Configuration, TaskManager};
+use node_template_runtime::{self, opaque::Block, BlockNumber, ExecutionExtractor, Hash, RuntimeApi};
+use sc_service::{error::Error as ServiceError, Configuration, SpawnTaskHandle, TaskManager};
 use sp_inherents::InherentDataProviders;
 use sc_executor::native_executor_instance;
 pub use sc_executor::NativeExecutor;
@@ -13,23 +14,63 @@ use sc_consensus_aura::{ImportQueueParams, StartAuraParams, SlotProportion};
 use sc_finality_grandpa::SharedVoterState;
 use sc_keystore::LocalKeystore;
 use sc_telemetry::{Telemetry, TelemetryWorker};
+use sp_runtime::traits::Block as BlockT;
+use crate::executor::{
+	ExecutionBackoff, ExecutionChainApi, ExecutionConfig, ExecutionMetrics, ExecutionProver,
+	ExecutionRole, ExecutorExtensionsFactory, ExecutorGossip, FraudProofGenerator, FraudProofVerifier,
+	LocalPool, OrderingBlockImport, OrderingPool, ProveExecution, StatelessBlockExecutor, StepExecutor,
+};
 
 // Our native executor instance.
 native_executor_instance!(
 	pub Executor,
 	node_template_runtime::api::dispatch,
 	node_template_runtime::native_version,
-	frame_benchmarking::benchmarking::HostFunctions,
+	(frame_benchmarking::benchmarking::HostFunctions, sp_executor::executor_ext::HostFunctions),
 );
 
-type FullClient = sc_service::TFullClient<Block, RuntimeApi, Executor>;
+pub type FullClient = sc_service::TFullClient<Block, RuntimeApi, Executor>;
 type FullBackend = sc_service::TFullBackend<Block>;
 type FullSelectChain = sc_consensus::LongestChain<FullBackend, Block>;
+type FullStepExecutor = StepExecutor<Block, NativeExecutor<Executor>, SpawnTaskHandle>;
+
+/// Transaction pool of full nodes, validating user transactions against the execution state on
+/// executors.
+pub type FullPool = sc_transaction_pool::BasicPool<
+	ExecutionChainApi<Block, FullClient, FullBackend, NativeExecutor<Executor>, SpawnTaskHandle>,
+	Block,
+>;
+
+/// Whether the extrinsic is a user transaction, which only makes it into blocks through the
+/// bundle of an executor.
+fn is_bundled(xt: &<Block as BlockT>::Extrinsic) -> bool {
+	node_template_runtime::UncheckedExtrinsic::decode(&mut &xt.encode()[..])
+		.map(|xt| node_template_runtime::is_bundled(&xt))
+		.unwrap_or(false)
+}
+
+/// The bundle the encoded extrinsic submits, if any.
+fn extract_bundle(xt: &[u8]) -> Option<sp_executor::SignedOpaqueBundle> {
+	<node_template_runtime::BundleExtractor as sp_executor::ExtractBundle>::extract_bundle(xt)
+}
+
+/// The execution receipt the encoded extrinsic submits, if any.
+fn extract_receipt(xt: &[u8]) -> Option<sp_executor::SignedExecutionReceipt<BlockNumber, Hash>> {
+	<ExecutionExtractor as sp_executor::ExtractExecution<_, _>>::extract_receipt(xt)
+}
+
+/// The fraud proof the encoded extrinsic submits, if any.
+fn extract_fraud_proof(xt: &[u8]) -> Option<sp_executor::FraudProof<BlockNumber, Hash>> {
+	<ExecutionExtractor as sp_executor::ExtractExecution<_, _>>::extract_fraud_proof(xt)
+}
 
-pub fn new_partial(config: &Configuration) -> Result<sc_service::PartialComponents<
+pub fn new_partial(
+	config: &Configuration,
+	execution_role: ExecutionRole,
+) -> Result<sc_service::PartialComponents<
 	FullClient, FullBackend, FullSelectChain,
 	sp_consensus::DefaultImportQueue<Block, FullClient>,
-	sc_transaction_pool::FullPool<Block, FullClient>,
+	FullPool,
 	(
 		sc_consensus_aura::AuraBlockImport<
 			Block,
@@ -39,6 +80,7 @@ pub fn new_partial(config: &Configuration) -> Result<sc_service::PartialComponen
 		>,
 		sc_finality_grandpa::LinkHalf<Block, FullClient, FullSelectChain>,
 		Option<Telemetry>,
+		FullStepExecutor,
 	)
 >, ServiceError> {
 	if config.keystore_remote.is_some() {
@@ -56,7 +98,7 @@ pub fn new_partial(config: &Configuration) -> Result<sc_service::PartialComponen
 		})
 		.transpose()?;
 
-	let (client, backend, keystore_container, task_manager) =
+	let (client, backend, keystore_container, mut task_manager) =
 		sc_service::new_full_parts::<Block, RuntimeApi, Executor>(
 			&config,
 			telemetry.as_ref().map(|(_, telemetry)| telemetry.handle()),
@@ -69,15 +111,49 @@ pub fn new_partial(config: &Configuration) -> Result<sc_service::PartialComponen
 			telemetry
 		});
 
+	// the runtime hands fraud proofs and stateless executions over to the node, which runs them
+	// step by step on the execution state.
+	let code_executor = NativeExecutor::<Executor>::new(
+		config.wasm_method,
+		config.default_heap_pages,
+		config.max_runtime_instances,
+	);
+	let fraud_proof_verifier: Arc<dyn sp_executor::VerifyFraudProof + Send + Sync> = Arc::new(
+		FraudProofVerifier::<Block, _, _>::new(code_executor.clone(), task_manager.spawn_handle()),
+	);
+	let steps = StepExecutor::<Block, _, _>::new(
+		code_executor,
+		task_manager.spawn_handle(),
+		fraud_proof_verifier.clone(),
+		extract_bundle,
+	);
+	let stateless_executor = StatelessBlockExecutor::new(steps.clone());
+	client.execution_extensions().set_extensions_factory(Box::new(
+		ExecutorExtensionsFactory::new(fraud_proof_verifier, Arc::new(stateless_executor)),
+	));
+
 	let select_chain = sc_consensus::LongestChain::new(backend.clone());
 
-	let transaction_pool = sc_transaction_pool::BasicPool::new_full(
+	let pool_api = ExecutionChainApi::new(
+		sc_transaction_pool::FullChainApi::new(client.clone(), config.prometheus_registry()),
+		client.clone(),
+		backend.clone(),
+		execution_role.is_executor().then(|| steps.clone()),
+		is_bundled,
+		task_manager.spawn_handle(),
+	);
+	let transaction_pool = Arc::new(sc_transaction_pool::BasicPool::with_revalidation_type(
 		config.transaction_pool.clone(),
 		config.role.is_authority().into(),
+		Arc::new(pool_api),
 		config.prometheus_registry(),
+		sc_transaction_pool::RevalidationType::Full,
 		task_manager.spawn_handle(),
-		client.clone(),
-	);
+	));
+	// the runtime submits receipts, bundles and reports through the pool.
+	let local_pool = Arc::new(LocalPool::new(transaction_pool.clone()));
+	client.execution_extensions().register_transaction_pool(&local_pool);
+	task_manager.keep_alive(local_pool);
 
 	let (grandpa_block_import, grandpa_link) = sc_finality_grandpa::block_import(
 		client.clone(),
@@ -90,9 +166,18 @@ pub fn new_partial(config: &Configuration) -> Result<sc_service::PartialComponen
 		grandpa_block_import.clone(), client.clone(),
 	);
 
+	// blocks we author come with their storage changes and always go through the plain
+	// `aura_block_import`, only the ones coming from the import queue may skip execution.
+	let ordering_block_import = OrderingBlockImport::new(
+		aura_block_import.clone(),
+		execution_role,
+		config.role.is_authority(),
+		extract_bundle,
+	);
+
 	let import_queue = sc_consensus_aura::import_queue::<AuraPair, _, _, _, _, _>(
 		ImportQueueParams {
-			block_import: aura_block_import.clone(),
+			block_import: ordering_block_import,
 			justification_import: Some(Box::new(grandpa_block_import.clone())),
 			client: client.clone(),
 			inherent_data_providers: inherent_data_providers.clone(),
@@ -114,7 +199,7 @@ pub fn new_partial(config: &Configuration) -> Result<sc_service::PartialComponen
 		select_chain,
 		transaction_pool,
 		inherent_data_providers,
-		other: (aura_block_import, grandpa_link, telemetry),
+		other: (aura_block_import, grandpa_link, telemetry, steps),
 	})
 }
 
@@ -125,8 +210,31 @@ fn remote_keystore(_url: &String) -> Result<Arc<LocalKeystore>, &'static str> {
 	Err("Remote Keystore not supported.")
 }
 
+/// The parts of a running full node others can drive it through.
+pub struct NewFullBase {
+	/// Task manager of the node, dropping it stops the node.
+	pub task_manager: TaskManager,
+	/// Client of the node.
+	pub client: Arc<FullClient>,
+	/// Network service of the node.
+	pub network: Arc<sc_network::NetworkService<Block, <Block as BlockT>::Hash>>,
+	/// Transaction pool of the node.
+	pub transaction_pool: Arc<FullPool>,
+}
+
 /// Builds a new service for a full client.
-pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError> {
+pub fn new_full(
+	config: Configuration,
+	execution_config: ExecutionConfig,
+) -> Result<TaskManager, ServiceError> {
+	new_full_base(config, execution_config).map(|NewFullBase { task_manager, .. }| task_manager)
+}
+
+/// Builds a new service for a full client, and returns its main parts.
+pub fn new_full_base(
+	mut config: Configuration,
+	execution_config: ExecutionConfig,
+) -> Result<NewFullBase, ServiceError> {
 	let sc_service::PartialComponents {
 		client,
 		backend,
@@ -136,8 +244,8 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 		select_chain,
 		transaction_pool,
 		inherent_data_providers,
-		other: (block_import, grandpa_link, mut telemetry),
-	} = new_partial(&config)?;
+		other: (block_import, grandpa_link, mut telemetry, steps),
+	} = new_partial(&config, execution_config.role)?;
 
 	if let Some(url) = &config.keystore_remote {
 		match remote_keystore(url) {
@@ -150,6 +258,20 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 	}
 
 	config.network.extra_sets.push(sc_finality_grandpa::grandpa_peers_set_config());
+	if execution_config.role.is_executor() {
+		config.network.extra_sets.push(crate::executor::executor_peers_set_config());
+	}
+	// full nodes serve the chunks of the bundles of the blocks they have.
+	let (chunk_requests_sink, chunk_requests) = futures::channel::mpsc::channel(16);
+	config.network.request_response_protocols
+		.push(crate::executor::availability_protocol_config(Some(chunk_requests_sink)));
+	// executors serve their state to the ones syncing it.
+	let state_requests = execution_config.role.is_executor().then(|| {
+		let (sink, requests) = futures::channel::mpsc::channel(16);
+		config.network.request_response_protocols
+			.push(crate::executor::state_request_protocol_config(sink));
+		requests
+	});
 
 	let (network, network_status_sinks, system_rpc_tx, network_starter) =
 		sc_service::build_network(sc_service::BuildNetworkParams {
@@ -168,22 +290,58 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 		);
 	}
 
+	task_manager.spawn_handle().spawn(
+		"availability-chunk-requests",
+		crate::executor::start_chunk_request_handler(client.clone(), chunk_requests, extract_bundle),
+	);
+	if execution_config.availability_samples > 0 {
+		task_manager.spawn_handle().spawn(
+			"availability-sampler",
+			crate::executor::start_availability_sampler(
+				client.clone(),
+				network.clone(),
+				execution_config.availability_samples,
+			),
+		);
+	}
+
 	let role = config.role.clone();
+	let execution_role = execution_config.role;
+	// executors follow the chain, they never author blocks or vote on finality.
+	let is_authority = role.is_authority() && execution_role.is_consensus();
 	let force_authoring = config.force_authoring;
-	let backoff_authoring_blocks: Option<()> = None;
+	// executors only put receipts on chain for blocks ordered by `consensus` nodes.
+	let backoff_authoring_blocks = match (execution_role, execution_config.backpressure.clone()) {
+		(ExecutionRole::Consensus, Some(backpressure)) =>
+			Some(ExecutionBackoff::<Block, _>::new(client.clone(), backpressure)),
+		_ => None,
+	};
 	let name = config.network.node_name.clone();
 	let enable_grandpa = !config.disable_grandpa;
 	let prometheus_registry = config.prometheus_registry().cloned();
 
+	if execution_role.is_executor() {
+		if let sc_service::config::PruningMode::Constrained(_) = config.state_pruning {
+			log::warn!(
+				target: crate::executor::LOG_TARGET,
+				"Execution states share trie nodes with later consensus states, run executors with `--pruning archive`",
+			);
+		}
+	}
+
 	let rpc_extensions_builder = {
 		let client = client.clone();
 		let pool = transaction_pool.clone();
+		// only executors keep the execution state blocks are proven against.
+		let execution_prover: Option<Arc<dyn ProveExecution<Hash>>> = execution_role.is_executor()
+			.then(|| Arc::new(ExecutionProver::new(client.clone(), backend.clone(), steps.clone())) as _);
 
 		Box::new(move |deny_unsafe, _| {
 			let deps = crate::rpc::FullDeps {
 				client: client.clone(),
 				pool: pool.clone(),
 				deny_unsafe,
+				execution_prover: execution_prover.clone(),
 			};
 
 			crate::rpc::create_full(deps)
@@ -200,7 +358,7 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 			rpc_extensions_builder,
 			on_demand: None,
 			remote_blockchain: None,
-			backend,
+			backend: backend.clone(),
 			network_status_sinks,
 			system_rpc_tx,
 			config,
@@ -208,11 +366,37 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 		},
 	)?;
 
-	if role.is_authority() {
+	if let Some(registry) = prometheus_registry.as_ref() {
+		task_manager.spawn_handle().spawn(
+			"execution-metrics",
+			crate::executor::start_metrics_reporter(
+				client.clone(),
+				ExecutionMetrics::register(registry)?,
+				execution_role,
+				is_authority,
+			),
+		);
+	}
+
+	task_manager.spawn_handle().spawn(
+		"execution-confirmation",
+		crate::executor::start_confirmation_tracker(
+			client.clone(),
+			execution_role,
+			is_authority,
+		),
+	);
+
+	if is_authority {
+		// coupled nodes keep authoring blocks out of the whole pool.
+		let is_bundled: fn(&<Block as BlockT>::Extrinsic) -> bool = match execution_role {
+			ExecutionRole::Coupled => |_| false,
+			_ => is_bundled,
+		};
 		let proposer_factory = sc_basic_authorship::ProposerFactory::new(
 			task_manager.spawn_handle(),
 			client.clone(),
-			transaction_pool,
+			Arc::new(OrderingPool::new(transaction_pool.clone(), is_bundled)),
 			prometheus_registry.as_ref(),
 			telemetry.as_ref().map(|x| x.handle()),
 		);
@@ -241,11 +425,85 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 		// the AURA authoring task is considered essential, i.e. if it
 		// fails we take down the service with it.
 		task_manager.spawn_essential_handle().spawn_blocking("aura", aura);
+
+		// authorities put the equivocations they notice in their own blocks.
+		task_manager.spawn_handle().spawn(
+			"aura-equivocation-reporter",
+			crate::equivocation::start_equivocation_reporter(client.clone()),
+		);
+	}
+
+	if execution_role.is_executor() {
+		let gossip = ExecutorGossip::new(network.clone(), prometheus_registry.as_ref());
+		task_manager.spawn_handle().spawn("executor-gossip", gossip.clone().run());
+		let (own_receipts_sink, own_receipts) = futures::channel::mpsc::unbounded();
+
+		// receipts disagreeing with ours are challenged without any action of the operator.
+		let fraud_proof_generator = FraudProofGenerator::new(client.clone(), backend.clone(), steps.clone());
+		// an executor lying on purpose doesn't challenge its own receipts.
+		if execution_config.invalid_execution.is_none() {
+			task_manager.spawn_handle().spawn_blocking(
+				"executor-watchdog",
+				crate::executor::start_watchdog(
+					client.clone(),
+					fraud_proof_generator,
+					gossip.clone(),
+					own_receipts,
+				),
+			);
+		} else {
+			log::warn!(target: crate::executor::LOG_TARGET, "Publishing invalid execution receipts on purpose");
+		}
+
+		if let Some(state_requests) = state_requests {
+			task_manager.spawn_handle().spawn(
+				"executor-state-requests",
+				crate::executor::start_state_request_handler(client.clone(), backend.clone(), state_requests),
+			);
+		}
+		let state_sync = execution_config.state_sync.then(|| crate::executor::sync_state(
+			client.clone(),
+			network.clone(),
+			gossip.clone(),
+			extract_receipt,
+			extract_fraud_proof,
+		));
+
+		// the executor is what the node is run for, if it stops we take down the
+		// service with it.
+		let executor = crate::executor::start_executor(
+			client.clone(),
+			backend,
+			steps,
+			keystore_container.sync_keystore(),
+			gossip.clone(),
+			own_receipts_sink,
+			execution_config.invalid_execution.clone(),
+		);
+		// the executor starts from the synced state, if any.
+		task_manager.spawn_essential_handle().spawn_blocking("executor", async move {
+			if let Some(state_sync) = state_sync {
+				state_sync.await;
+			}
+			executor.await
+		});
+
+		task_manager.spawn_handle().spawn(
+			"bundle-producer",
+			crate::executor::start_bundle_producer(
+				client.clone(),
+				transaction_pool.clone(),
+				keystore_container.sync_keystore(),
+				gossip,
+				is_bundled,
+				extract_bundle,
+			),
+		);
 	}
 
 	// if the node isn't actively participating in consensus then it doesn't
 	// need a keystore, regardless of which protocol we use below.
-	let keystore = if role.is_authority() {
+	let keystore = if is_authority {
 		Some(keystore_container.sync_keystore())
 	} else {
 		None
@@ -258,7 +516,7 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 		name: Some(name),
 		observer_enabled: false,
 		keystore,
-		is_authority: role.is_authority(),
+		is_authority,
 		telemetry: telemetry.as_ref().map(|x| x.handle()),
 	};
 
@@ -272,7 +530,7 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 		let grandpa_config = sc_finality_grandpa::GrandpaParams {
 			config: grandpa_config,
 			link: grandpa_link,
-			network,
+			network: network.clone(),
 			voting_rule: sc_finality_grandpa::VotingRulesBuilder::default().build(),
 			prometheus_registry,
 			shared_voter_state: SharedVoterState::empty(),
@@ -288,11 +546,14 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 	}
 
 	network_starter.start_network();
-	Ok(task_manager)
+	Ok(NewFullBase { task_manager, client, network, transaction_pool })
 }
 
 /// Builds a new service for a light client.
-pub fn new_light(mut config: Configuration) -> Result<TaskManager, ServiceError> {
+pub fn new_light(
+	mut config: Configuration,
+	execution_config: ExecutionConfig,
+) -> Result<TaskManager, ServiceError> {
 	let telemetry = config.telemetry_endpoints.clone()
 		.filter(|x| !x.is_empty())
 		.map(|endpoints| -> Result<_, sc_telemetry::Error> {
@@ -315,6 +576,9 @@ pub fn new_light(mut config: Configuration) -> Result<TaskManager, ServiceError>
 		});
 
 	config.network.extra_sets.push(sc_finality_grandpa::grandpa_peers_set_config());
+	// light clients only sample chunks, they have no bundles to serve.
+	config.network.request_response_protocols
+		.push(crate::executor::availability_protocol_config(None));
 
 	let select_chain = sc_consensus::LongestChain::new(backend.clone());
 
@@ -370,6 +634,17 @@ pub fn new_light(mut config: Configuration) -> Result<TaskManager, ServiceError>
 		);
 	}
 
+	if execution_config.availability_samples > 0 {
+		task_manager.spawn_handle().spawn(
+			"availability-sampler",
+			crate::executor::start_availability_sampler(
+				client.clone(),
+				network.clone(),
+				execution_config.availability_samples,
+			),
+		);
+	}
+
 	sc_service::spawn_tasks(sc_service::SpawnTasksParams {
 		remote_blockchain: Some(backend.remote_blockchain()),
 		transaction_pool,
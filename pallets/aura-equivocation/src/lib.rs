@@ -0,0 +1,284 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Reports Aura authorities sealing two different blocks for the same slot.
+//!
+//! Nodes noticing an equivocation submit the two headers through an unsigned extrinsic, along with
+//! the proof that the offender key belonged to an authority of the session, from the historical
+//! sessions. Once checked, the equivocation is reported as an offence, for the offences handler to
+//! penalize the authority.
+//!
+//! Sessions are rotated by block number, so the session of a slot can't be computed from the slot
+//! alone: the pallet notes the first slot of each session instead, and only accepts proofs for the
+//! session the slot of the equivocation falls in.
+//!
+//! Reports are only accepted from the local node or from blocks, and aren't propagated: every
+//! authority notices equivocations on its own.
+
+pub use pallet::*;
+
+use codec::{Decode, Encode};
+use sp_aura_equivocation::Slot;
+use sp_runtime::{Perbill, RuntimeDebug};
+use sp_staking::{SessionIndex, offence::{Kind, Offence}};
+use sp_std::vec::Vec;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+/// An Aura authority sealing several blocks for the same slot.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct AuraEquivocationOffence<FullIdentification> {
+	/// The slot of the blocks.
+	pub slot: Slot,
+	/// The session the offence happened in.
+	pub session_index: SessionIndex,
+	/// The number of authorities in that session.
+	pub validator_set_count: u32,
+	/// The authority that equivocated.
+	pub offender: FullIdentification,
+}
+
+impl<FullIdentification: Clone> Offence<FullIdentification> for AuraEquivocationOffence<FullIdentification> {
+	const ID: Kind = *b"aura:equivocatio";
+	type TimeSlot = Slot;
+
+	fn offenders(&self) -> Vec<FullIdentification> {
+		sp_std::vec![self.offender.clone()]
+	}
+
+	fn session_index(&self) -> SessionIndex {
+		self.session_index
+	}
+
+	fn validator_set_count(&self) -> u32 {
+		self.validator_set_count
+	}
+
+	fn time_slot(&self) -> Self::TimeSlot {
+		self.slot
+	}
+
+	/// `min((3 * offenders_count / validator_set_count)^2, 1)`, as for BABE and GRANDPA.
+	fn slash_fraction(offenders_count: u32, validator_set_count: u32) -> Perbill {
+		let ratio = (3 * offenders_count as u64 * 1_000_000_000 / validator_set_count.max(1) as u64)
+			.min(1_000_000_000);
+		Perbill::from_parts(ratio as u32).square()
+	}
+}
+
+#[frame_support::pallet]
+pub mod pallet {
+	use frame_support::{
+		pallet_prelude::*,
+		traits::KeyOwnerProofSystem,
+		weights::Pays,
+	};
+	use frame_system::{
+		offchain::{SendTransactionTypes, SubmitTransaction},
+		pallet_prelude::*,
+	};
+	use sp_aura_equivocation::{AuthorityId, EquivocationProof, KEY_TYPE, Slot, check_equivocation_proof};
+	use sp_runtime::KeyTypeId;
+	use sp_session::{GetSessionNumber, GetValidatorCount};
+	use sp_staking::{SessionIndex, offence::ReportOffence};
+	use sp_std::{boxed::Box, vec::Vec};
+	use super::AuraEquivocationOffence;
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config + SendTransactionTypes<Call<Self>> {
+		/// Because this pallet emits events, it depends on the runtime's definition of an event.
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		/// Proof that an authority key belonged to an authority of some session.
+		type KeyOwnerProof: Parameter + GetSessionNumber + GetValidatorCount;
+
+		/// Identification of the authority owning a key.
+		type KeyOwnerIdentification: Parameter;
+
+		/// Checks the proofs of the ownership of authority keys.
+		type KeyOwnerProofSystem: KeyOwnerProofSystem<
+			(KeyTypeId, AuthorityId),
+			Proof = Self::KeyOwnerProof,
+			IdentificationTuple = Self::KeyOwnerIdentification,
+		>;
+
+		/// Where the equivocations are reported, as offences.
+		type ReportOffence: ReportOffence<
+			Self::AccountId,
+			Self::KeyOwnerIdentification,
+			AuraEquivocationOffence<Self::KeyOwnerIdentification>,
+		>;
+
+		/// Number of blocks reports stay valid in the transaction pool.
+		#[pallet::constant]
+		type ReportLongevity: Get<u64>;
+
+		/// The slot of the current block.
+		type CurrentSlot: Get<Slot>;
+
+		/// The index of the current session.
+		type CurrentSession: Get<SessionIndex>;
+
+		/// Number of sessions, the current one included, whose first slot is kept. Equivocations
+		/// in older sessions can't be reported anymore.
+		#[pallet::constant]
+		type SessionsKept: Get<SessionIndex>;
+	}
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T>(_);
+
+	/// The first slot of the kept sessions, noted at the end of their first block.
+	#[pallet::storage]
+	pub type SessionStartSlots<T: Config> = StorageMap<_, Twox64Concat, SessionIndex, Slot>;
+
+	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		/// An authority was reported for sealing several blocks for a slot. [authority, slot]
+		EquivocationReported(AuthorityId, u64),
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// The headers aren't two different headers sealed by the offender for the slot.
+		InvalidEquivocationProof,
+		/// The offender key isn't proven to belong to an authority of the session of the slot.
+		InvalidKeyOwnershipProof,
+		/// The equivocation was already reported.
+		DuplicateOffenceReport,
+	}
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		/// Reserve the weight of `on_finalize`.
+		fn on_initialize(_n: BlockNumberFor<T>) -> Weight {
+			T::DbWeight::get().reads_writes(1, 2)
+		}
+
+		/// Note the first slot of the session if the block is its first one, once the session and
+		/// the slot of the block are both known, and forget the session no longer kept.
+		fn on_finalize(_n: BlockNumberFor<T>) {
+			let session = T::CurrentSession::get();
+			if !SessionStartSlots::<T>::contains_key(session) {
+				SessionStartSlots::<T>::insert(session, T::CurrentSlot::get());
+				if let Some(pruned) = session.checked_sub(T::SessionsKept::get()) {
+					SessionStartSlots::<T>::remove(pruned);
+				}
+			}
+		}
+	}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Report an authority for sealing the two headers of `equivocation_proof` for the same
+		/// slot. `key_owner_proof` proves the offender key belonged to an authority then.
+		///
+		/// Only valid when included by a block author, reporting is free.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(4 + T::SessionsKept::get() as Weight, 3))]
+		pub fn report_equivocation_unsigned(
+			origin: OriginFor<T>,
+			equivocation_proof: Box<EquivocationProof<T::Header>>,
+			key_owner_proof: T::KeyOwnerProof,
+		) -> DispatchResultWithPostInfo {
+			ensure_none(origin)?;
+
+			let offence = Self::check_report(&equivocation_proof, key_owner_proof)?;
+			T::ReportOffence::report_offence(Vec::new(), offence)
+				.map_err(|_| Error::<T>::DuplicateOffenceReport)?;
+
+			let EquivocationProof { offender, slot, .. } = *equivocation_proof;
+			Self::deposit_event(Event::EquivocationReported(offender, *slot));
+			Ok(Pays::No.into())
+		}
+	}
+
+	#[pallet::validate_unsigned]
+	impl<T: Config> ValidateUnsigned for Pallet<T> {
+		type Call = Call<T>;
+
+		fn validate_unsigned(source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+			if let Call::report_equivocation_unsigned(equivocation_proof, key_owner_proof) = call {
+				match source {
+					TransactionSource::Local | TransactionSource::InBlock => {},
+					_ => return InvalidTransaction::Call.into(),
+				}
+
+				Self::check_report(equivocation_proof, key_owner_proof.clone()).map_err(|e| match e {
+					Error::<T>::DuplicateOffenceReport => InvalidTransaction::Stale,
+					_ => InvalidTransaction::BadProof,
+				})?;
+
+				ValidTransaction::with_tag_prefix("AuraEquivocation")
+					.priority(TransactionPriority::max_value())
+					.and_provides((&equivocation_proof.offender, *equivocation_proof.slot))
+					.longevity(T::ReportLongevity::get())
+					.propagate(false)
+					.build()
+			} else {
+				InvalidTransaction::Call.into()
+			}
+		}
+	}
+
+	impl<T: Config> Pallet<T> {
+		/// Submit the report of an equivocation through an unsigned extrinsic.
+		pub fn submit_unsigned_equivocation_report(
+			equivocation_proof: EquivocationProof<T::Header>,
+			key_owner_proof: T::KeyOwnerProof,
+		) -> Result<(), ()> {
+			let call = Call::report_equivocation_unsigned(Box::new(equivocation_proof), key_owner_proof);
+			SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into())
+		}
+
+		/// The kept session `slot` is in, if any.
+		fn session_of(slot: Slot) -> Option<SessionIndex> {
+			let mut session = T::CurrentSession::get();
+			loop {
+				match SessionStartSlots::<T>::get(session) {
+					Some(start) if start <= slot => return Some(session),
+					// the first slot of the current session is only noted at the end of its first
+					// block.
+					None if session != T::CurrentSession::get() => return None,
+					_ => session = session.checked_sub(1)?,
+				}
+			}
+		}
+
+		/// The offence `equivocation_proof` shows, if it is valid and wasn't reported yet.
+		fn check_report(
+			equivocation_proof: &EquivocationProof<T::Header>,
+			key_owner_proof: T::KeyOwnerProof,
+		) -> Result<AuraEquivocationOffence<T::KeyOwnerIdentification>, Error<T>> {
+			ensure!(check_equivocation_proof(equivocation_proof), Error::<T>::InvalidEquivocationProof);
+
+			// the proof must be about the session of the slot, the offence is reported in it.
+			let session_index = key_owner_proof.session();
+			ensure!(
+				Self::session_of(equivocation_proof.slot) == Some(session_index),
+				Error::<T>::InvalidKeyOwnershipProof,
+			);
+			let validator_set_count = key_owner_proof.validator_count();
+			let offender = T::KeyOwnerProofSystem::check_proof(
+				(KEY_TYPE, equivocation_proof.offender.clone()),
+				key_owner_proof,
+			).ok_or(Error::<T>::InvalidKeyOwnershipProof)?;
+
+			ensure!(
+				!T::ReportOffence::is_known_offence(&[offender.clone()], &equivocation_proof.slot),
+				Error::<T>::DuplicateOffenceReport,
+			);
+
+			Ok(AuraEquivocationOffence {
+				slot: equivocation_proof.slot,
+				session_index,
+				validator_set_count,
+				offender,
+			})
+		}
+	}
+}
@@ -0,0 +1,223 @@
+use crate::{self as pallet_aura_equivocation, AuraEquivocationOffence};
+use codec::{Decode, Encode};
+use sp_core::{H256, Pair};
+use frame_support::{parameter_types, traits::{Get, Hooks, KeyOwnerProofSystem}};
+use sp_aura_equivocation::{AuthorityId, EquivocationProof, Slot};
+use sp_consensus_aura::{digests::CompatibleDigestItem, sr25519::{AuthorityPair, AuthoritySignature}};
+use sp_runtime::{
+	KeyTypeId, RuntimeDebug,
+	generic::DigestItem,
+	traits::{BlakeTwo256, Header as HeaderT, IdentityLookup}, testing::{Header, TestXt},
+};
+use sp_staking::{SessionIndex, offence::{OffenceError, ReportOffence}};
+use std::cell::RefCell;
+use frame_system as system;
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+// Configure a mock runtime to test the pallet.
+frame_support::construct_runtime!(
+	pub enum Test where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic,
+	{
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		AuraEquivocation: pallet_aura_equivocation::{Pallet, Call, Event<T>, ValidateUnsigned},
+	}
+);
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const SS58Prefix: u8 = 42;
+}
+
+impl system::Config for Test {
+	type BaseCallFilter = ();
+	type BlockWeights = ();
+	type BlockLength = ();
+	type DbWeight = ();
+	type Origin = Origin;
+	type Call = Call;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type Version = ();
+	type PalletInfo = PalletInfo;
+	type AccountData = ();
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+	type SS58Prefix = SS58Prefix;
+	type OnSetCode = ();
+}
+
+impl<C> system::offchain::SendTransactionTypes<C> for Test where Call: From<C> {
+	type OverarchingCall = Call;
+	type Extrinsic = TestXt<Call, ()>;
+}
+
+/// Claims that the authority derived from `//<owner>` belongs to `owner` in session 1.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct MockKeyOwnerProof {
+	pub owner: u64,
+}
+
+impl sp_session::GetSessionNumber for MockKeyOwnerProof {
+	fn session(&self) -> SessionIndex {
+		1
+	}
+}
+
+impl sp_session::GetValidatorCount for MockKeyOwnerProof {
+	fn validator_count(&self) -> u32 {
+		4
+	}
+}
+
+pub struct MockKeyOwnerProofSystem;
+
+impl KeyOwnerProofSystem<(KeyTypeId, AuthorityId)> for MockKeyOwnerProofSystem {
+	type Proof = MockKeyOwnerProof;
+	type IdentificationTuple = u64;
+
+	fn prove(_key: (KeyTypeId, AuthorityId)) -> Option<Self::Proof> {
+		None
+	}
+
+	fn check_proof((_, key): (KeyTypeId, AuthorityId), proof: Self::Proof) -> Option<u64> {
+		(key == authority(proof.owner).public()).then(|| proof.owner)
+	}
+}
+
+thread_local! {
+	/// The offences reported so far.
+	pub static OFFENCES: RefCell<Vec<AuraEquivocationOffence<u64>>> = RefCell::new(Vec::new());
+}
+
+pub struct MockReportOffence;
+
+impl ReportOffence<u64, u64, AuraEquivocationOffence<u64>> for MockReportOffence {
+	fn report_offence(_reporters: Vec<u64>, offence: AuraEquivocationOffence<u64>) -> Result<(), OffenceError> {
+		if Self::is_known_offence(&[offence.offender], &offence.slot) {
+			return Err(OffenceError::DuplicateReport)
+		}
+		OFFENCES.with(|offences| offences.borrow_mut().push(offence));
+		Ok(())
+	}
+
+	fn is_known_offence(offenders: &[u64], slot: &Slot) -> bool {
+		OFFENCES.with(|offences| offences.borrow().iter().any(|offence| {
+			offenders.contains(&offence.offender) && offence.slot == *slot
+		}))
+	}
+}
+
+thread_local! {
+	/// The current session, and the slot of the current block.
+	pub static SESSION: RefCell<(SessionIndex, Slot)> = RefCell::new((0, Slot::from(0)));
+}
+
+/// The slot set with `start_session`.
+pub struct MockCurrentSlot;
+
+impl Get<Slot> for MockCurrentSlot {
+	fn get() -> Slot {
+		SESSION.with(|session| session.borrow().1)
+	}
+}
+
+/// The session set with `start_session`.
+pub struct MockCurrentSession;
+
+impl Get<SessionIndex> for MockCurrentSession {
+	fn get() -> SessionIndex {
+		SESSION.with(|session| session.borrow().0)
+	}
+}
+
+parameter_types! {
+	pub const ReportLongevity: u64 = 10;
+	pub const SessionsKept: SessionIndex = 3;
+}
+
+impl pallet_aura_equivocation::Config for Test {
+	type Event = Event;
+	type KeyOwnerProof = MockKeyOwnerProof;
+	type KeyOwnerIdentification = u64;
+	type KeyOwnerProofSystem = MockKeyOwnerProofSystem;
+	type ReportOffence = MockReportOffence;
+	type ReportLongevity = ReportLongevity;
+	type CurrentSlot = MockCurrentSlot;
+	type CurrentSession = MockCurrentSession;
+	type SessionsKept = SessionsKept;
+}
+
+/// Authorities.
+pub const ALICE: u64 = 1;
+pub const BOB: u64 = 2;
+
+// Build genesis storage according to the mock runtime, session 0 starting at slot 1 and session 1
+// at slot 5.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	OFFENCES.with(|offences| offences.borrow_mut().clear());
+	let storage = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+
+	let mut ext: sp_io::TestExternalities = storage.into();
+	ext.execute_with(|| {
+		System::set_block_number(1);
+		start_session(0, 1);
+		start_session(1, 5);
+	});
+	ext
+}
+
+/// Finalize a block for `slot`, the first one of `session`.
+pub fn start_session(session: SessionIndex, slot: u64) {
+	SESSION.with(|current| *current.borrow_mut() = (session, Slot::from(slot)));
+	AuraEquivocation::on_finalize(System::block_number());
+}
+
+/// The authority key of `owner`.
+pub fn authority(owner: u64) -> AuthorityPair {
+	AuthorityPair::from_string(&format!("//{}", owner), None).unwrap()
+}
+
+/// A header for `slot` sealed by `owner`, made unique by `state_root`.
+pub fn sealed_header(owner: u64, slot: u64, state_root: u8) -> Header {
+	let mut header = Header::new(
+		1,
+		Default::default(),
+		H256::repeat_byte(state_root),
+		Default::default(),
+		Default::default(),
+	);
+	header.digest_mut().push(
+		<DigestItem<H256> as CompatibleDigestItem<AuthoritySignature>>::aura_pre_digest(Slot::from(slot)),
+	);
+	let signature = authority(owner).sign(header.hash().as_ref());
+	header.digest_mut().push(DigestItem::aura_seal(signature));
+	header
+}
+
+/// Proof that `offender` sealed both headers for `slot`.
+pub fn equivocation_proof(
+	offender: u64,
+	slot: u64,
+	first_header: Header,
+	second_header: Header,
+) -> Box<EquivocationProof<Header>> {
+	Box::new(EquivocationProof {
+		offender: authority(offender).public(),
+		slot: Slot::from(slot),
+		first_header,
+		second_header,
+	})
+}
@@ -0,0 +1,148 @@
+use crate::{AuraEquivocationOffence, Call, Error, SessionStartSlots, mock::*};
+use frame_support::{assert_noop, assert_ok, unsigned::ValidateUnsigned, weights::Pays};
+use sp_aura_equivocation::Slot;
+use sp_core::Pair;
+use sp_runtime::transaction_validity::{InvalidTransaction, TransactionSource};
+
+#[test]
+fn equivocations_are_reported_as_offences() {
+	new_test_ext().execute_with(|| {
+		let proof = equivocation_proof(ALICE, 7, sealed_header(ALICE, 7, 1), sealed_header(ALICE, 7, 2));
+
+		let post_info = AuraEquivocation::report_equivocation_unsigned(
+			Origin::none(),
+			proof,
+			MockKeyOwnerProof { owner: ALICE },
+		).unwrap();
+
+		assert_eq!(post_info.pays_fee, Pays::No);
+		assert_eq!(
+			OFFENCES.with(|offences| offences.borrow().clone()),
+			vec![AuraEquivocationOffence {
+				slot: Slot::from(7),
+				session_index: 1,
+				validator_set_count: 4,
+				offender: ALICE,
+			}],
+		);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_aura_equivocation(crate::Event::EquivocationReported(authority(ALICE).public(), 7)),
+		);
+	});
+}
+
+#[test]
+fn equivocation_proofs_are_checked() {
+	new_test_ext().execute_with(|| {
+		let report = |proof| AuraEquivocation::report_equivocation_unsigned(
+			Origin::none(),
+			proof,
+			MockKeyOwnerProof { owner: ALICE },
+		);
+
+		// the same header twice.
+		assert_noop!(
+			report(equivocation_proof(ALICE, 7, sealed_header(ALICE, 7, 1), sealed_header(ALICE, 7, 1))),
+			Error::<Test>::InvalidEquivocationProof,
+		);
+		// headers for different slots.
+		assert_noop!(
+			report(equivocation_proof(ALICE, 7, sealed_header(ALICE, 7, 1), sealed_header(ALICE, 8, 2))),
+			Error::<Test>::InvalidEquivocationProof,
+		);
+		// a header sealed by someone else.
+		assert_noop!(
+			report(equivocation_proof(ALICE, 7, sealed_header(ALICE, 7, 1), sealed_header(BOB, 7, 2))),
+			Error::<Test>::InvalidEquivocationProof,
+		);
+	});
+}
+
+#[test]
+fn key_ownership_proofs_are_checked() {
+	new_test_ext().execute_with(|| {
+		let proof = equivocation_proof(ALICE, 7, sealed_header(ALICE, 7, 1), sealed_header(ALICE, 7, 2));
+
+		assert_noop!(
+			AuraEquivocation::report_equivocation_unsigned(Origin::none(), proof, MockKeyOwnerProof { owner: BOB }),
+			Error::<Test>::InvalidKeyOwnershipProof,
+		);
+	});
+}
+
+#[test]
+fn key_ownership_proofs_are_for_the_session_of_the_slot() {
+	new_test_ext().execute_with(|| {
+		let report = |slot| AuraEquivocation::report_equivocation_unsigned(
+			Origin::none(),
+			equivocation_proof(ALICE, slot, sealed_header(ALICE, slot, 1), sealed_header(ALICE, slot, 2)),
+			MockKeyOwnerProof { owner: ALICE },
+		);
+		start_session(2, 10);
+
+		// the proofs are for session 1, which goes from slot 5 to 9.
+		assert_noop!(report(4), Error::<Test>::InvalidKeyOwnershipProof);
+		assert_noop!(report(10), Error::<Test>::InvalidKeyOwnershipProof);
+		assert_ok!(report(9));
+
+		// session 1 is no longer kept once session 4 starts.
+		start_session(3, 15);
+		start_session(4, 20);
+		assert!(SessionStartSlots::<Test>::get(1).is_none());
+		assert_noop!(report(5), Error::<Test>::InvalidKeyOwnershipProof);
+	});
+}
+
+#[test]
+fn equivocations_are_reported_once() {
+	new_test_ext().execute_with(|| {
+		let proof = equivocation_proof(ALICE, 7, sealed_header(ALICE, 7, 1), sealed_header(ALICE, 7, 2));
+		assert_ok!(AuraEquivocation::report_equivocation_unsigned(
+			Origin::none(),
+			proof,
+			MockKeyOwnerProof { owner: ALICE },
+		));
+
+		// other headers for the same slot.
+		let proof = equivocation_proof(ALICE, 7, sealed_header(ALICE, 7, 3), sealed_header(ALICE, 7, 4));
+		assert_noop!(
+			AuraEquivocation::report_equivocation_unsigned(
+				Origin::none(),
+				proof.clone(),
+				MockKeyOwnerProof { owner: ALICE },
+			),
+			Error::<Test>::DuplicateOffenceReport,
+		);
+		let call = Call::report_equivocation_unsigned(proof, MockKeyOwnerProof { owner: ALICE });
+		assert_eq!(
+			AuraEquivocation::validate_unsigned(TransactionSource::Local, &call),
+			InvalidTransaction::Stale.into(),
+		);
+	});
+}
+
+#[test]
+fn reports_only_come_from_the_local_node() {
+	new_test_ext().execute_with(|| {
+		let proof = equivocation_proof(ALICE, 7, sealed_header(ALICE, 7, 1), sealed_header(ALICE, 7, 2));
+		let call = Call::report_equivocation_unsigned(proof, MockKeyOwnerProof { owner: ALICE });
+
+		assert_eq!(
+			AuraEquivocation::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::Call.into(),
+		);
+		let valid = AuraEquivocation::validate_unsigned(TransactionSource::Local, &call).unwrap();
+		assert!(!valid.propagate);
+		assert_eq!(valid.longevity, 10);
+
+		let bad_owner = Call::report_equivocation_unsigned(
+			equivocation_proof(ALICE, 7, sealed_header(ALICE, 7, 1), sealed_header(ALICE, 7, 2)),
+			MockKeyOwnerProof { owner: BOB },
+		);
+		assert_eq!(
+			AuraEquivocation::validate_unsigned(TransactionSource::Local, &bad_owner),
+			InvalidTransaction::BadProof.into(),
+		);
+	});
+}
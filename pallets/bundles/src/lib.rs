@@ -0,0 +1,308 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Orders the transaction bundles of executors.
+//!
+//! Users don't send their transactions to consensus nodes directly anymore. Executors collect
+//! them into signed bundles, which consensus nodes include in blocks as a single unsigned
+//! extrinsic, checking nothing but the executor who signed the bundle. Applying the bundle in the
+//! consensus state only records that it was included: the transactions inside are neither decoded
+//! nor applied by consensus nodes.
+//!
+//! Executors keep an execution state of their own, which goes through the extrinsics of the
+//! blocks like the consensus state does, and through the transactions of their bundles too. Each
+//! transaction is a step of its own, right after the extrinsic submitting the bundle, see
+//! [`sp_executor::ExecutionLayout`], applied with [`Pallet::apply_bundled_extrinsic`] through the
+//! `BundleApi`. Everything a step needs is worked out again from the extrinsic and the state, so
+//! that any step can be re-executed on its own to check a fraud proof.
+//!
+//! The transactions of a bundle are not applied in the order the executor put them in, which would
+//! let it front-run the others. Their senders are [shuffled](shuffle_by_sender) with the randomness
+//! of the block instead, so that anyone can check the order they were applied in, while the
+//! transactions of each sender are still applied in nonce order.
+//!
+//! The seed is only as unpredictable as the randomness source: with `RandomnessCollectiveFlip` it
+//! is derived from the hashes of the previous blocks, which the executor knows when it builds the
+//! bundle. It can then work the order out in advance, and try bundles until one suits it, so the
+//! shuffle only keeps it from picking the order outright.
+//!
+//! Consensus nodes order bundles without looking into them, so they have to be able to show that
+//! the bundles of a block can be retrieved by anyone who wants to execute or challenge it. At the
+//! end of each block the bundles submitted in it, applied or not, are erasure coded and the block
+//! commits to the chunks in its header digest, see [`sp_executor::availability`].
+
+use codec::Encode;
+use frame_support::weights::Weight;
+use sp_core::hashing::blake2_256;
+use sp_runtime::transaction_validity::TransactionValidityError;
+use sp_std::{collections::btree_map::BTreeMap, vec, vec::Vec};
+
+pub use pallet::*;
+
+/// Weight per byte of the block of erasure coding its bundles and committing to their chunks, see
+/// [`sp_executor::availability::commit_bundles`].
+pub const ERASURE_CODING_WEIGHT_PER_BYTE: Weight = 5_000;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+/// Applies the transactions of bundles.
+pub trait ApplyBundledExtrinsic {
+	/// Decode, check and apply the encoded `extrinsic`, returning the weight it consumed.
+	///
+	/// Extrinsics that are invalid or would consume more than `remaining_weight` are skipped
+	/// without touching the state. A failed dispatch still counts as applied.
+	fn apply_bundled_extrinsic(
+		extrinsic: &[u8],
+		remaining_weight: Weight,
+	) -> Result<Weight, TransactionValidityError>;
+
+	/// The encoded account that signed the encoded `extrinsic` and its nonce, or `None` if it is
+	/// unsigned or can't be decoded.
+	fn sender_and_nonce(extrinsic: &[u8]) -> Option<(Vec<u8>, u64)>;
+}
+
+/// Shuffle `items` deterministically out of `seed`.
+///
+/// A Fisher-Yates shuffle, drawing the item to put at position `i` from the hash of the seed and
+/// `i`.
+pub fn shuffle<Item>(items: &mut [Item], seed: &[u8]) {
+	for i in (1..items.len()).rev() {
+		let draw = blake2_256(&(seed, i as u32).encode());
+		let mut bytes = [0u8; 8];
+		bytes.copy_from_slice(&draw[..8]);
+		let j = u64::from_le_bytes(bytes) % (i as u64 + 1);
+		items.swap(i, j as usize);
+	}
+}
+
+/// Shuffle `extrinsics` deterministically out of `seed`, keeping the ones of each sender together
+/// and in nonce order.
+///
+/// Shuffling the transactions one by one would put the later transactions of a sender before its
+/// earlier ones, which then fail on their nonce. They are grouped by sender instead, the groups are
+/// [shuffled](shuffle) and sorted by nonce. Extrinsics without a sender are groups of their own.
+pub fn shuffle_by_sender<Extrinsic>(
+	extrinsics: Vec<Extrinsic>,
+	seed: &[u8],
+	sender_and_nonce: impl Fn(&Extrinsic) -> Option<(Vec<u8>, u64)>,
+) -> Vec<Extrinsic> {
+	let mut groups = Vec::<Vec<(u64, Extrinsic)>>::new();
+	let mut group_of_sender = BTreeMap::new();
+	for extrinsic in extrinsics {
+		match sender_and_nonce(&extrinsic) {
+			Some((sender, nonce)) => {
+				let group = *group_of_sender.entry(sender).or_insert_with(|| {
+					groups.push(Vec::new());
+					groups.len() - 1
+				});
+				groups[group].push((nonce, extrinsic));
+			},
+			None => groups.push(vec![(0, extrinsic)]),
+		}
+	}
+
+	for group in groups.iter_mut() {
+		group.sort_by_key(|(nonce, _)| *nonce);
+	}
+	shuffle(&mut groups, seed);
+
+	groups.into_iter().flatten().map(|(_, extrinsic)| extrinsic).collect()
+}
+
+#[frame_support::pallet]
+pub mod pallet {
+	use codec::Encode;
+	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, traits::Randomness};
+	use frame_system::{
+		offchain::{SendTransactionTypes, SubmitTransaction},
+		pallet_prelude::*,
+	};
+	use sp_core::H256;
+	use sp_executor::{ExecutorId, ExecutorRegistry, ExtractBundle, SignedOpaqueBundle, availability};
+	use sp_std::vec::Vec;
+	use super::{ApplyBundledExtrinsic, ERASURE_CODING_WEIGHT_PER_BYTE, shuffle_by_sender};
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config + SendTransactionTypes<Call<Self>> {
+		/// Because this pallet emits events, it depends on the runtime's definition of an event.
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		/// Weight available to the transactions of a single bundle.
+		#[pallet::constant]
+		type MaxBundleWeight: Get<Weight>;
+
+		/// Priority of the unsigned transactions submitting bundles.
+		#[pallet::constant]
+		type UnsignedPriority: Get<TransactionPriority>;
+
+		/// Applies the transactions of bundles.
+		type ApplyExtrinsic: ApplyBundledExtrinsic;
+
+		/// The executors allowed to submit bundles.
+		type ExecutorRegistry: ExecutorRegistry;
+
+		/// Source of the seed the transactions of bundles are shuffled with.
+		///
+		/// Executors can only be kept from ordering their bundles at will if they don't know the
+		/// seed when they build them, which is not the case of `RandomnessCollectiveFlip`.
+		type Randomness: Randomness<Self::Hash, Self::BlockNumber>;
+
+		/// Finds the bundles among the extrinsics of the block, for the availability commitment,
+		/// and the bundle whose transactions executors apply.
+		type ExtractBundle: ExtractBundle;
+	}
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T>(_);
+
+	/// Weight consumed so far by the transactions of the bundle being applied, in the execution
+	/// state. Reset with the first transaction of every bundle, and cleared at the end of the block.
+	#[pallet::storage]
+	pub type BundleWeight<T: Config> = StorageValue<_, Weight, ValueQuery>;
+
+	#[pallet::event]
+	#[pallet::metadata(ExecutorId = "ExecutorId")]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		/// The bundle of an executor was included, its transactions are left for executors to
+		/// apply. [executor, bundle_hash, transactions]
+		BundleIncluded(ExecutorId, H256, u32),
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// The bundle is not signed by the executor it claims to come from.
+		BadSignature,
+		/// The bundle is signed by an executor that is not registered, or is leaving.
+		UnknownExecutor,
+	}
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		/// Reserve the weight of `on_finalize`, for a block full of bundles as their size isn't
+		/// known yet.
+		fn on_initialize(_n: BlockNumberFor<T>) -> Weight {
+			let max_len = *T::BlockLength::get().max.get(DispatchClass::Normal);
+			// the extrinsic count, read back in `on_finalize` along with the extrinsics, and the
+			// weight of the last bundle applied.
+			T::DbWeight::get().reads_writes(1, 1)
+				.saturating_add((max_len as Weight).saturating_mul(ERASURE_CODING_WEIGHT_PER_BYTE))
+		}
+
+		/// Commit to the erasure coded bundles of the block in its header digest, and forget the
+		/// weight of the last bundle applied.
+		fn on_finalize(_n: BlockNumberFor<T>) {
+			BundleWeight::<T>::kill();
+
+			let bundles = (0..frame_system::Pallet::<T>::extrinsic_count())
+				.filter_map(|index| T::ExtractBundle::extract_bundle(
+					&frame_system::Pallet::<T>::extrinsic_data(index),
+				))
+				.collect::<Vec<_>>();
+
+			if let Some((commitment, _)) = availability::commit_bundles(&bundles) {
+				frame_system::Pallet::<T>::deposit_log(commitment.to_digest_item());
+			}
+		}
+	}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Include a bundle in the block, leaving its transactions for executors to apply.
+		///
+		/// Bundles are submitted through unsigned extrinsics, they carry the signature of a
+		/// registered executor instead. The transactions inside pay their own fees, when they are
+		/// applied to the execution state.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads(1))]
+		pub fn submit_bundle(
+			origin: OriginFor<T>,
+			signed_bundle: SignedOpaqueBundle,
+		) -> DispatchResult {
+			ensure_none(origin)?;
+
+			Self::check_bundle(&signed_bundle)?;
+
+			Self::deposit_event(Event::BundleIncluded(
+				signed_bundle.signer,
+				signed_bundle.bundle.hash(),
+				signed_bundle.bundle.extrinsics.len() as u32,
+			));
+			Ok(())
+		}
+	}
+
+	#[pallet::validate_unsigned]
+	impl<T: Config> ValidateUnsigned for Pallet<T> {
+		type Call = Call<T>;
+
+		fn validate_unsigned(_source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+			if let Call::submit_bundle(signed_bundle) = call {
+				// the contents are left for executors to deal with.
+				Self::check_bundle(signed_bundle).map_err(|e| match e {
+					Error::<T>::UnknownExecutor => InvalidTransaction::BadSigner,
+					_ => InvalidTransaction::BadProof,
+				})?;
+
+				ValidTransaction::with_tag_prefix("Bundle")
+					.priority(T::UnsignedPriority::get())
+					.and_provides(signed_bundle.bundle.hash())
+					.propagate(true)
+					.build()
+			} else {
+				InvalidTransaction::Call.into()
+			}
+		}
+	}
+
+	impl<T: Config> Pallet<T> {
+		/// Submit a bundle through an unsigned extrinsic.
+		pub fn submit_bundle_unsigned(signed_bundle: SignedOpaqueBundle) -> Result<(), ()> {
+			let call = Call::submit_bundle(signed_bundle);
+			SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into())
+		}
+
+		/// Apply the transaction at `position` of the bundle the encoded `extrinsic` submits, in
+		/// the order given by shuffling the senders of the bundle with the random seed of the
+		/// block, returning the weight it consumed.
+		///
+		/// Only called by executors, on the execution state, right after applying `extrinsic`.
+		/// The bundle is checked again and shuffled for every transaction, so that each of them
+		/// can be applied on its own. Transactions that are invalid, or would take the bundle
+		/// over `MaxBundleWeight`, are skipped without touching the state.
+		pub fn apply_bundled_extrinsic(
+			extrinsic: &[u8],
+			position: u32,
+		) -> Result<Weight, TransactionValidityError> {
+			if position == 0 {
+				BundleWeight::<T>::kill();
+			}
+
+			let signed_bundle = T::ExtractBundle::extract_bundle(extrinsic)
+				.ok_or(InvalidTransaction::Call)?;
+			Self::check_bundle(&signed_bundle).map_err(|_| InvalidTransaction::BadProof)?;
+
+			let extrinsics = shuffle_by_sender(
+				signed_bundle.bundle.extrinsics.iter().map(Encode::encode).collect(),
+				T::Randomness::random_seed().0.as_ref(),
+				|extrinsic| T::ApplyExtrinsic::sender_and_nonce(extrinsic),
+			);
+			let extrinsic = extrinsics.get(position as usize).ok_or(InvalidTransaction::Call)?;
+
+			let remaining_weight = T::MaxBundleWeight::get().saturating_sub(BundleWeight::<T>::get());
+			let weight = T::ApplyExtrinsic::apply_bundled_extrinsic(extrinsic, remaining_weight)?;
+			BundleWeight::<T>::mutate(|used| *used = used.saturating_add(weight));
+			Ok(weight)
+		}
+
+		/// Check that the bundle is signed by the registered executor it claims to come from.
+		fn check_bundle(signed_bundle: &SignedOpaqueBundle) -> Result<(), Error<T>> {
+			ensure!(T::ExecutorRegistry::is_active(&signed_bundle.signer), Error::<T>::UnknownExecutor);
+			ensure!(signed_bundle.verify(), Error::<T>::BadSignature);
+			Ok(())
+		}
+	}
+}
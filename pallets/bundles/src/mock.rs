@@ -0,0 +1,196 @@
+use crate as pallet_bundles;
+use sp_core::{H256, Pair};
+use frame_support::{parameter_types, traits::Randomness, weights::Weight};
+use sp_runtime::{
+	OpaqueExtrinsic, traits::{BlakeTwo256, IdentityLookup}, testing::{Header, TestXt},
+	transaction_validity::{InvalidTransaction, TransactionPriority, TransactionValidityError},
+};
+use sp_executor::{Bundle, ExecutorId, ExecutorPair, SignedOpaqueBundle};
+use codec::{Decode, Encode};
+use std::cell::RefCell;
+use frame_system as system;
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+// Configure a mock runtime to test the pallet.
+frame_support::construct_runtime!(
+	pub enum Test where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic,
+	{
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		Bundles: pallet_bundles::{Pallet, Call, Event<T>, ValidateUnsigned},
+	}
+);
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const SS58Prefix: u8 = 42;
+}
+
+impl system::Config for Test {
+	type BaseCallFilter = ();
+	type BlockWeights = ();
+	type BlockLength = ();
+	type DbWeight = ();
+	type Origin = Origin;
+	type Call = Call;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type Version = ();
+	type PalletInfo = PalletInfo;
+	type AccountData = ();
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+	type SS58Prefix = SS58Prefix;
+	type OnSetCode = ();
+}
+
+impl<C> system::offchain::SendTransactionTypes<C> for Test where Call: From<C> {
+	type OverarchingCall = Call;
+	type Extrinsic = TestXt<Call, ()>;
+}
+
+parameter_types! {
+	pub const MaxBundleWeight: Weight = 10;
+	pub const UnsignedPriority: TransactionPriority = TransactionPriority::max_value();
+}
+
+thread_local! {
+	/// Extrinsics applied by `MockApplier`, in order.
+	pub static APPLIED: RefCell<Vec<Vec<u8>>> = RefCell::new(Vec::new());
+}
+
+/// Applies extrinsics whose first byte is their weight. Empty extrinsics are invalid, the ones
+/// of three bytes or more are signed by their second byte, with their third as nonce.
+pub struct MockApplier;
+
+impl pallet_bundles::ApplyBundledExtrinsic for MockApplier {
+	fn apply_bundled_extrinsic(
+		extrinsic: &[u8],
+		remaining_weight: Weight,
+	) -> Result<Weight, TransactionValidityError> {
+		let extrinsic = Vec::<u8>::decode(&mut &extrinsic[..]).map_err(|_| InvalidTransaction::Call)?;
+		let weight = *extrinsic.first().ok_or(InvalidTransaction::Call)? as Weight;
+		if weight > remaining_weight {
+			return Err(InvalidTransaction::ExhaustsResources.into())
+		}
+
+		APPLIED.with(|applied| applied.borrow_mut().push(extrinsic));
+		Ok(weight)
+	}
+
+	fn sender_and_nonce(extrinsic: &[u8]) -> Option<(Vec<u8>, u64)> {
+		match Vec::<u8>::decode(&mut &extrinsic[..]).ok()?[..] {
+			[_, sender, nonce, ..] => Some((vec![sender], nonce as u64)),
+			_ => None,
+		}
+	}
+}
+
+thread_local! {
+	/// Seed returned by `MockRandomness`.
+	pub static SEED: RefCell<H256> = RefCell::new(H256::zero());
+}
+
+/// Randomness returning the seed set with `set_seed`.
+pub struct MockRandomness;
+
+impl Randomness<H256, u64> for MockRandomness {
+	fn random(_subject: &[u8]) -> (H256, u64) {
+		(SEED.with(|seed| *seed.borrow()), 0)
+	}
+}
+
+/// Every executor but `Mallory` is registered.
+pub struct MockRegistry;
+
+impl sp_executor::ExecutorRegistry for MockRegistry {
+	fn is_active(executor: &ExecutorId) -> bool {
+		*executor != self::executor("Mallory").public()
+	}
+}
+
+/// Extrinsics made of a zero byte followed by an encoded bundle submit that bundle.
+pub struct MockExtractor;
+
+impl sp_executor::ExtractBundle for MockExtractor {
+	fn extract_bundle(extrinsic: &[u8]) -> Option<SignedOpaqueBundle> {
+		match extrinsic.split_first() {
+			Some((0, mut bundle)) => SignedOpaqueBundle::decode(&mut bundle).ok(),
+			_ => None,
+		}
+	}
+}
+
+impl pallet_bundles::Config for Test {
+	type Event = Event;
+	type MaxBundleWeight = MaxBundleWeight;
+	type UnsignedPriority = UnsignedPriority;
+	type ApplyExtrinsic = MockApplier;
+	type ExecutorRegistry = MockRegistry;
+	type Randomness = MockRandomness;
+	type ExtractBundle = MockExtractor;
+}
+
+// Build genesis storage according to the mock runtime.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut ext: sp_io::TestExternalities =
+		system::GenesisConfig::default().build_storage::<Test>().unwrap().into();
+	ext.execute_with(|| System::set_block_number(1));
+	ext
+}
+
+/// The executor key derived from `seed`.
+pub fn executor(seed: &str) -> ExecutorPair {
+	ExecutorPair::from_string(&format!("//{}", seed), None).unwrap()
+}
+
+/// A bundle of the given extrinsics signed by `executor`.
+pub fn signed_bundle(executor: &ExecutorPair, extrinsics: Vec<Vec<u8>>) -> SignedOpaqueBundle {
+	let bundle = Bundle {
+		extrinsics: extrinsics.iter()
+			.map(|extrinsic| OpaqueExtrinsic::decode(&mut &extrinsic.encode()[..]).unwrap())
+			.collect(),
+	};
+	let signature = executor.sign(bundle.hash().as_ref());
+
+	SignedOpaqueBundle { bundle, signer: executor.public(), signature }
+}
+
+/// Set the seed bundles are shuffled with.
+pub fn set_seed(seed: H256) {
+	SEED.with(|current| *current.borrow_mut() = seed);
+}
+
+/// The extrinsics applied so far.
+pub fn applied() -> Vec<Vec<u8>> {
+	APPLIED.with(|applied| applied.borrow().clone())
+}
+
+/// Note the given encoded extrinsics as the ones of the block, the way they are before
+/// `on_finalize`.
+pub fn note_extrinsics(extrinsics: Vec<Vec<u8>>) {
+	for extrinsic in extrinsics {
+		System::note_extrinsic(extrinsic);
+		System::note_applied_extrinsic(&Ok(().into()), Default::default());
+	}
+	System::note_finished_extrinsics();
+}
+
+/// The extrinsic submitting `bundle`, as `MockExtractor` finds it.
+pub fn bundle_extrinsic(bundle: &SignedOpaqueBundle) -> Vec<u8> {
+	let mut extrinsic = vec![0];
+	extrinsic.extend(bundle.encode());
+	extrinsic
+}
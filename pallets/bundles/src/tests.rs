@@ -0,0 +1,284 @@
+use crate::{BundleWeight, ERASURE_CODING_WEIGHT_PER_BYTE, Error, mock::*, shuffle, shuffle_by_sender};
+use codec::Encode;
+use frame_support::{
+	assert_noop, assert_ok, traits::{Get, OnFinalize, OnInitialize}, unsigned::ValidateUnsigned,
+	weights::{DispatchClass, Weight},
+};
+use sp_core::{H256, Pair};
+use sp_executor::availability::{self, ChunkWithProof, DATA_CHUNKS, TOTAL_CHUNKS};
+use sp_runtime::transaction_validity::{InvalidTransaction, TransactionSource};
+
+#[test]
+fn shuffle_is_a_deterministic_permutation() {
+	let items = (0..32u32).collect::<Vec<_>>();
+	let shuffled = |seed: &[u8]| {
+		let mut shuffled = items.clone();
+		shuffle(&mut shuffled, seed);
+		shuffled
+	};
+
+	let mut sorted = shuffled(b"seed");
+	assert_ne!(sorted, items);
+	assert_eq!(shuffled(b"seed"), sorted);
+	assert_ne!(shuffled(b"another seed"), sorted);
+
+	sorted.sort();
+	assert_eq!(sorted, items);
+}
+
+#[test]
+fn shuffle_by_sender_keeps_the_nonce_order_of_each_sender() {
+	// (sender, nonce), the unsigned extrinsics have no sender.
+	let extrinsics = vec![
+		Some((1, 1)), Some((2, 0)), None, Some((1, 0)), Some((3, 5)), Some((2, 1)), None, Some((1, 2)),
+	];
+	let shuffled = |seed: &[u8]| shuffle_by_sender(
+		extrinsics.clone(),
+		seed,
+		|extrinsic| extrinsic.map(|(sender, nonce)| (vec![sender], nonce)),
+	);
+
+	let order = shuffled(b"seed");
+	assert_eq!(shuffled(b"seed"), order);
+	assert_ne!(shuffled(b"another seed"), order);
+
+	for sender in 1..=3 {
+		let positions = order.iter()
+			.enumerate()
+			.filter(|(_, extrinsic)| matches!(extrinsic, Some((s, _)) if *s == sender))
+			.collect::<Vec<_>>();
+		// together, and in nonce order.
+		assert!(positions.windows(2).all(|pair| pair[1].0 == pair[0].0 + 1 && pair[0].1 < pair[1].1));
+	}
+
+	let mut sorted = order;
+	sorted.sort();
+	let mut expected = extrinsics;
+	expected.sort();
+	assert_eq!(sorted, expected);
+}
+
+#[test]
+fn submit_bundle_only_records_the_bundle() {
+	new_test_ext().execute_with(|| {
+		let alice = executor("Alice");
+		let bundle = signed_bundle(&alice, vec![vec![2, 1, 1], vec![3], vec![]]);
+		let bundle_hash = bundle.bundle.hash();
+
+		assert_ok!(Bundles::submit_bundle(Origin::none(), bundle));
+
+		// the transactions are left for executors, invalid ones included.
+		assert!(applied().is_empty());
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_bundles(crate::Event::BundleIncluded(alice.public(), bundle_hash, 3)),
+		);
+	});
+}
+
+#[test]
+fn bundled_extrinsics_are_applied_in_shuffled_order() {
+	new_test_ext().execute_with(|| {
+		// the ones of sender 1 come out of nonce order.
+		let extrinsics = vec![vec![2, 1, 1], vec![3], vec![1, 2, 0], vec![1, 1, 0], vec![2, 3, 0], vec![1]];
+		let extrinsic = bundle_extrinsic(&signed_bundle(&executor("Alice"), extrinsics.clone()));
+		let seed = H256::repeat_byte(7);
+		set_seed(seed);
+
+		for position in 0..extrinsics.len() as u32 {
+			assert_ok!(Bundles::apply_bundled_extrinsic(&extrinsic, position));
+		}
+
+		let expected = shuffle_by_sender(extrinsics, seed.as_ref(), |extrinsic| match extrinsic[..] {
+			[_, sender, nonce] => Some((vec![sender], nonce as u64)),
+			_ => None,
+		});
+		assert_eq!(applied(), expected);
+		let position = |extrinsic: Vec<u8>| expected.iter().position(|e| *e == extrinsic).unwrap();
+		assert_eq!(position(vec![1, 1, 0]) + 1, position(vec![2, 1, 1]));
+		assert_eq!(BundleWeight::<Test>::get(), 10);
+	});
+}
+
+#[test]
+fn invalid_extrinsics_are_skipped() {
+	new_test_ext().execute_with(|| {
+		// the empty extrinsic is invalid and the last one exceeds the weight of the bundle.
+		let extrinsic = bundle_extrinsic(&signed_bundle(&executor("Alice"), vec![vec![4], vec![], vec![5], vec![11]]));
+
+		let skipped = (0..4)
+			.filter(|position| Bundles::apply_bundled_extrinsic(&extrinsic, *position).is_err())
+			.count();
+
+		let mut applied = applied();
+		applied.sort();
+		assert_eq!(applied, vec![vec![4], vec![5]]);
+		assert_eq!(skipped, 2);
+		assert_eq!(BundleWeight::<Test>::get(), 9);
+	});
+}
+
+#[test]
+fn every_bundle_gets_its_own_weight() {
+	new_test_ext().execute_with(|| {
+		let first = bundle_extrinsic(&signed_bundle(&executor("Alice"), vec![vec![8]]));
+		let second = bundle_extrinsic(&signed_bundle(&executor("Bob"), vec![vec![9]]));
+
+		assert_eq!(Bundles::apply_bundled_extrinsic(&first, 0), Ok(8));
+		assert_eq!(Bundles::apply_bundled_extrinsic(&second, 0), Ok(9));
+		assert_eq!(applied(), vec![vec![8], vec![9]]);
+
+		Bundles::on_finalize(1);
+		assert!(!BundleWeight::<Test>::exists());
+	});
+}
+
+#[test]
+fn transactions_of_invalid_bundles_are_not_applied() {
+	new_test_ext().execute_with(|| {
+		let mut bad_signature = signed_bundle(&executor("Alice"), vec![vec![1]]);
+		bad_signature.signer = executor("Bob").public();
+		let unregistered = signed_bundle(&executor("Mallory"), vec![vec![1]]);
+
+		for bundle in vec![bad_signature, unregistered] {
+			assert_eq!(
+				Bundles::apply_bundled_extrinsic(&bundle_extrinsic(&bundle), 0),
+				Err(InvalidTransaction::BadProof.into()),
+			);
+		}
+		// not a bundle, or past its end.
+		let bundle = signed_bundle(&executor("Alice"), vec![vec![1]]);
+		assert_eq!(
+			Bundles::apply_bundled_extrinsic(&bundle.encode(), 0),
+			Err(InvalidTransaction::Call.into()),
+		);
+		assert_eq!(
+			Bundles::apply_bundled_extrinsic(&bundle_extrinsic(&bundle), 1),
+			Err(InvalidTransaction::Call.into()),
+		);
+		assert!(applied().is_empty());
+	});
+}
+
+#[test]
+fn bundle_with_bad_signature_is_rejected() {
+	new_test_ext().execute_with(|| {
+		let mut bundle = signed_bundle(&executor("Alice"), vec![vec![1]]);
+		bundle.signer = executor("Bob").public();
+
+		assert_noop!(Bundles::submit_bundle(Origin::none(), bundle), Error::<Test>::BadSignature);
+	});
+}
+
+#[test]
+fn bundle_of_unregistered_executor_is_rejected() {
+	new_test_ext().execute_with(|| {
+		let bundle = signed_bundle(&executor("Mallory"), vec![vec![1]]);
+
+		let call = crate::Call::submit_bundle(bundle.clone());
+		assert_eq!(
+			Bundles::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::BadSigner.into(),
+		);
+		assert_noop!(Bundles::submit_bundle(Origin::none(), bundle), Error::<Test>::UnknownExecutor);
+	});
+}
+
+#[test]
+fn bundles_must_be_unsigned_extrinsics() {
+	new_test_ext().execute_with(|| {
+		let bundle = signed_bundle(&executor("Alice"), vec![vec![1]]);
+
+		assert_noop!(
+			Bundles::submit_bundle(Origin::signed(1), bundle),
+			frame_support::error::BadOrigin,
+		);
+	});
+}
+
+#[test]
+fn validate_unsigned_only_checks_the_signature() {
+	new_test_ext().execute_with(|| {
+		// the contents are not looked at, invalid extrinsics included.
+		let bundle = signed_bundle(&executor("Alice"), vec![vec![], vec![42]]);
+		let call = crate::Call::submit_bundle(bundle.clone());
+		assert_ok!(Bundles::validate_unsigned(TransactionSource::External, &call));
+		assert!(applied().is_empty());
+
+		let mut bad_bundle = bundle;
+		bad_bundle.signer = executor("Bob").public();
+		let call = crate::Call::submit_bundle(bad_bundle);
+		assert_eq!(
+			Bundles::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::BadProof.into(),
+		);
+	});
+}
+
+#[test]
+fn blocks_commit_to_their_bundles() {
+	new_test_ext().execute_with(|| {
+		let first = signed_bundle(&executor("Alice"), vec![vec![1], vec![2, 3]]);
+		// bundles are committed to whether they are applied or not.
+		let second = signed_bundle(&executor("Mallory"), vec![vec![4; 100]]);
+		note_extrinsics(vec![vec![1, 2, 3], bundle_extrinsic(&first), bundle_extrinsic(&second)]);
+
+		Bundles::on_finalize(1);
+
+		let (expected, chunks) = availability::commit_bundles(&[first.clone(), second.clone()]).unwrap();
+		let commitment = availability::find_commitment(System::digest().logs()).unwrap();
+		assert_eq!(commitment, expected);
+		assert_eq!(commitment.data_len as usize, vec![first, second].encode().len());
+
+		for (index, chunk) in chunks.iter().enumerate() {
+			let proof = availability::chunk_proof(&chunks, index).unwrap();
+			let chunk_with_proof = ChunkWithProof { index: index as u32, chunk: chunk.clone(), proof };
+			assert!(chunk_with_proof.verify(&commitment));
+
+			let mut tampered = chunk_with_proof;
+			tampered.chunk[0] ^= 1;
+			assert!(!tampered.verify(&commitment));
+		}
+	});
+}
+
+#[test]
+fn erasure_coding_a_full_block_is_reserved_up_front() {
+	new_test_ext().execute_with(|| {
+		let max_len = *<Test as frame_system::Config>::BlockLength::get().max.get(DispatchClass::Normal);
+
+		assert!(Bundles::on_initialize(1) >= max_len as Weight * ERASURE_CODING_WEIGHT_PER_BYTE);
+	});
+}
+
+#[test]
+fn blocks_without_bundles_commit_to_nothing() {
+	new_test_ext().execute_with(|| {
+		note_extrinsics(vec![vec![1, 2, 3]]);
+
+		Bundles::on_finalize(1);
+
+		assert_eq!(availability::find_commitment(System::digest().logs()), None);
+	});
+}
+
+#[test]
+fn any_data_chunks_give_the_bundles_back() {
+	let bundles = vec![
+		signed_bundle(&executor("Alice"), vec![vec![1, 2, 3], vec![4; 33]]),
+		signed_bundle(&executor("Bob"), vec![vec![5; 7]]),
+	];
+	let data = bundles.encode();
+	let (commitment, chunks) = availability::commit_bundles(&bundles).unwrap();
+	assert_eq!(chunks.len(), TOTAL_CHUNKS);
+
+	let indexed = chunks.into_iter().enumerate().collect::<Vec<_>>();
+	for first in 0..=TOTAL_CHUNKS - DATA_CHUNKS {
+		let some = indexed[first..first + DATA_CHUNKS].to_vec();
+		assert_eq!(availability::reconstruct(&some, commitment.data_len as usize), Some(data.clone()));
+	}
+	let every_other = indexed.iter().step_by(2).cloned().collect::<Vec<_>>();
+	assert_eq!(availability::reconstruct(&every_other, data.len()), Some(data.clone()));
+
+	assert_eq!(availability::reconstruct(&indexed[..DATA_CHUNKS - 1], data.len()), None);
+}
@@ -0,0 +1,591 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Records the execution receipts executors submit for the blocks of the chain.
+//!
+//! Now that blocks are ordered without being executed, the receipts are the on-chain record of
+//! what executing them produced. Executors have `MaxReceiptLag` blocks to submit the receipt of a
+//! block, capped below `ChallengePeriod` so that the receipts of a block all come in while the
+//! first ones can still be challenged. Anyone can challenge a receipt with a fraud proof for
+//! `ChallengePeriod` blocks from the one it was included in. Receipts are pruned
+//! `ReceiptsPruningDepth` blocks after the block they are about, and never before the challenge
+//! period of the last receipt the block can get is over.
+//!
+//! The receipts of a block are settled when they are pruned: if those left agree on the state
+//! root, the block becomes the [confirmed head](Pallet::confirmed_head), which nodes rely on for
+//! settled state. Blocks nobody submitted a receipt for are confirmed along with the next block
+//! that has some, as its state builds on theirs. Once the parent block is confirmed, only the
+//! receipts building on its state root count. A block whose receipts still disagree, nobody having
+//! proven the wrong ones wrong in time, is left unconfirmed and recorded as the
+//! [unsettled block](Pallet::unsettled_block), the blocks after it are settled all the same.
+//!
+//! Receipts are about the execution state, which goes through the initialization of the block, its
+//! extrinsics, the transactions of its bundles and its finalization, step by step as laid out by
+//! the [execution layout](Pallet::execution_layout) of the block. Their trace starts with the
+//! parent execution state root and ends with their state root. Fraud proofs dispute a single step.
+//!
+//! Fraud proofs are free to submit, so at most `MaxFraudProofsPerBlock` of them are accepted in a
+//! block, and the cheap checks against the receipt and the extrinsics root of the disputed block
+//! come before re-executing the step.
+//!
+//! The runtime can back these parameters with `parameter_types!` storage items, so that they can
+//! be changed without upgrading the runtime.
+
+use codec::Encode;
+use frame_support::weights::Weight;
+use sp_executor::FraudProof;
+
+pub use pallet::*;
+
+/// Maximum number of blocks whose receipts are pruned in a single block. Pruning catches up
+/// over several blocks when the pruning depth shrinks.
+pub const MAX_PRUNED_PER_BLOCK: u32 = 16;
+
+/// Weight of re-executing the disputed step of a fraud proof, on top of the weight of
+/// decoding and hashing its proofs, see [`FRAUD_PROOF_WEIGHT_PER_BYTE`].
+pub const FRAUD_PROOF_BASE_WEIGHT: Weight = 100_000_000;
+
+/// Weight per byte of fraud proof. The storage proof includes the runtime code, which is by far
+/// the largest part of the proof and has to be compiled before the step is applied.
+pub const FRAUD_PROOF_WEIGHT_PER_BYTE: Weight = 1_000;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+/// Re-executes the disputed step of fraud proofs.
+pub trait FraudProofVerifier<Number, Hash> {
+	/// Check whether `fraud_proof` proves its receipt wrong.
+	fn verify(fraud_proof: &FraudProof<Number, Hash>) -> bool;
+}
+
+/// Verifies fraud proofs statelessly through the host, see [`sp_executor::executor_ext`].
+pub struct HostFraudProofVerifier;
+
+impl<Number: Encode, Hash: Encode> FraudProofVerifier<Number, Hash> for HostFraudProofVerifier {
+	fn verify(fraud_proof: &FraudProof<Number, Hash>) -> bool {
+		// a proof that can't be checked proves nothing.
+		sp_executor::executor_ext::verify_fraud_proof(&fraud_proof.encode()).unwrap_or(false)
+	}
+}
+
+#[frame_support::pallet]
+pub mod pallet {
+	use frame_support::{dispatch::DispatchResult, pallet_prelude::*};
+	use frame_system::{
+		offchain::{SendTransactionTypes, SubmitTransaction},
+		pallet_prelude::*,
+	};
+	use sp_executor::{
+		ConfirmedBlock, ExecutionLayout, ExecutionReceipt, ExecutionStep, ExecutorId,
+		ExecutorRegistry, ExtractBundle, FraudProof, OnFraudProof, SignedExecutionReceipt,
+	};
+	use super::{
+		FRAUD_PROOF_BASE_WEIGHT, FRAUD_PROOF_WEIGHT_PER_BYTE, FraudProofVerifier, MAX_PRUNED_PER_BLOCK,
+	};
+	use codec::{Decode, Encode};
+	use sp_core::H256;
+	use sp_std::vec::Vec;
+	use sp_runtime::{SaturatedConversion, traits::{CheckedSub, Hash, Header as HeaderT, One, Saturating}};
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config + SendTransactionTypes<Call<Self>> {
+		/// Because this pallet emits events, it depends on the runtime's definition of an event.
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		/// Number of blocks during which a receipt can be challenged, from the block it was
+		/// included in.
+		#[pallet::constant]
+		type ChallengePeriod: Get<Self::BlockNumber>;
+
+		/// Number of blocks during which executors can submit the receipt of a block, see
+		/// [`Pallet::receipt_lag`].
+		#[pallet::constant]
+		type MaxReceiptLag: Get<Self::BlockNumber>;
+
+		/// Number of blocks after which the receipts of a block are pruned, if their challenge
+		/// period is over, see [`Pallet::pruning_depth`].
+		#[pallet::constant]
+		type ReceiptsPruningDepth: Get<Self::BlockNumber>;
+
+		/// Priority of the unsigned transactions submitting receipts and fraud proofs.
+		#[pallet::constant]
+		type UnsignedPriority: Get<TransactionPriority>;
+
+		/// Maximum number of fraud proofs accepted in a single block.
+		#[pallet::constant]
+		type MaxFraudProofsPerBlock: Get<u32>;
+
+		/// Re-executes the disputed step of fraud proofs.
+		type FraudProofVerifier: FraudProofVerifier<Self::BlockNumber, Self::Hash>;
+
+		/// The executors allowed to submit receipts.
+		type ExecutorRegistry: ExecutorRegistry;
+
+		/// Punishes the executors whose receipts are proven wrong.
+		type OnFraudProof: OnFraudProof;
+
+		/// Finds the bundles of blocks, whose transactions are steps of their execution.
+		type ExtractBundle: ExtractBundle;
+	}
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T>(_);
+
+	/// Receipts of the blocks still inside their challenge period, by block number and executor.
+	#[pallet::storage]
+	#[pallet::getter(fn receipts)]
+	pub type Receipts<T: Config> = StorageDoubleMap<
+		_,
+		Twox64Concat,
+		T::BlockNumber,
+		Blake2_128Concat,
+		ExecutorId,
+		ExecutionReceipt<T::BlockNumber, T::Hash>,
+	>;
+
+	/// The block each receipt was included in, by block number and executor, which its
+	/// challenge period starts from.
+	#[pallet::storage]
+	pub type ReceiptInclusions<T: Config> = StorageDoubleMap<
+		_,
+		Twox64Concat,
+		T::BlockNumber,
+		Blake2_128Concat,
+		ExecutorId,
+		T::BlockNumber,
+	>;
+
+	/// The highest block with a receipt, i.e. how far executors are known to have executed the
+	/// chain.
+	#[pallet::storage]
+	#[pallet::getter(fn executed_head)]
+	pub type ExecutedHead<T: Config> = StorageValue<_, T::BlockNumber, ValueQuery>;
+
+	/// The highest block each executor submitted a receipt for, unless one of its receipts was
+	/// proven wrong since.
+	#[pallet::storage]
+	pub type ExecutorHeads<T: Config> = StorageMap<_, Blake2_128Concat, ExecutorId, T::BlockNumber>;
+
+	/// The lowest block whose receipts may not be pruned yet.
+	#[pallet::storage]
+	pub type NextToPrune<T: Config> = StorageValue<_, T::BlockNumber, ValueQuery>;
+
+	/// The highest block whose receipts were settled in agreement.
+	#[pallet::storage]
+	#[pallet::getter(fn confirmed_head)]
+	pub type ConfirmedHead<T: Config> = StorageValue<_, ConfirmedBlock<T::BlockNumber, T::Hash>>;
+
+	/// The last block whose receipts still disagreed once settled, which was left unconfirmed.
+	#[pallet::storage]
+	#[pallet::getter(fn unsettled_block)]
+	pub type UnsettledBlock<T: Config> = StorageValue<_, T::BlockNumber>;
+
+	/// Extrinsics roots of the blocks whose receipts may not be pruned yet, which the disputed
+	/// extrinsics of fraud proofs are proven against.
+	#[pallet::storage]
+	#[pallet::getter(fn extrinsics_root)]
+	pub type ExtrinsicsRoots<T: Config> = StorageMap<_, Twox64Concat, T::BlockNumber, T::Hash>;
+
+	/// Execution layouts of the blocks whose receipts may not be pruned yet, which locate the
+	/// disputed steps of fraud proofs in the traces of receipts.
+	#[pallet::storage]
+	#[pallet::getter(fn execution_layout)]
+	pub type ExecutionLayouts<T: Config> = StorageMap<_, Twox64Concat, T::BlockNumber, ExecutionLayout>;
+
+	/// Number of fraud proofs accepted in the current block.
+	#[pallet::storage]
+	pub type FraudProofCount<T: Config> = StorageValue<_, u32, ValueQuery>;
+
+	/// Hashes of the receipts proven wrong by a fraud proof, by block number and executor. Kept as
+	/// long as the valid receipts of the same block.
+	#[pallet::storage]
+	#[pallet::getter(fn invalid_receipts)]
+	pub type InvalidReceipts<T: Config> = StorageDoubleMap<
+		_,
+		Twox64Concat,
+		T::BlockNumber,
+		Blake2_128Concat,
+		ExecutorId,
+		H256,
+	>;
+
+	#[pallet::event]
+	#[pallet::metadata(T::BlockNumber = "BlockNumber", ExecutorId = "ExecutorId")]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		/// An executor submitted the receipt of a block. [block_number, executor]
+		ExecutionReceiptStored(T::BlockNumber, ExecutorId),
+		/// A fraud proof showed the receipt of an executor to be wrong. [block_number, executor]
+		ExecutionReceiptInvalidated(T::BlockNumber, ExecutorId),
+		/// The receipts of a block still disagree after their challenge period, the block is left
+		/// unconfirmed. [block_number]
+		ExecutionUnsettled(T::BlockNumber),
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// The receipt is not signed by the executor it claims to come from.
+		BadSignature,
+		/// The receipt is signed by an executor that is not registered, or is leaving.
+		UnknownExecutor,
+		/// The receipt is about a block that is not in the chain yet.
+		ReceiptFromFuture,
+		/// The block the receipt is about is more than `MaxReceiptLag` blocks behind, or its
+		/// receipts were pruned already.
+		ReceiptTooOld,
+		/// The receipt is about a block that is not part of the chain.
+		UnknownBlock,
+		/// The executor already submitted a receipt for this block.
+		DuplicateReceipt,
+		/// The trace of the receipt doesn't go through the steps of the block, or doesn't end with
+		/// the state root of the receipt.
+		MalformedTrace,
+		/// There is no receipt of the executor for the disputed block.
+		UnknownReceipt,
+		/// The pre and post state roots of the fraud proof are not the ones of the receipt.
+		TraceMismatch,
+		/// Re-executing the extrinsic produced the post state root claimed by the receipt.
+		InvalidFraudProof,
+		/// The challenge period of the disputed receipt is over.
+		ChallengePeriodOver,
+		/// The disputed extrinsic is not the one the block has at the disputed index, or the header
+		/// the block is initialized with is not the one of the block.
+		UnknownExtrinsic,
+		/// The block has no such step as the disputed one.
+		UnknownStep,
+		/// `MaxFraudProofsPerBlock` fraud proofs were already accepted in this block.
+		TooManyFraudProofs,
+	}
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			FraudProofCount::<T>::kill();
+			// the extrinsics, read back in `on_finalize`, their root and the execution layout.
+			let finalize_weight = T::DbWeight::get().reads_writes(1, 2);
+
+			// the parameters may be storage items themselves.
+			let expired = match n.checked_sub(&Self::pruning_depth()) {
+				Some(expired) => expired,
+				None => return T::DbWeight::get().reads_writes(3, 1) + finalize_weight,
+			};
+
+			let mut next = NextToPrune::<T>::get();
+			let mut pruned = 0;
+			while next <= expired && pruned < MAX_PRUNED_PER_BLOCK {
+				Self::settle(next);
+				Receipts::<T>::remove_prefix(next);
+				ReceiptInclusions::<T>::remove_prefix(next);
+				InvalidReceipts::<T>::remove_prefix(next);
+				ExtrinsicsRoots::<T>::remove(next);
+				ExecutionLayouts::<T>::remove(next);
+				next += One::one();
+				pruned += 1;
+			}
+			NextToPrune::<T>::put(next);
+
+			// NOTE: settling reads the receipts of every pruned block, which registered executors
+			// submit one of each.
+			T::DbWeight::get().reads_writes(4 + 2 * pruned as Weight, 6 * pruned as Weight + 2) + finalize_weight
+		}
+
+		/// Record the extrinsics root of the block, which the header only commits to once the
+		/// block is finalized, and the execution layout of the block.
+		fn on_finalize(n: T::BlockNumber) {
+			let extrinsics = (0..frame_system::Pallet::<T>::extrinsic_count())
+				.map(|index| frame_system::Pallet::<T>::extrinsic_data(index))
+				.collect::<Vec<_>>();
+			ExecutionLayouts::<T>::insert(n, ExecutionLayout::new(&extrinsics, T::ExtractBundle::extract_bundle));
+			ExtrinsicsRoots::<T>::insert(n, T::Hashing::ordered_trie_root(extrinsics));
+		}
+	}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Store the receipt of an executor.
+		///
+		/// Receipts are submitted through unsigned extrinsics, they carry the signature of the
+		/// executor instead.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(8, 4))]
+		pub fn submit_execution_receipt(
+			origin: OriginFor<T>,
+			signed_execution_receipt: SignedExecutionReceipt<T::BlockNumber, T::Hash>,
+		) -> DispatchResult {
+			ensure_none(origin)?;
+
+			Self::check_receipt(&signed_execution_receipt)?;
+
+			let SignedExecutionReceipt { execution_receipt, signer, .. } = signed_execution_receipt;
+			let number = execution_receipt.primary_number;
+			Receipts::<T>::insert(number, &signer, execution_receipt);
+			ReceiptInclusions::<T>::insert(number, &signer, frame_system::Pallet::<T>::block_number());
+			ExecutedHead::<T>::mutate(|head| *head = number.max(*head));
+			ExecutorHeads::<T>::mutate(&signer, |head| *head = Some(head.map_or(number, |head| head.max(number))));
+
+			Self::deposit_event(Event::ExecutionReceiptStored(number, signer));
+			Ok(())
+		}
+
+		/// Challenge the receipt of an executor with a fraud proof. If the proof holds, the
+		/// receipt is removed and flagged as invalid, and the executor punished.
+		///
+		/// Submitted through unsigned extrinsics, the proof speaks for itself. At most
+		/// `MaxFraudProofsPerBlock` of them are accepted in a block.
+		#[pallet::weight(
+			FRAUD_PROOF_BASE_WEIGHT
+				.saturating_add((fraud_proof.encoded_size() as Weight).saturating_mul(FRAUD_PROOF_WEIGHT_PER_BYTE))
+				.saturating_add(T::DbWeight::get().reads_writes(5, 4))
+		)]
+		pub fn submit_fraud_proof(
+			origin: OriginFor<T>,
+			fraud_proof: FraudProof<T::BlockNumber, T::Hash>,
+		) -> DispatchResult {
+			ensure_none(origin)?;
+
+			let receipt = Self::check_fraud_proof(&fraud_proof)?;
+			FraudProofCount::<T>::mutate(|count| *count += 1);
+
+			let FraudProof { primary_number, executor, .. } = fraud_proof;
+			Receipts::<T>::remove(primary_number, &executor);
+			ReceiptInclusions::<T>::remove(primary_number, &executor);
+			InvalidReceipts::<T>::insert(primary_number, &executor, receipt.hash());
+			ExecutorHeads::<T>::remove(&executor);
+			T::OnFraudProof::on_fraud_proof(&executor);
+
+			Self::deposit_event(Event::ExecutionReceiptInvalidated(primary_number, executor));
+			Ok(())
+		}
+	}
+
+	#[pallet::validate_unsigned]
+	impl<T: Config> ValidateUnsigned for Pallet<T> {
+		type Call = Call<T>;
+
+		fn validate_unsigned(_source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+			if let Call::submit_execution_receipt(signed_execution_receipt) = call {
+				Self::check_receipt(signed_execution_receipt).map_err(|e| match e {
+					Error::<T>::BadSignature | Error::<T>::MalformedTrace => InvalidTransaction::BadProof,
+					Error::<T>::UnknownExecutor => InvalidTransaction::BadSigner,
+					Error::<T>::ReceiptFromFuture => InvalidTransaction::Future,
+					_ => InvalidTransaction::Stale,
+				})?;
+
+				let receipt = &signed_execution_receipt.execution_receipt;
+				let longevity = receipt.primary_number
+					.saturating_add(Self::receipt_lag())
+					.saturating_sub(frame_system::Pallet::<T>::block_number());
+
+				ValidTransaction::with_tag_prefix("ExecutionReceipt")
+					.priority(T::UnsignedPriority::get())
+					.and_provides((receipt.primary_number, &signed_execution_receipt.signer))
+					.longevity(longevity.saturated_into::<u64>())
+					.propagate(true)
+					.build()
+			} else if let Call::submit_fraud_proof(fraud_proof) = call {
+				Self::check_fraud_proof(fraud_proof).map_err(|e| match e {
+					Error::<T>::InvalidFraudProof
+						| Error::<T>::UnknownExtrinsic
+						| Error::<T>::UnknownStep => InvalidTransaction::BadProof,
+					Error::<T>::TooManyFraudProofs => InvalidTransaction::ExhaustsResources,
+					_ => InvalidTransaction::Stale,
+				})?;
+
+				let longevity = Self::challenge_period_end(fraud_proof.primary_number, &fraud_proof.executor)
+					.unwrap_or_default()
+					.saturating_sub(frame_system::Pallet::<T>::block_number());
+
+				ValidTransaction::with_tag_prefix("FraudProof")
+					.priority(T::UnsignedPriority::get())
+					.and_provides((fraud_proof.primary_number, &fraud_proof.executor))
+					.longevity(longevity.saturated_into::<u64>())
+					.propagate(true)
+					.build()
+			} else {
+				InvalidTransaction::Call.into()
+			}
+		}
+	}
+
+	impl<T: Config> Pallet<T> {
+		/// Submit the receipt of an executor through an unsigned extrinsic.
+		pub fn submit_execution_receipt_unsigned(
+			signed_execution_receipt: SignedExecutionReceipt<T::BlockNumber, T::Hash>,
+		) -> Result<(), ()> {
+			let call = Call::submit_execution_receipt(signed_execution_receipt);
+			SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into())
+		}
+
+		/// The receipts of the block with the given number, by executor.
+		pub fn receipts_of(
+			number: T::BlockNumber,
+		) -> Vec<(ExecutorId, ExecutionReceipt<T::BlockNumber, T::Hash>)> {
+			Receipts::<T>::iter_prefix(number).collect()
+		}
+
+		/// The highest block a majority of the active executors with receipts submitted a receipt
+		/// for. Unlike the executed head, a single executor claiming to be ahead doesn't move it.
+		pub fn majority_executed_head() -> T::BlockNumber {
+			let mut heads = ExecutorHeads::<T>::iter()
+				.filter(|(executor, _)| T::ExecutorRegistry::is_active(executor))
+				.map(|(_, head)| head)
+				.collect::<Vec<_>>();
+			heads.sort_unstable_by(|a, b| b.cmp(a));
+			heads.get(heads.len() / 2).copied().unwrap_or_default()
+		}
+
+		/// Number of blocks during which executors can submit the receipt of a block:
+		/// `MaxReceiptLag`, capped below `ChallengePeriod`.
+		pub fn receipt_lag() -> T::BlockNumber {
+			T::MaxReceiptLag::get().min(T::ChallengePeriod::get().saturating_sub(One::one()))
+		}
+
+		/// Number of blocks after which the receipts of a block are pruned: `ReceiptsPruningDepth`,
+		/// or more until the challenge period of a receipt submitted at the last moment is over.
+		pub fn pruning_depth() -> T::BlockNumber {
+			T::ReceiptsPruningDepth::get()
+				.max(Self::receipt_lag().saturating_add(T::ChallengePeriod::get()))
+		}
+
+		/// The block the challenge period of the receipt of `executor` for the block at `number`
+		/// ends at, if there is such a receipt.
+		fn challenge_period_end(number: T::BlockNumber, executor: &ExecutorId) -> Option<T::BlockNumber> {
+			ReceiptInclusions::<T>::get(number, executor)
+				.map(|included_at| included_at.saturating_add(T::ChallengePeriod::get()))
+		}
+
+		/// Settle the receipts of the block with the given number, whose challenge periods are over.
+		///
+		/// When the parent block is confirmed, the receipts executing the block on top of another
+		/// parent state are left out.
+		fn settle(number: T::BlockNumber) {
+			let parent_state_root = ConfirmedHead::<T>::get()
+				.filter(|head| head.number.saturating_add(One::one()) == number)
+				.map(|head| head.state_root);
+			let mut receipts = Receipts::<T>::iter_prefix_values(number).filter(|receipt| {
+				parent_state_root.map_or(true, |root| receipt.trace.first() == Some(&root))
+			});
+			let first = match receipts.next() {
+				Some(first) => first,
+				// confirmed along with the next block with receipts.
+				None => return,
+			};
+			if receipts.any(|receipt| receipt.state_root != first.state_root) {
+				UnsettledBlock::<T>::put(number);
+				Self::deposit_event(Event::ExecutionUnsettled(number));
+				return
+			}
+
+			ConfirmedHead::<T>::put(ConfirmedBlock {
+				number,
+				hash: first.primary_hash,
+				state_root: first.state_root,
+			});
+		}
+
+		/// Submit a fraud proof through an unsigned extrinsic.
+		pub fn submit_fraud_proof_unsigned(
+			fraud_proof: FraudProof<T::BlockNumber, T::Hash>,
+		) -> Result<(), ()> {
+			let call = Call::submit_fraud_proof(fraud_proof);
+			SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into())
+		}
+
+		fn check_receipt(
+			signed_execution_receipt: &SignedExecutionReceipt<T::BlockNumber, T::Hash>,
+		) -> Result<(), Error<T>> {
+			let receipt = &signed_execution_receipt.execution_receipt;
+			let current_number = frame_system::Pallet::<T>::block_number();
+
+			ensure!(receipt.primary_number < current_number, Error::<T>::ReceiptFromFuture);
+			ensure!(
+				receipt.primary_number.saturating_add(Self::receipt_lag()) > current_number
+					&& receipt.primary_number >= NextToPrune::<T>::get(),
+				Error::<T>::ReceiptTooOld,
+			);
+			ensure!(
+				frame_system::Pallet::<T>::block_hash(receipt.primary_number) == receipt.primary_hash,
+				Error::<T>::UnknownBlock,
+			);
+			ensure!(
+				!Receipts::<T>::contains_key(receipt.primary_number, &signed_execution_receipt.signer)
+					&& !InvalidReceipts::<T>::contains_key(
+						receipt.primary_number,
+						&signed_execution_receipt.signer,
+					),
+				Error::<T>::DuplicateReceipt,
+			);
+			ensure!(
+				T::ExecutorRegistry::is_active(&signed_execution_receipt.signer),
+				Error::<T>::UnknownExecutor,
+			);
+			ensure!(signed_execution_receipt.verify(), Error::<T>::BadSignature);
+
+			// the layout of the block is recorded once it is finalized, i.e. before any receipt.
+			let step_count = ExecutionLayouts::<T>::get(receipt.primary_number)
+				.ok_or(Error::<T>::UnknownBlock)?
+				.step_count();
+			ensure!(
+				receipt.trace.len() == step_count as usize + 1
+					&& receipt.trace.last() == Some(&receipt.state_root),
+				Error::<T>::MalformedTrace,
+			);
+
+			Ok(())
+		}
+
+		/// Check the fraud proof against the disputed receipt, which is returned if the proof
+		/// holds.
+		///
+		/// The step is only re-executed once everything else checks out.
+		fn check_fraud_proof(
+			fraud_proof: &FraudProof<T::BlockNumber, T::Hash>,
+		) -> Result<ExecutionReceipt<T::BlockNumber, T::Hash>, Error<T>> {
+			ensure!(
+				FraudProofCount::<T>::get() < T::MaxFraudProofsPerBlock::get(),
+				Error::<T>::TooManyFraudProofs,
+			);
+
+			let receipt = Receipts::<T>::get(fraud_proof.primary_number, &fraud_proof.executor)
+				.filter(|receipt| receipt.primary_hash == fraud_proof.primary_hash)
+				.ok_or(Error::<T>::UnknownReceipt)?;
+			ensure!(
+				Self::challenge_period_end(fraud_proof.primary_number, &fraud_proof.executor)
+					.map_or(false, |end| end > frame_system::Pallet::<T>::block_number()),
+				Error::<T>::ChallengePeriodOver,
+			);
+
+			let layout = ExecutionLayouts::<T>::get(fraud_proof.primary_number)
+				.ok_or(Error::<T>::UnknownBlock)?;
+			let index = layout.index_of(&fraud_proof.step).ok_or(Error::<T>::UnknownStep)? as usize;
+			ensure!(
+				receipt.trace.get(index) == Some(&fraud_proof.pre_state_root)
+					&& receipt.trace.get(index + 1) == Some(&fraud_proof.post_state_root),
+				Error::<T>::TraceMismatch,
+			);
+
+			let known_extrinsic = match fraud_proof.step {
+				ExecutionStep::InitializeBlock => T::Header::decode(&mut &fraud_proof.extrinsic[..])
+					.map_or(false, |header| header.hash() == fraud_proof.primary_hash),
+				ExecutionStep::FinalizeBlock => true,
+				ExecutionStep::ApplyExtrinsic(index) | ExecutionStep::ApplyBundledExtrinsic(index, _) => {
+					let extrinsics_root = ExtrinsicsRoots::<T>::get(fraud_proof.primary_number)
+						.ok_or(Error::<T>::UnknownBlock)?;
+					sp_executor::verify_extrinsic::<T::Hashing>(
+						&extrinsics_root,
+						&fraud_proof.extrinsic_proof,
+						index,
+						&fraud_proof.extrinsic,
+					)
+				},
+			};
+			ensure!(known_extrinsic, Error::<T>::UnknownExtrinsic);
+
+			ensure!(T::FraudProofVerifier::verify(fraud_proof), Error::<T>::InvalidFraudProof);
+
+			Ok(receipt)
+		}
+	}
+}
@@ -0,0 +1,220 @@
+use crate as pallet_execution_receipts;
+use sp_core::{H256, Pair};
+use frame_support::{parameter_types, traits::Hooks};
+use sp_runtime::{
+	OpaqueExtrinsic, traits::{BlakeTwo256, Hash, IdentityLookup}, testing::{Header, TestXt},
+	transaction_validity::TransactionPriority,
+};
+use sp_executor::{
+	Bundle, ExecutionLayout, ExecutionReceipt, ExecutionStep, ExecutorId, ExecutorPair, FraudProof,
+	SignedExecutionReceipt, SignedOpaqueBundle,
+};
+use codec::{Decode, Encode};
+use std::cell::RefCell;
+use frame_system as system;
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+// Configure a mock runtime to test the pallet.
+frame_support::construct_runtime!(
+	pub enum Test where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic,
+	{
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		ExecutionReceipts: pallet_execution_receipts::{Pallet, Call, Storage, Event<T>, ValidateUnsigned},
+	}
+);
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const SS58Prefix: u8 = 42;
+}
+
+impl system::Config for Test {
+	type BaseCallFilter = ();
+	type BlockWeights = ();
+	type BlockLength = ();
+	type DbWeight = ();
+	type Origin = Origin;
+	type Call = Call;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type Version = ();
+	type PalletInfo = PalletInfo;
+	type AccountData = ();
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+	type SS58Prefix = SS58Prefix;
+	type OnSetCode = ();
+}
+
+impl<C> system::offchain::SendTransactionTypes<C> for Test where Call: From<C> {
+	type OverarchingCall = Call;
+	type Extrinsic = TestXt<Call, ()>;
+}
+
+parameter_types! {
+	pub storage ChallengePeriod: u64 = 10;
+	pub storage MaxReceiptLag: u64 = 10;
+	pub storage ReceiptsPruningDepth: u64 = 10;
+	pub const UnsignedPriority: TransactionPriority = TransactionPriority::max_value();
+	pub const MaxFraudProofsPerBlock: u32 = 1;
+}
+
+/// Extrinsic that leads to another post state than the one claimed by `signed_receipt`.
+pub const BAD_EXTRINSIC: &[u8] = b"bad";
+
+/// Considers fraud proofs about `BAD_EXTRINSIC` valid, and only those.
+pub struct MockFraudProofVerifier;
+
+impl pallet_execution_receipts::FraudProofVerifier<u64, H256> for MockFraudProofVerifier {
+	fn verify(fraud_proof: &FraudProof<u64, H256>) -> bool {
+		fraud_proof.extrinsic == BAD_EXTRINSIC
+	}
+}
+
+/// Every executor but `Mallory` is registered.
+pub struct MockRegistry;
+
+impl sp_executor::ExecutorRegistry for MockRegistry {
+	fn is_active(executor: &ExecutorId) -> bool {
+		*executor != self::executor("Mallory").public()
+	}
+}
+
+thread_local! {
+	/// Executors punished through `MockOnFraudProof`, in order.
+	pub static PUNISHED: RefCell<Vec<ExecutorId>> = RefCell::new(Vec::new());
+}
+
+/// Records the executors whose receipts are proven wrong.
+pub struct MockOnFraudProof;
+
+impl sp_executor::OnFraudProof for MockOnFraudProof {
+	fn on_fraud_proof(executor: &ExecutorId) {
+		PUNISHED.with(|punished| punished.borrow_mut().push(executor.clone()));
+	}
+}
+
+/// Extrinsics made of a zero byte followed by an encoded bundle submit that bundle.
+pub struct MockExtractor;
+
+impl sp_executor::ExtractBundle for MockExtractor {
+	fn extract_bundle(extrinsic: &[u8]) -> Option<SignedOpaqueBundle> {
+		match extrinsic.split_first() {
+			Some((0, mut bundle)) => SignedOpaqueBundle::decode(&mut bundle).ok(),
+			_ => None,
+		}
+	}
+}
+
+impl pallet_execution_receipts::Config for Test {
+	type Event = Event;
+	type ChallengePeriod = ChallengePeriod;
+	type MaxReceiptLag = MaxReceiptLag;
+	type ReceiptsPruningDepth = ReceiptsPruningDepth;
+	type UnsignedPriority = UnsignedPriority;
+	type MaxFraudProofsPerBlock = MaxFraudProofsPerBlock;
+	type FraudProofVerifier = MockFraudProofVerifier;
+	type ExecutorRegistry = MockRegistry;
+	type OnFraudProof = MockOnFraudProof;
+	type ExtractBundle = MockExtractor;
+}
+
+// Build genesis storage according to the mock runtime.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
+}
+
+/// Move the chain forward to block `n`, running the hooks of the pallet on the way. The blocks
+/// left behind are taken to be made of a single extrinsic, unless their layout is already noted.
+pub fn run_to_block(n: u64) {
+	while System::block_number() < n {
+		let current = System::block_number();
+		if !pallet_execution_receipts::ExecutionLayouts::<Test>::contains_key(current) {
+			pallet_execution_receipts::ExecutionLayouts::<Test>::insert(
+				current,
+				ExecutionLayout { extrinsics: 1, bundles: Vec::new() },
+			);
+		}
+		let next = current + 1;
+		System::initialize(&next, &H256::from_low_u64_be(next - 1), &Default::default(), Default::default());
+		ExecutionReceipts::on_initialize(next);
+	}
+}
+
+/// The executor key derived from `seed`.
+pub fn executor(seed: &str) -> ExecutorPair {
+	ExecutorPair::from_string(&format!("//{}", seed), None).unwrap()
+}
+
+/// A receipt of the block at `number` signed by `executor`, for a block made of a single
+/// extrinsic. Its parent execution state is the one it ends with.
+pub fn signed_receipt(executor: &ExecutorPair, number: u64) -> SignedExecutionReceipt<u64, H256> {
+	let execution_receipt = ExecutionReceipt {
+		primary_number: number,
+		primary_hash: System::block_hash(number),
+		state_root: H256::repeat_byte(1),
+		trace: [1, 2, 7, 1].iter().map(|byte| H256::repeat_byte(*byte)).collect(),
+	};
+	let signature = executor.sign(execution_receipt.hash().as_ref());
+
+	SignedExecutionReceipt { execution_receipt, signer: executor.public(), signature }
+}
+
+/// A fraud proof against the receipt of `executor` for the block at `number`, disputing the
+/// first extrinsic of the block. The block is taken to be made of `extrinsic` only, whose root and
+/// layout are noted as the ones of the block: build the proof outside of `assert_noop!`.
+pub fn fraud_proof(executor: &ExecutorPair, number: u64, extrinsic: &[u8]) -> FraudProof<u64, H256> {
+	let extrinsics = vec![extrinsic.to_vec()];
+	pallet_execution_receipts::ExtrinsicsRoots::<Test>::insert(
+		number,
+		BlakeTwo256::ordered_trie_root(extrinsics.clone()),
+	);
+	pallet_execution_receipts::ExecutionLayouts::<Test>::insert(
+		number,
+		ExecutionLayout::new(&extrinsics, <MockExtractor as sp_executor::ExtractBundle>::extract_bundle),
+	);
+
+	FraudProof {
+		primary_number: number,
+		primary_hash: System::block_hash(number),
+		executor: executor.public(),
+		step: ExecutionStep::ApplyExtrinsic(0),
+		extrinsic: extrinsic.to_vec(),
+		extrinsic_proof: sp_executor::prove_extrinsic::<BlakeTwo256>(&extrinsics, 0).unwrap(),
+		pre_state_root: H256::repeat_byte(2),
+		post_state_root: H256::repeat_byte(7),
+		proof: Default::default(),
+	}
+}
+
+/// An extrinsic submitting a bundle of `transactions` transactions, for `MockExtractor`.
+pub fn bundle_extrinsic(transactions: u8) -> Vec<u8> {
+	let bundle = Bundle {
+		extrinsics: (0..transactions)
+			.map(|transaction| OpaqueExtrinsic::decode(&mut &vec![transaction].encode()[..]).unwrap())
+			.collect(),
+	};
+	let alice = executor("Alice");
+	let signature = alice.sign(bundle.hash().as_ref());
+	let mut extrinsic = vec![0];
+	extrinsic.extend(SignedOpaqueBundle { bundle, signer: alice.public(), signature }.encode());
+	extrinsic
+}
+
+/// The executors punished so far.
+pub fn punished() -> Vec<ExecutorId> {
+	PUNISHED.with(|punished| punished.borrow().clone())
+}
@@ -0,0 +1,748 @@
+use crate::{
+	Error, ExecutionLayouts, ExtrinsicsRoots, InvalidReceipts, MAX_PRUNED_PER_BLOCK, NextToPrune,
+	Receipts, mock::*,
+};
+use frame_support::{assert_noop, assert_ok, traits::Hooks, unsigned::ValidateUnsigned};
+use sp_core::{H256, Pair};
+use sp_executor::{ConfirmedBlock, ExecutionLayout, ExecutionReceipt, ExecutionStep, SignedExecutionReceipt};
+use sp_runtime::{
+	testing::Header,
+	traits::Header as HeaderT,
+	transaction_validity::{InvalidTransaction, TransactionSource},
+};
+use codec::Encode;
+
+#[test]
+fn submit_execution_receipt_works() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let alice = executor("Alice");
+		let receipt = signed_receipt(&alice, 1);
+
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), receipt.clone()));
+
+		assert_eq!(
+			ExecutionReceipts::receipts(1, alice.public()),
+			Some(receipt.execution_receipt),
+		);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_execution_receipts(crate::Event::ExecutionReceiptStored(1, alice.public())),
+		);
+	});
+}
+
+#[test]
+fn executed_head_follows_the_highest_receipt() {
+	new_test_ext().execute_with(|| {
+		run_to_block(4);
+		assert_eq!(ExecutionReceipts::executed_head(), 0);
+
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(
+			Origin::none(),
+			signed_receipt(&executor("Alice"), 2),
+		));
+		assert_eq!(ExecutionReceipts::executed_head(), 2);
+
+		// late receipts don't move it back.
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(
+			Origin::none(),
+			signed_receipt(&executor("Alice"), 1),
+		));
+		assert_eq!(ExecutionReceipts::executed_head(), 2);
+	});
+}
+
+#[test]
+fn receipts_must_be_unsigned_extrinsics() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let receipt = signed_receipt(&executor("Alice"), 1);
+
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::signed(1), receipt),
+			frame_support::error::BadOrigin,
+		);
+	});
+}
+
+#[test]
+fn receipt_with_bad_signature_is_rejected() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let mut receipt = signed_receipt(&executor("Alice"), 1);
+		receipt.execution_receipt.state_root = H256::repeat_byte(3);
+
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), receipt),
+			Error::<Test>::BadSignature,
+		);
+	});
+}
+
+#[test]
+fn receipt_of_unregistered_executor_is_rejected() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let receipt = signed_receipt(&executor("Mallory"), 1);
+
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), receipt.clone()),
+			Error::<Test>::UnknownExecutor,
+		);
+		let call = crate::Call::submit_execution_receipt(receipt);
+		assert_eq!(
+			ExecutionReceipts::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::BadSigner.into(),
+		);
+	});
+}
+
+#[test]
+fn receipt_of_unknown_block_is_rejected() {
+	new_test_ext().execute_with(|| {
+		run_to_block(3);
+		let alice = executor("Alice");
+
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 3)),
+			Error::<Test>::ReceiptFromFuture,
+		);
+
+		let mut receipt = signed_receipt(&alice, 1);
+		receipt.execution_receipt.primary_hash = H256::repeat_byte(4);
+		receipt.signature = alice.sign(receipt.execution_receipt.hash().as_ref());
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), receipt),
+			Error::<Test>::UnknownBlock,
+		);
+	});
+}
+
+#[test]
+fn receipt_with_malformed_trace_is_rejected() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let alice = executor("Alice");
+		let sign = |execution_receipt: ExecutionReceipt<u64, H256>| {
+			let signature = alice.sign(execution_receipt.hash().as_ref());
+			SignedExecutionReceipt { execution_receipt, signer: alice.public(), signature }
+		};
+
+		// one step short.
+		let mut receipt = signed_receipt(&alice, 1).execution_receipt;
+		receipt.trace.remove(1);
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), sign(receipt)),
+			Error::<Test>::MalformedTrace,
+		);
+
+		// not ending with the state root.
+		let mut receipt = signed_receipt(&alice, 1).execution_receipt;
+		receipt.state_root = H256::repeat_byte(3);
+		let call = crate::Call::submit_execution_receipt(sign(receipt.clone()));
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), sign(receipt)),
+			Error::<Test>::MalformedTrace,
+		);
+		assert_eq!(
+			ExecutionReceipts::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::BadProof.into(),
+		);
+	});
+}
+
+#[test]
+fn duplicate_receipt_is_rejected() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let alice = executor("Alice");
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)),
+			Error::<Test>::DuplicateReceipt,
+		);
+		// other executors can still submit their own.
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&executor("Bob"), 1)));
+	});
+}
+
+#[test]
+fn majority_executed_head_ignores_executors_ahead_of_the_others() {
+	new_test_ext().execute_with(|| {
+		run_to_block(4);
+		let (alice, bob, charlie) = (executor("Alice"), executor("Bob"), executor("Charlie"));
+		assert_eq!(ExecutionReceipts::majority_executed_head(), 0);
+
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 3)));
+		assert_eq!(ExecutionReceipts::majority_executed_head(), 3);
+
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&bob, 1)));
+		assert_eq!(ExecutionReceipts::majority_executed_head(), 1);
+
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&charlie, 2)));
+		assert_eq!(ExecutionReceipts::majority_executed_head(), 2);
+
+		// executors proven wrong don't count anymore.
+		assert_ok!(ExecutionReceipts::submit_fraud_proof(Origin::none(), fraud_proof(&alice, 3, BAD_EXTRINSIC)));
+		assert_eq!(ExecutionReceipts::majority_executed_head(), 1);
+	});
+}
+
+#[test]
+fn receipts_are_pruned_after_the_pruning_depth() {
+	new_test_ext().execute_with(|| {
+		// enough for the receipts submitted last to be challenged for the challenge period.
+		assert_eq!(ExecutionReceipts::pruning_depth(), (ChallengePeriod::get() - 1) + ChallengePeriod::get());
+		run_to_block(2);
+		let alice = executor("Alice");
+		let receipt = signed_receipt(&alice, 1);
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), receipt.clone()));
+
+		run_to_block(1 + ExecutionReceipts::pruning_depth() - 1);
+		assert!(Receipts::<Test>::contains_key(1, alice.public()));
+
+		run_to_block(1 + ExecutionReceipts::pruning_depth());
+		assert!(!Receipts::<Test>::contains_key(1, alice.public()));
+
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), receipt),
+			Error::<Test>::ReceiptTooOld,
+		);
+	});
+}
+
+#[test]
+fn validate_unsigned_checks_the_receipt() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let receipt = signed_receipt(&executor("Alice"), 1);
+
+		let call = crate::Call::submit_execution_receipt(receipt.clone());
+		assert_ok!(ExecutionReceipts::validate_unsigned(TransactionSource::External, &call));
+
+		let mut bad_receipt = receipt;
+		bad_receipt.execution_receipt.state_root = H256::repeat_byte(3);
+		let call = crate::Call::submit_execution_receipt(bad_receipt);
+		assert_eq!(
+			ExecutionReceipts::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::BadProof.into(),
+		);
+	});
+}
+
+#[test]
+fn valid_fraud_proof_invalidates_receipt() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let alice = executor("Alice");
+		let receipt = signed_receipt(&alice, 1);
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), receipt.clone()));
+
+		assert_ok!(ExecutionReceipts::submit_fraud_proof(
+			Origin::none(),
+			fraud_proof(&alice, 1, BAD_EXTRINSIC),
+		));
+
+		assert!(!Receipts::<Test>::contains_key(1, alice.public()));
+		assert_eq!(
+			ExecutionReceipts::invalid_receipts(1, alice.public()),
+			Some(receipt.execution_receipt.hash()),
+		);
+		assert_eq!(punished(), vec![alice.public()]);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_execution_receipts(crate::Event::ExecutionReceiptInvalidated(1, alice.public())),
+		);
+
+		// the executor can't take another shot at the same block.
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), receipt),
+			Error::<Test>::DuplicateReceipt,
+		);
+	});
+}
+
+#[test]
+fn invalid_fraud_proof_is_rejected() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let alice = executor("Alice");
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+
+		let proof = fraud_proof(&alice, 1, b"good");
+		assert_noop!(
+			ExecutionReceipts::submit_fraud_proof(Origin::none(), proof),
+			Error::<Test>::InvalidFraudProof,
+		);
+		assert!(Receipts::<Test>::contains_key(1, alice.public()));
+		assert!(punished().is_empty());
+	});
+}
+
+#[test]
+fn fraud_proof_must_match_the_receipt() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let alice = executor("Alice");
+
+		let proof = fraud_proof(&alice, 1, BAD_EXTRINSIC);
+		assert_noop!(
+			ExecutionReceipts::submit_fraud_proof(Origin::none(), proof),
+			Error::<Test>::UnknownReceipt,
+		);
+
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+		let mut proof = fraud_proof(&alice, 1, BAD_EXTRINSIC);
+		proof.pre_state_root = H256::repeat_byte(3);
+		assert_noop!(
+			ExecutionReceipts::submit_fraud_proof(Origin::none(), proof),
+			Error::<Test>::TraceMismatch,
+		);
+
+		let mut proof = fraud_proof(&alice, 1, BAD_EXTRINSIC);
+		proof.step = ExecutionStep::ApplyExtrinsic(1);
+		assert_noop!(
+			ExecutionReceipts::submit_fraud_proof(Origin::none(), proof.clone()),
+			Error::<Test>::UnknownStep,
+		);
+		let call = crate::Call::submit_fraud_proof(proof);
+		assert_eq!(
+			ExecutionReceipts::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::BadProof.into(),
+		);
+	});
+}
+
+#[test]
+fn fraud_proof_can_dispute_a_bundled_transaction() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let alice = executor("Alice");
+		let mut proof = fraud_proof(&alice, 1, BAD_EXTRINSIC);
+		// the block submits a bundle of two transactions, applied after it.
+		ExecutionLayouts::<Test>::insert(1, ExecutionLayout { extrinsics: 1, bundles: vec![(0, 2)] });
+		let execution_receipt = ExecutionReceipt {
+			primary_number: 1,
+			primary_hash: System::block_hash(1),
+			state_root: H256::repeat_byte(7),
+			trace: (2..8).map(H256::repeat_byte).collect(),
+		};
+		let signature = alice.sign(execution_receipt.hash().as_ref());
+		let receipt = SignedExecutionReceipt { execution_receipt, signer: alice.public(), signature };
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), receipt));
+
+		proof.step = ExecutionStep::ApplyBundledExtrinsic(0, 1);
+		assert_noop!(
+			ExecutionReceipts::submit_fraud_proof(Origin::none(), proof.clone()),
+			Error::<Test>::TraceMismatch,
+		);
+
+		proof.pre_state_root = H256::repeat_byte(5);
+		proof.post_state_root = H256::repeat_byte(6);
+		assert_ok!(ExecutionReceipts::submit_fraud_proof(Origin::none(), proof));
+		assert_eq!(punished(), vec![alice.public()]);
+	});
+}
+
+#[test]
+fn fraud_proof_must_dispute_an_extrinsic_of_the_block() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let alice = executor("Alice");
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+
+		// the block holds another extrinsic than the disputed one.
+		let mut proof = fraud_proof(&alice, 1, b"good");
+		proof.extrinsic = BAD_EXTRINSIC.to_vec();
+		assert_noop!(
+			ExecutionReceipts::submit_fraud_proof(Origin::none(), proof.clone()),
+			Error::<Test>::UnknownExtrinsic,
+		);
+		let call = crate::Call::submit_fraud_proof(proof);
+		assert_eq!(
+			ExecutionReceipts::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::BadProof.into(),
+		);
+	});
+}
+
+#[test]
+fn fraud_proof_can_dispute_the_block_initialization() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let alice = executor("Alice");
+		let header = Header::new(1, H256::repeat_byte(8), H256::repeat_byte(9), System::block_hash(0), Default::default());
+		frame_system::BlockHash::<Test>::insert(1, header.hash());
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+
+		let mut proof = fraud_proof(&alice, 1, BAD_EXTRINSIC);
+		proof.step = ExecutionStep::InitializeBlock;
+		proof.extrinsic_proof = Vec::new();
+		proof.pre_state_root = H256::repeat_byte(1);
+		proof.post_state_root = H256::repeat_byte(2);
+
+		// the block is initialized with its own header only.
+		let mut other = header.clone();
+		other.set_state_root(H256::repeat_byte(10));
+		proof.extrinsic = other.encode();
+		assert_noop!(
+			ExecutionReceipts::submit_fraud_proof(Origin::none(), proof.clone()),
+			Error::<Test>::UnknownExtrinsic,
+		);
+
+		// `MockFraudProofVerifier` gets to re-execute the step, and finds nothing wrong.
+		proof.extrinsic = header.encode();
+		assert_noop!(
+			ExecutionReceipts::submit_fraud_proof(Origin::none(), proof),
+			Error::<Test>::InvalidFraudProof,
+		);
+	});
+}
+
+#[test]
+fn fraud_proofs_are_limited_per_block() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let (alice, bob) = (executor("Alice"), executor("Bob"));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&bob, 1)));
+
+		assert_ok!(ExecutionReceipts::submit_fraud_proof(Origin::none(), fraud_proof(&alice, 1, BAD_EXTRINSIC)));
+		assert_noop!(
+			ExecutionReceipts::submit_fraud_proof(Origin::none(), fraud_proof(&bob, 1, BAD_EXTRINSIC)),
+			Error::<Test>::TooManyFraudProofs,
+		);
+		let call = crate::Call::submit_fraud_proof(fraud_proof(&bob, 1, BAD_EXTRINSIC));
+		assert_eq!(
+			ExecutionReceipts::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::ExhaustsResources.into(),
+		);
+
+		run_to_block(3);
+		assert_ok!(ExecutionReceipts::submit_fraud_proof(Origin::none(), fraud_proof(&bob, 1, BAD_EXTRINSIC)));
+	});
+}
+
+#[test]
+fn extrinsics_root_is_the_one_of_the_header() {
+	new_test_ext().execute_with(|| {
+		run_to_block(1);
+		for extrinsic in vec![b"first".to_vec(), bundle_extrinsic(3), b"other".to_vec()] {
+			System::note_extrinsic(extrinsic);
+			System::note_applied_extrinsic(&Ok(().into()), Default::default());
+		}
+		System::note_finished_extrinsics();
+
+		ExecutionReceipts::on_finalize(1);
+		let header = System::finalize();
+
+		assert_eq!(ExecutionReceipts::extrinsics_root(1), Some(header.extrinsics_root));
+		let layout = ExecutionReceipts::execution_layout(1).unwrap();
+		assert_eq!(layout, ExecutionLayout { extrinsics: 3, bundles: vec![(1, 3)] });
+		assert_eq!(layout.step_count(), 8);
+		assert_eq!(layout.index_of(&ExecutionStep::InitializeBlock), Some(0));
+		assert_eq!(layout.index_of(&ExecutionStep::ApplyBundledExtrinsic(1, 2)), Some(5));
+		assert_eq!(layout.index_of(&ExecutionStep::ApplyExtrinsic(2)), Some(6));
+		assert_eq!(layout.index_of(&ExecutionStep::FinalizeBlock), Some(7));
+	});
+}
+
+#[test]
+fn invalid_receipts_are_pruned_after_challenge_period() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let alice = executor("Alice");
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+		assert_ok!(ExecutionReceipts::submit_fraud_proof(
+			Origin::none(),
+			fraud_proof(&alice, 1, BAD_EXTRINSIC),
+		));
+
+		run_to_block(1 + ExecutionReceipts::pruning_depth());
+		assert!(!InvalidReceipts::<Test>::contains_key(1, alice.public()));
+		assert!(!ExtrinsicsRoots::<Test>::contains_key(1));
+		assert!(!ExecutionLayouts::<Test>::contains_key(1));
+	});
+}
+
+#[test]
+fn receipts_are_kept_for_the_pruning_depth() {
+	new_test_ext().execute_with(|| {
+		ReceiptsPruningDepth::set(&25);
+		run_to_block(2);
+		let alice = executor("Alice");
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+
+		run_to_block(2 + ChallengePeriod::get());
+		assert!(Receipts::<Test>::contains_key(1, alice.public()));
+		// kept, but out of reach of fraud proofs.
+		let proof = fraud_proof(&alice, 1, BAD_EXTRINSIC);
+		assert_noop!(
+			ExecutionReceipts::submit_fraud_proof(Origin::none(), proof),
+			Error::<Test>::ChallengePeriodOver,
+		);
+
+		run_to_block(1 + 25);
+		assert!(!Receipts::<Test>::contains_key(1, alice.public()));
+	});
+}
+
+#[test]
+fn receipts_are_kept_for_the_challenge_period_at_least() {
+	new_test_ext().execute_with(|| {
+		ReceiptsPruningDepth::set(&2);
+		// the last block the receipt of block 1 can be submitted in.
+		let included_at = 1 + ExecutionReceipts::receipt_lag() - 1;
+		run_to_block(included_at);
+		let alice = executor("Alice");
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+
+		run_to_block(included_at + ChallengePeriod::get() - 1);
+		assert!(Receipts::<Test>::contains_key(1, alice.public()));
+		assert_ok!(ExecutionReceipts::submit_fraud_proof(
+			Origin::none(),
+			fraud_proof(&alice, 1, BAD_EXTRINSIC),
+		));
+	});
+}
+
+#[test]
+fn challenge_period_starts_from_the_inclusion_of_the_receipt() {
+	new_test_ext().execute_with(|| {
+		run_to_block(9);
+		let (alice, bob) = (executor("Alice"), executor("Bob"));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&bob, 1)));
+
+		// past the challenge period counted from the block itself.
+		run_to_block(1 + ChallengePeriod::get());
+		assert_ok!(ExecutionReceipts::submit_fraud_proof(
+			Origin::none(),
+			fraud_proof(&alice, 1, BAD_EXTRINSIC),
+		));
+
+		run_to_block(9 + ChallengePeriod::get() - 1);
+		let call = crate::Call::submit_fraud_proof(fraud_proof(&bob, 1, BAD_EXTRINSIC));
+		assert_eq!(
+			ExecutionReceipts::validate_unsigned(TransactionSource::External, &call)
+				.map(|validity| validity.longevity),
+			Ok(1),
+		);
+
+		run_to_block(9 + ChallengePeriod::get());
+		assert_noop!(
+			ExecutionReceipts::submit_fraud_proof(Origin::none(), fraud_proof(&bob, 1, BAD_EXTRINSIC)),
+			Error::<Test>::ChallengePeriodOver,
+		);
+	});
+}
+
+#[test]
+fn pruning_catches_up_when_the_depth_shrinks() {
+	new_test_ext().execute_with(|| {
+		ReceiptsPruningDepth::set(&40);
+		MaxReceiptLag::set(&40);
+		ChallengePeriod::set(&41);
+		run_to_block(25);
+		let alice = executor("Alice");
+		for number in 1..25 {
+			assert_ok!(ExecutionReceipts::submit_execution_receipt(
+				Origin::none(),
+				signed_receipt(&alice, number),
+			));
+		}
+
+		ReceiptsPruningDepth::set(&5);
+		MaxReceiptLag::set(&5);
+		ChallengePeriod::set(&5);
+		assert_eq!(ExecutionReceipts::pruning_depth(), 9);
+		// 26 - 9 = 17 blocks to prune, more than fits in one block.
+		run_to_block(26);
+		assert!(!Receipts::<Test>::contains_key(MAX_PRUNED_PER_BLOCK as u64 - 1, alice.public()));
+		assert!(Receipts::<Test>::contains_key(MAX_PRUNED_PER_BLOCK as u64, alice.public()));
+
+		run_to_block(27);
+		assert!(!Receipts::<Test>::contains_key(18, alice.public()));
+		assert!(Receipts::<Test>::contains_key(19, alice.public()));
+		assert_eq!(NextToPrune::<Test>::get(), 19);
+	});
+}
+
+#[test]
+fn receipts_of_pruned_blocks_are_rejected() {
+	new_test_ext().execute_with(|| {
+		run_to_block(25);
+		assert_eq!(NextToPrune::<Test>::get(), 25 - ExecutionReceipts::pruning_depth() + 1);
+
+		// the receipts of blocks 0 to 6 are gone, however long executors are given.
+		MaxReceiptLag::set(&30);
+		ChallengePeriod::set(&31);
+		let alice = executor("Alice");
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 6)),
+			Error::<Test>::ReceiptTooOld,
+		);
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 7)));
+	});
+}
+
+/// A receipt of `executor` for the block at `number` claiming another state root than
+/// `signed_receipt`.
+fn diverging_receipt(executor: &sp_executor::ExecutorPair, number: u64) -> sp_executor::SignedExecutionReceipt<u64, H256> {
+	let mut receipt = signed_receipt(executor, number);
+	receipt.execution_receipt.state_root = H256::repeat_byte(3);
+	*receipt.execution_receipt.trace.last_mut().unwrap() = H256::repeat_byte(3);
+	receipt.signature = executor.sign(receipt.execution_receipt.hash().as_ref());
+	receipt
+}
+
+#[test]
+fn agreeing_receipts_confirm_their_block() {
+	new_test_ext().execute_with(|| {
+		run_to_block(3);
+		let (alice, bob) = (executor("Alice"), executor("Bob"));
+		for number in 1..3 {
+			assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, number)));
+			assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&bob, number)));
+		}
+
+		run_to_block(1 + ExecutionReceipts::pruning_depth() - 1);
+		assert_eq!(ExecutionReceipts::confirmed_head(), None);
+
+		run_to_block(1 + ExecutionReceipts::pruning_depth());
+		assert_eq!(
+			ExecutionReceipts::confirmed_head(),
+			Some(ConfirmedBlock { number: 1, hash: System::block_hash(1), state_root: H256::repeat_byte(1) }),
+		);
+
+		run_to_block(2 + ExecutionReceipts::pruning_depth());
+		assert_eq!(ExecutionReceipts::confirmed_head().map(|head| head.number), Some(2));
+	});
+}
+
+#[test]
+fn blocks_without_receipts_are_confirmed_by_the_next_one() {
+	new_test_ext().execute_with(|| {
+		run_to_block(3);
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&executor("Alice"), 2)));
+
+		run_to_block(1 + ExecutionReceipts::pruning_depth());
+		assert_eq!(ExecutionReceipts::confirmed_head(), None);
+
+		run_to_block(2 + ExecutionReceipts::pruning_depth());
+		assert_eq!(ExecutionReceipts::confirmed_head().map(|head| head.number), Some(2));
+	});
+}
+
+#[test]
+fn receipts_proven_wrong_are_left_out_of_the_confirmation() {
+	new_test_ext().execute_with(|| {
+		run_to_block(2);
+		let (alice, bob) = (executor("Alice"), executor("Bob"));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), diverging_receipt(&alice, 1)));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&bob, 1)));
+		assert_ok!(ExecutionReceipts::submit_fraud_proof(Origin::none(), fraud_proof(&alice, 1, BAD_EXTRINSIC)));
+
+		run_to_block(1 + ExecutionReceipts::pruning_depth());
+		assert_eq!(
+			ExecutionReceipts::confirmed_head().map(|head| head.state_root),
+			Some(H256::repeat_byte(1)),
+		);
+	});
+}
+
+#[test]
+fn receipts_on_another_parent_state_are_left_out_of_the_confirmation() {
+	new_test_ext().execute_with(|| {
+		run_to_block(3);
+		let (alice, bob) = (executor("Alice"), executor("Bob"));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 2)));
+		let mut receipt = diverging_receipt(&bob, 2);
+		receipt.execution_receipt.trace[0] = H256::repeat_byte(4);
+		receipt.signature = bob.sign(receipt.execution_receipt.hash().as_ref());
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), receipt));
+
+		run_to_block(2 + ExecutionReceipts::pruning_depth());
+		assert_eq!(ExecutionReceipts::unsettled_block(), None);
+		assert_eq!(
+			ExecutionReceipts::confirmed_head(),
+			Some(ConfirmedBlock { number: 2, hash: System::block_hash(2), state_root: H256::repeat_byte(1) }),
+		);
+	});
+}
+
+#[test]
+fn disagreeing_receipts_leave_their_block_unconfirmed() {
+	new_test_ext().execute_with(|| {
+		run_to_block(3);
+		let (alice, bob) = (executor("Alice"), executor("Bob"));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), diverging_receipt(&alice, 1)));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&bob, 1)));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&bob, 2)));
+
+		run_to_block(1 + ExecutionReceipts::pruning_depth());
+		assert_eq!(ExecutionReceipts::unsettled_block(), Some(1));
+		assert!(System::events().iter().any(|record| record.event ==
+			Event::pallet_execution_receipts(crate::Event::ExecutionUnsettled(1))
+		));
+		assert_eq!(ExecutionReceipts::confirmed_head(), None);
+
+		// the next blocks are settled all the same.
+		run_to_block(2 + ExecutionReceipts::pruning_depth());
+		assert_eq!(ExecutionReceipts::confirmed_head().map(|head| head.number), Some(2));
+	});
+}
+
+#[test]
+fn receipts_lagging_too_far_behind_are_rejected() {
+	new_test_ext().execute_with(|| {
+		MaxReceiptLag::set(&3);
+		run_to_block(4);
+		let alice = executor("Alice");
+
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)),
+			Error::<Test>::ReceiptTooOld,
+		);
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 2)));
+	});
+}
+
+#[test]
+fn receipt_lag_is_kept_below_the_challenge_period() {
+	new_test_ext().execute_with(|| {
+		MaxReceiptLag::set(&40);
+		assert_eq!(ExecutionReceipts::receipt_lag(), ChallengePeriod::get() - 1);
+		run_to_block(10);
+		let alice = executor("Alice");
+
+		assert_noop!(
+			ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)),
+			Error::<Test>::ReceiptTooOld,
+		);
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 2)));
+	});
+}
+
+#[test]
+fn receipts_of_lists_the_receipts_of_a_block() {
+	new_test_ext().execute_with(|| {
+		run_to_block(3);
+		let (alice, bob) = (executor("Alice"), executor("Bob"));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 1)));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&bob, 1)));
+		assert_ok!(ExecutionReceipts::submit_execution_receipt(Origin::none(), signed_receipt(&alice, 2)));
+
+		let mut receipts = ExecutionReceipts::receipts_of(1);
+		receipts.sort_by_key(|(executor, _)| executor.clone());
+		let mut expected = vec![
+			(alice.public(), signed_receipt(&alice, 1).execution_receipt),
+			(bob.public(), signed_receipt(&bob, 1).execution_receipt),
+		];
+		expected.sort_by_key(|(executor, _)| executor.clone());
+		assert_eq!(receipts, expected);
+	});
+}
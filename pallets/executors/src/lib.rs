@@ -0,0 +1,253 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Keeps the registry of executors and of the bonds they answer for their claims with.
+//!
+//! An account registers an executor key by reserving a bond of at least `MinimumBond`. Only the
+//! keys of registered executors are allowed to submit receipts and bundles. When a fraud proof
+//! against one of their receipts succeeds, the whole bond is slashed. Executors leaving the set
+//! stop being active right away, but their bond stays reserved, and slashable, for
+//! `UnbondingPeriod` blocks.
+
+pub use pallet::*;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+#[frame_support::pallet]
+pub mod pallet {
+	use codec::{Decode, Encode};
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		traits::{Currency, Imbalance, OnUnbalanced, ReservableCurrency},
+	};
+	use frame_system::pallet_prelude::*;
+	use sp_executor::{ExecutorId, ExecutorRegistry, ExecutorSignature, OnFraudProof};
+	use sp_runtime::{RuntimeAppPublic, RuntimeDebug, traits::Saturating};
+	use sp_std::vec::Vec;
+
+	pub(crate) type BalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+	type NegativeImbalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::NegativeImbalance;
+
+	/// A registered executor.
+	#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+	pub struct ExecutorInfo<AccountId, Balance, BlockNumber> {
+		/// The account the bond is reserved from.
+		pub owner: AccountId,
+		/// The reserved bond.
+		pub bond: Balance,
+		/// The block from which the bond can be withdrawn, once the executor is unbonding.
+		pub unlocking_at: Option<BlockNumber>,
+	}
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config {
+		/// Because this pallet emits events, it depends on the runtime's definition of an event.
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		/// The currency bonds are reserved in.
+		type Currency: ReservableCurrency<Self::AccountId>;
+
+		/// The smallest bond an executor can register with.
+		#[pallet::constant]
+		type MinimumBond: Get<BalanceOf<Self>>;
+
+		/// Number of blocks between the moment an executor unbonds and the moment its bond can
+		/// be withdrawn. Should be at least the challenge period of receipts, for their fraud
+		/// proofs to still find something to slash.
+		#[pallet::constant]
+		type UnbondingPeriod: Get<Self::BlockNumber>;
+
+		/// Where the slashed bonds go.
+		type Slash: OnUnbalanced<NegativeImbalanceOf<Self>>;
+	}
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T>(_);
+
+	/// The registered executors, by key.
+	#[pallet::storage]
+	#[pallet::getter(fn executors)]
+	pub type Executors<T: Config> = StorageMap<
+		_,
+		Blake2_128Concat,
+		ExecutorId,
+		ExecutorInfo<T::AccountId, BalanceOf<T>, T::BlockNumber>,
+	>;
+
+	/// The executor key registered by an account.
+	#[pallet::storage]
+	#[pallet::getter(fn executor_of)]
+	pub type ExecutorOf<T: Config> = StorageMap<_, Blake2_128Concat, T::AccountId, ExecutorId>;
+
+	#[pallet::genesis_config]
+	pub struct GenesisConfig<T: Config> {
+		/// The executors registered from genesis, with their owner and bond.
+		pub executors: Vec<(T::AccountId, ExecutorId, BalanceOf<T>)>,
+	}
+
+	#[cfg(feature = "std")]
+	impl<T: Config> Default for GenesisConfig<T> {
+		fn default() -> Self {
+			GenesisConfig { executors: Vec::new() }
+		}
+	}
+
+	#[pallet::genesis_build]
+	impl<T: Config> GenesisBuild<T> for GenesisConfig<T> {
+		fn build(&self) {
+			for (owner, executor, bond) in &self.executors {
+				Pallet::<T>::do_register(owner.clone(), executor.clone(), *bond)
+					.expect("Genesis executors must be unique and able to afford their bond");
+			}
+		}
+	}
+
+	#[pallet::event]
+	#[pallet::metadata(T::AccountId = "AccountId", ExecutorId = "ExecutorId", BalanceOf<T> = "Balance")]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		/// An account registered an executor. [owner, executor, bond]
+		ExecutorRegistered(T::AccountId, ExecutorId, BalanceOf<T>),
+		/// An executor started unbonding. [executor, unlocking_at]
+		ExecutorUnbonding(ExecutorId, T::BlockNumber),
+		/// The bond of an executor was given back to its owner. [owner, executor, bond]
+		BondWithdrawn(T::AccountId, ExecutorId, BalanceOf<T>),
+		/// The bond of an executor was slashed for a wrong receipt. [executor, amount]
+		ExecutorSlashed(ExecutorId, BalanceOf<T>),
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// The account or the executor key is already registered.
+		AlreadyRegistered,
+		/// The bond is lower than `MinimumBond`.
+		BondTooLow,
+		/// The account can't afford the bond.
+		InsufficientBalance,
+		/// The signature doesn't prove the possession of the executor key.
+		BadSignature,
+		/// The account has no executor registered.
+		NotRegistered,
+		/// The executor is already unbonding.
+		AlreadyUnbonding,
+		/// The executor has to unbond before withdrawing.
+		NotUnbonding,
+		/// The unbonding period is not over yet.
+		StillUnbonding,
+	}
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Register `executor` on behalf of the origin, reserving `bond` from its balance.
+		///
+		/// `signature` is the signature of the SCALE encoded origin account by the executor key,
+		/// which prevents registering the key of someone else.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(3, 3))]
+		pub fn register(
+			origin: OriginFor<T>,
+			executor: ExecutorId,
+			signature: ExecutorSignature,
+			#[pallet::compact] bond: BalanceOf<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+
+			ensure!(executor.verify(&who.encode(), &signature), Error::<T>::BadSignature);
+
+			Self::do_register(who.clone(), executor.clone(), bond)?;
+
+			Self::deposit_event(Event::ExecutorRegistered(who, executor, bond));
+			Ok(())
+		}
+
+		/// Stop the executor of the origin from making claims. Its bond can be withdrawn once the
+		/// unbonding period is over.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 1))]
+		pub fn unbond(origin: OriginFor<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+
+			let executor = ExecutorOf::<T>::get(&who).ok_or(Error::<T>::NotRegistered)?;
+			let unlocking_at = frame_system::Pallet::<T>::block_number()
+				.saturating_add(T::UnbondingPeriod::get());
+			Executors::<T>::try_mutate(&executor, |info| -> DispatchResult {
+				let info = info.as_mut().ok_or(Error::<T>::NotRegistered)?;
+				ensure!(info.unlocking_at.is_none(), Error::<T>::AlreadyUnbonding);
+				info.unlocking_at = Some(unlocking_at);
+				Ok(())
+			})?;
+
+			Self::deposit_event(Event::ExecutorUnbonding(executor, unlocking_at));
+			Ok(())
+		}
+
+		/// Give the bond of the executor of the origin back, once it is done unbonding. The
+		/// executor key is unregistered.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(3, 3))]
+		pub fn withdraw(origin: OriginFor<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+
+			let executor = ExecutorOf::<T>::get(&who).ok_or(Error::<T>::NotRegistered)?;
+			let info = Executors::<T>::get(&executor).ok_or(Error::<T>::NotRegistered)?;
+			let unlocking_at = info.unlocking_at.ok_or(Error::<T>::NotUnbonding)?;
+			ensure!(
+				frame_system::Pallet::<T>::block_number() >= unlocking_at,
+				Error::<T>::StillUnbonding,
+			);
+
+			T::Currency::unreserve(&who, info.bond);
+			Executors::<T>::remove(&executor);
+			ExecutorOf::<T>::remove(&who);
+
+			Self::deposit_event(Event::BondWithdrawn(who, executor, info.bond));
+			Ok(())
+		}
+	}
+
+	impl<T: Config> Pallet<T> {
+		fn do_register(owner: T::AccountId, executor: ExecutorId, bond: BalanceOf<T>) -> DispatchResult {
+			ensure!(
+				!ExecutorOf::<T>::contains_key(&owner) && !Executors::<T>::contains_key(&executor),
+				Error::<T>::AlreadyRegistered,
+			);
+			ensure!(bond >= T::MinimumBond::get(), Error::<T>::BondTooLow);
+			T::Currency::reserve(&owner, bond).map_err(|_| Error::<T>::InsufficientBalance)?;
+
+			Executors::<T>::insert(&executor, ExecutorInfo { owner: owner.clone(), bond, unlocking_at: None });
+			ExecutorOf::<T>::insert(&owner, executor);
+			Ok(())
+		}
+	}
+
+	impl<T: Config> ExecutorRegistry for Pallet<T> {
+		fn is_active(executor: &ExecutorId) -> bool {
+			Executors::<T>::get(executor).map_or(false, |info| info.unlocking_at.is_none())
+		}
+	}
+
+	impl<T: Config> OnFraudProof for Pallet<T> {
+		/// Slash the whole bond of `executor` and unregister it.
+		fn on_fraud_proof(executor: &ExecutorId) {
+			let info = match Executors::<T>::take(executor) {
+				Some(info) => info,
+				// unregistered since, nothing left to slash.
+				None => return,
+			};
+			ExecutorOf::<T>::remove(&info.owner);
+
+			let (imbalance, _) = T::Currency::slash_reserved(&info.owner, info.bond);
+			let slashed = imbalance.peek();
+			T::Slash::on_unbalanced(imbalance);
+
+			Self::deposit_event(Event::ExecutorSlashed(executor.clone(), slashed));
+		}
+	}
+}
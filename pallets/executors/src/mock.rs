@@ -0,0 +1,114 @@
+use crate as pallet_executors;
+use codec::Encode;
+use sp_core::{H256, Pair};
+use frame_support::parameter_types;
+use sp_runtime::{
+	traits::{BlakeTwo256, IdentityLookup}, testing::Header,
+};
+use sp_executor::{ExecutorPair, ExecutorSignature};
+use frame_system as system;
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+// Configure a mock runtime to test the pallet.
+frame_support::construct_runtime!(
+	pub enum Test where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic,
+	{
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
+		Executors: pallet_executors::{Pallet, Call, Storage, Config<T>, Event<T>},
+	}
+);
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const SS58Prefix: u8 = 42;
+}
+
+impl system::Config for Test {
+	type BaseCallFilter = ();
+	type BlockWeights = ();
+	type BlockLength = ();
+	type DbWeight = ();
+	type Origin = Origin;
+	type Call = Call;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type Version = ();
+	type PalletInfo = PalletInfo;
+	type AccountData = pallet_balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+	type SS58Prefix = SS58Prefix;
+	type OnSetCode = ();
+}
+
+parameter_types! {
+	pub const ExistentialDeposit: u64 = 1;
+	pub const MaxLocks: u32 = 50;
+}
+
+impl pallet_balances::Config for Test {
+	type MaxLocks = MaxLocks;
+	type Balance = u64;
+	type Event = Event;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+	type WeightInfo = ();
+}
+
+parameter_types! {
+	pub const MinimumBond: u64 = 100;
+	pub const UnbondingPeriod: u64 = 10;
+}
+
+impl pallet_executors::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+	type MinimumBond = MinimumBond;
+	type UnbondingPeriod = UnbondingPeriod;
+	type Slash = ();
+}
+
+/// Account registered as the owner of the `Alice` executor at genesis, with a bond of 100.
+pub const ALICE: u64 = 1;
+/// Account with funds and no executor.
+pub const BOB: u64 = 2;
+
+// Build genesis storage according to the mock runtime.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut storage = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	pallet_balances::GenesisConfig::<Test> {
+		balances: vec![(ALICE, 1_000), (BOB, 1_000)],
+	}.assimilate_storage(&mut storage).unwrap();
+	pallet_executors::GenesisConfig::<Test> {
+		executors: vec![(ALICE, executor("Alice").public(), 100)],
+	}.assimilate_storage(&mut storage).unwrap();
+
+	let mut ext: sp_io::TestExternalities = storage.into();
+	ext.execute_with(|| System::set_block_number(1));
+	ext
+}
+
+/// The executor key derived from `seed`.
+pub fn executor(seed: &str) -> ExecutorPair {
+	ExecutorPair::from_string(&format!("//{}", seed), None).unwrap()
+}
+
+/// Proof that `executor` agrees to be registered by `owner`.
+pub fn ownership_proof(executor: &ExecutorPair, owner: u64) -> ExecutorSignature {
+	executor.sign(&owner.encode())
+}
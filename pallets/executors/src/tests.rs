@@ -0,0 +1,136 @@
+use crate::{Error, ExecutorInfo, mock::*};
+use frame_support::{assert_noop, assert_ok};
+use sp_core::Pair;
+use sp_executor::{ExecutorRegistry, OnFraudProof};
+
+#[test]
+fn genesis_executors_are_bonded() {
+	new_test_ext().execute_with(|| {
+		let alice = executor("Alice").public();
+
+		assert_eq!(
+			Executors::executors(&alice),
+			Some(ExecutorInfo { owner: ALICE, bond: 100, unlocking_at: None }),
+		);
+		assert_eq!(Balances::reserved_balance(ALICE), 100);
+		assert!(Executors::is_active(&alice));
+		assert!(!Executors::is_active(&executor("Bob").public()));
+	});
+}
+
+#[test]
+fn register_reserves_the_bond() {
+	new_test_ext().execute_with(|| {
+		let bob = executor("Bob");
+
+		assert_ok!(Executors::register(Origin::signed(BOB), bob.public(), ownership_proof(&bob, BOB), 200));
+
+		assert_eq!(Balances::reserved_balance(BOB), 200);
+		assert_eq!(Executors::executor_of(BOB), Some(bob.public()));
+		assert!(Executors::is_active(&bob.public()));
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_executors(crate::Event::ExecutorRegistered(BOB, bob.public(), 200)),
+		);
+	});
+}
+
+#[test]
+fn register_checks_the_key_and_the_bond() {
+	new_test_ext().execute_with(|| {
+		let bob = executor("Bob");
+
+		// signed by the key for another account.
+		assert_noop!(
+			Executors::register(Origin::signed(BOB), bob.public(), ownership_proof(&bob, ALICE), 200),
+			Error::<Test>::BadSignature,
+		);
+		assert_noop!(
+			Executors::register(Origin::signed(BOB), bob.public(), ownership_proof(&bob, BOB), 99),
+			Error::<Test>::BondTooLow,
+		);
+		assert_noop!(
+			Executors::register(Origin::signed(BOB), bob.public(), ownership_proof(&bob, BOB), 2_000),
+			Error::<Test>::InsufficientBalance,
+		);
+
+		let alice = executor("Alice");
+		assert_noop!(
+			Executors::register(Origin::signed(BOB), alice.public(), ownership_proof(&alice, BOB), 200),
+			Error::<Test>::AlreadyRegistered,
+		);
+		assert_noop!(
+			Executors::register(Origin::signed(ALICE), bob.public(), ownership_proof(&bob, ALICE), 200),
+			Error::<Test>::AlreadyRegistered,
+		);
+	});
+}
+
+#[test]
+fn bond_is_withdrawn_after_the_unbonding_period() {
+	new_test_ext().execute_with(|| {
+		let alice = executor("Alice").public();
+
+		assert_noop!(Executors::withdraw(Origin::signed(ALICE)), Error::<Test>::NotUnbonding);
+		assert_ok!(Executors::unbond(Origin::signed(ALICE)));
+		assert_noop!(Executors::unbond(Origin::signed(ALICE)), Error::<Test>::AlreadyUnbonding);
+
+		// inactive right away, but still bonded.
+		assert!(!Executors::is_active(&alice));
+		assert_eq!(Balances::reserved_balance(ALICE), 100);
+
+		System::set_block_number(10);
+		assert_noop!(Executors::withdraw(Origin::signed(ALICE)), Error::<Test>::StillUnbonding);
+
+		System::set_block_number(11);
+		assert_ok!(Executors::withdraw(Origin::signed(ALICE)));
+		assert_eq!(Balances::reserved_balance(ALICE), 0);
+		assert_eq!(Balances::free_balance(ALICE), 1_000);
+		assert_eq!(Executors::executors(&alice), None);
+		assert_eq!(Executors::executor_of(ALICE), None);
+	});
+}
+
+#[test]
+fn unbond_requires_an_executor() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(Executors::unbond(Origin::signed(BOB)), Error::<Test>::NotRegistered);
+		assert_noop!(Executors::withdraw(Origin::signed(BOB)), Error::<Test>::NotRegistered);
+	});
+}
+
+#[test]
+fn fraud_proof_slashes_the_whole_bond() {
+	new_test_ext().execute_with(|| {
+		let alice = executor("Alice").public();
+
+		Executors::on_fraud_proof(&alice);
+
+		assert_eq!(Balances::reserved_balance(ALICE), 0);
+		assert_eq!(Balances::free_balance(ALICE), 900);
+		assert_eq!(Executors::executors(&alice), None);
+		assert_eq!(Executors::executor_of(ALICE), None);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_executors(crate::Event::ExecutorSlashed(alice.clone(), 100)),
+		);
+
+		// nothing left to slash.
+		Executors::on_fraud_proof(&alice);
+		assert_eq!(Balances::free_balance(ALICE), 900);
+	});
+}
+
+#[test]
+fn unbonding_executors_are_still_slashed() {
+	new_test_ext().execute_with(|| {
+		let alice = executor("Alice").public();
+
+		assert_ok!(Executors::unbond(Origin::signed(ALICE)));
+		Executors::on_fraud_proof(&alice);
+
+		System::set_block_number(11);
+		assert_noop!(Executors::withdraw(Origin::signed(ALICE)), Error::<Test>::NotRegistered);
+		assert_eq!(Balances::free_balance(ALICE), 900);
+	});
+}
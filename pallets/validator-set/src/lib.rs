@@ -0,0 +1,209 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Keeps the set of authorities authoring and finalizing blocks.
+//!
+//! The set is handed over to `pallet_session` at every session rotation, which passes the
+//! session keys of its members to Aura and GRANDPA, and records it in the historical sessions so
+//! that the ownership of past keys can be proven in equivocation reports.
+//!
+//! Authorities are added and removed by `AddRemoveOrigin`, e.g. root, and join or leave the set of
+//! Aura and GRANDPA at the session after the next one. An account joining the set has to register
+//! its session keys with `pallet_session` for that, see `author_rotateKeys` and `session.setKeys`.
+//!
+//! Authorities reported for an offence through `pallet_offences` are removed from the set the same
+//! way. The set is never shrunk below `MinAuthorities` though, for the chain to keep going.
+
+pub use pallet::*;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+#[frame_support::pallet]
+pub mod pallet {
+	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, weights::Weight};
+	use frame_system::pallet_prelude::*;
+	use sp_runtime::{Perbill, traits::Convert};
+	use sp_staking::{SessionIndex, offence::{OffenceDetails, OnOffenceHandler}};
+	use sp_std::{marker::PhantomData, vec::Vec};
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config {
+		/// Because this pallet emits events, it depends on the runtime's definition of an event.
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		/// The origin allowed to add and remove authorities.
+		type AddRemoveOrigin: EnsureOrigin<Self::Origin>;
+
+		/// The smallest the set of authorities can get by removing authorities or offenders.
+		#[pallet::constant]
+		type MinAuthorities: Get<u32>;
+	}
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T>(_);
+
+	/// The authorities of the next sessions.
+	#[pallet::storage]
+	#[pallet::getter(fn validators)]
+	pub type Validators<T: Config> = StorageValue<_, Vec<T::AccountId>, ValueQuery>;
+
+	#[pallet::genesis_config]
+	pub struct GenesisConfig<T: Config> {
+		/// The authorities from genesis.
+		pub validators: Vec<T::AccountId>,
+	}
+
+	#[cfg(feature = "std")]
+	impl<T: Config> Default for GenesisConfig<T> {
+		fn default() -> Self {
+			GenesisConfig { validators: Vec::new() }
+		}
+	}
+
+	#[pallet::genesis_build]
+	impl<T: Config> GenesisBuild<T> for GenesisConfig<T> {
+		fn build(&self) {
+			Validators::<T>::put(&self.validators);
+		}
+	}
+
+	#[pallet::event]
+	#[pallet::metadata(T::AccountId = "AccountId")]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		/// An authority was added to the set. [authority]
+		ValidatorAdded(T::AccountId),
+		/// An authority was removed from the set. [authority]
+		ValidatorRemoved(T::AccountId),
+		/// An authority was removed from the set for an offence. [authority, session]
+		OffenderRemoved(T::AccountId, SessionIndex),
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// The account is already an authority.
+		AlreadyValidator,
+		/// The account is no authority.
+		NotValidator,
+		/// Removing the authority would leave fewer than `MinAuthorities`.
+		TooFewValidators,
+	}
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Add `validator` to the set of authorities, from the session after the next one on.
+		///
+		/// The account has to register its session keys before, or it will be left out.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
+		pub fn add_validator(origin: OriginFor<T>, validator: T::AccountId) -> DispatchResult {
+			T::AddRemoveOrigin::ensure_origin(origin)?;
+
+			Validators::<T>::try_mutate(|validators| -> DispatchResult {
+				ensure!(!validators.contains(&validator), Error::<T>::AlreadyValidator);
+				validators.push(validator.clone());
+				Ok(())
+			})?;
+
+			Self::deposit_event(Event::ValidatorAdded(validator));
+			Ok(())
+		}
+
+		/// Remove `validator` from the set of authorities, from the session after the next one
+		/// on.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
+		pub fn remove_validator(origin: OriginFor<T>, validator: T::AccountId) -> DispatchResult {
+			T::AddRemoveOrigin::ensure_origin(origin)?;
+
+			Validators::<T>::try_mutate(|validators| -> DispatchResult {
+				let position = validators.iter().position(|v| v == &validator)
+					.ok_or(Error::<T>::NotValidator)?;
+				ensure!(
+					validators.len() > T::MinAuthorities::get() as usize,
+					Error::<T>::TooFewValidators,
+				);
+				validators.remove(position);
+				Ok(())
+			})?;
+
+			Self::deposit_event(Event::ValidatorRemoved(validator));
+			Ok(())
+		}
+	}
+
+	impl<T: Config> pallet_session::SessionManager<T::AccountId> for Pallet<T> {
+		fn new_session(_new_index: SessionIndex) -> Option<Vec<T::AccountId>> {
+			Some(Validators::<T>::get())
+		}
+
+		fn end_session(_end_index: SessionIndex) {}
+
+		fn start_session(_start_index: SessionIndex) {}
+	}
+
+	/// Authorities aren't identified by anything but their account in the historical sessions.
+	impl<T: Config> pallet_session::historical::SessionManager<T::AccountId, ()> for Pallet<T> {
+		fn new_session(new_index: SessionIndex) -> Option<Vec<(T::AccountId, ())>> {
+			<Self as pallet_session::SessionManager<_>>::new_session(new_index)
+				.map(|validators| validators.into_iter().map(|validator| (validator, ())).collect())
+		}
+
+		fn end_session(_end_index: SessionIndex) {}
+
+		fn start_session(_start_index: SessionIndex) {}
+	}
+
+	impl<T: Config> OnOffenceHandler<T::AccountId, (T::AccountId, ()), Weight> for Pallet<T> {
+		/// Remove the offenders from the set, whatever the fraction of their stake the offence
+		/// would slash.
+		fn on_offence(
+			offenders: &[OffenceDetails<T::AccountId, (T::AccountId, ())>],
+			_slash_fraction: &[Perbill],
+			session: SessionIndex,
+		) -> Weight {
+			let mut validators = Validators::<T>::get();
+			let mut removed = Vec::new();
+			for OffenceDetails { offender: (offender, ()), .. } in offenders {
+				if validators.len() <= T::MinAuthorities::get() as usize {
+					break
+				}
+				if let Some(position) = validators.iter().position(|validator| validator == offender) {
+					validators.remove(position);
+					removed.push(offender.clone());
+				}
+			}
+
+			if !removed.is_empty() {
+				Validators::<T>::put(validators);
+			}
+			for offender in removed {
+				Self::deposit_event(Event::OffenderRemoved(offender, session));
+			}
+			T::DbWeight::get().reads_writes(1, 1)
+		}
+	}
+
+	/// Authorities are identified by their account in the session.
+	pub struct ValidatorOf<T>(PhantomData<T>);
+
+	impl<T: Config> Convert<T::AccountId, Option<T::AccountId>> for ValidatorOf<T> {
+		fn convert(account: T::AccountId) -> Option<T::AccountId> {
+			Some(account)
+		}
+	}
+
+	/// The full identification of authorities in the historical sessions, nothing.
+	pub struct FullIdentificationOf<T>(PhantomData<T>);
+
+	impl<T: Config> Convert<T::AccountId, Option<()>> for FullIdentificationOf<T> {
+		fn convert(_account: T::AccountId) -> Option<()> {
+			Some(())
+		}
+	}
+}
@@ -0,0 +1,103 @@
+use crate::{Error, mock::*};
+use frame_support::{assert_noop, assert_ok};
+use pallet_session::{SessionManager, historical};
+use sp_runtime::{Perbill, traits::BadOrigin};
+use sp_staking::offence::{OffenceDetails, OnOffenceHandler};
+
+fn offence(offender: u64) -> OffenceDetails<u64, (u64, ())> {
+	OffenceDetails { offender: (offender, ()), reporters: vec![DAVE] }
+}
+
+fn report(offenders: &[u64]) {
+	let offenders = offenders.iter().cloned().map(offence).collect::<Vec<_>>();
+	let fractions = vec![Perbill::from_percent(10); offenders.len()];
+	ValidatorSet::on_offence(&offenders, &fractions, 3);
+}
+
+#[test]
+fn sessions_get_the_genesis_authorities() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(
+			<ValidatorSet as SessionManager<_>>::new_session(1),
+			Some(vec![ALICE, BOB, CHARLIE]),
+		);
+		assert_eq!(
+			<ValidatorSet as historical::SessionManager<_, _>>::new_session(1),
+			Some(vec![(ALICE, ()), (BOB, ()), (CHARLIE, ())]),
+		);
+	});
+}
+
+#[test]
+fn offenders_leave_the_set() {
+	new_test_ext().execute_with(|| {
+		report(&[BOB]);
+
+		assert_eq!(ValidatorSet::validators(), vec![ALICE, CHARLIE]);
+		assert_eq!(
+			<ValidatorSet as SessionManager<_>>::new_session(4),
+			Some(vec![ALICE, CHARLIE]),
+		);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_validator_set(crate::Event::OffenderRemoved(BOB, 3)),
+		);
+	});
+}
+
+#[test]
+fn offences_of_others_change_nothing() {
+	new_test_ext().execute_with(|| {
+		report(&[DAVE]);
+
+		assert_eq!(ValidatorSet::validators(), vec![ALICE, BOB, CHARLIE]);
+		assert!(System::events().is_empty());
+	});
+}
+
+#[test]
+fn the_set_keeps_its_minimum_size() {
+	new_test_ext().execute_with(|| {
+		report(&[ALICE, BOB]);
+
+		// `BOB` would leave fewer than `MinAuthorities`.
+		assert_eq!(ValidatorSet::validators(), vec![BOB, CHARLIE]);
+		assert_eq!(System::events().len(), 1);
+	});
+}
+
+#[test]
+fn root_adds_authorities() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(ValidatorSet::add_validator(Origin::signed(ALICE), DAVE), BadOrigin);
+
+		assert_ok!(ValidatorSet::add_validator(Origin::root(), DAVE));
+
+		assert_eq!(
+			<ValidatorSet as SessionManager<_>>::new_session(1),
+			Some(vec![ALICE, BOB, CHARLIE, DAVE]),
+		);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_validator_set(crate::Event::ValidatorAdded(DAVE)),
+		);
+		assert_noop!(ValidatorSet::add_validator(Origin::root(), DAVE), Error::<Test>::AlreadyValidator);
+	});
+}
+
+#[test]
+fn root_removes_authorities() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(ValidatorSet::remove_validator(Origin::signed(ALICE), BOB), BadOrigin);
+		assert_noop!(ValidatorSet::remove_validator(Origin::root(), DAVE), Error::<Test>::NotValidator);
+
+		assert_ok!(ValidatorSet::remove_validator(Origin::root(), BOB));
+
+		assert_eq!(ValidatorSet::validators(), vec![ALICE, CHARLIE]);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_validator_set(crate::Event::ValidatorRemoved(BOB)),
+		);
+		assert_noop!(ValidatorSet::remove_validator(Origin::root(), ALICE), Error::<Test>::TooFewValidators);
+	});
+}
@@ -0,0 +1,415 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Elects the authorities authoring and finalizing blocks from the funds bonded by candidates.
+//!
+//! An account becomes a candidate by reserving a bond of at least `MinimumBond`. Every era of
+//! `SessionsPerEra` sessions, the `Invulnerables` and the candidates with the largest bonds, up to
+//! `MaxAuthorities` in all, are handed over to `pallet_session` as the authorities of the next
+//! sessions. Candidates have to register their session keys with `pallet_session` to be elected,
+//! see `author_rotateKeys` and `session.setKeys`. If nobody is left to elect, the authorities of
+//! the last era stay on.
+//!
+//! Block authors are rewarded with `BlockReward` for every block, through the event handler of
+//! `pallet_authorship`. Elected candidates that didn't author any block during their era, up to the
+//! election of the next one, are kicked before it: they stop being candidates, as if they
+//! unbonded.
+//!
+//! Candidates reported for an offence through `pallet_offences` lose the fraction of their bond
+//! the offence slashes, and are kicked as well. Candidates leaving stay in the set until the end
+//! of the era, and their bond stays reserved, and slashable, for `UnbondingPeriod` blocks.
+
+pub use pallet::*;
+
+/// Tells which accounts registered the session keys they would author and finalize with.
+pub trait HasSessionKeys<AccountId> {
+	/// Whether `who` has keys set for the next session.
+	fn has_session_keys(who: &AccountId) -> bool;
+}
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+#[frame_support::pallet]
+pub mod pallet {
+	use codec::{Decode, Encode};
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		traits::{Currency, Imbalance, OnUnbalanced, ReservableCurrency},
+		weights::Weight,
+	};
+	use frame_system::pallet_prelude::*;
+	use sp_runtime::{Perbill, RuntimeDebug, traits::Saturating};
+	use sp_staking::{SessionIndex, offence::{OffenceDetails, OnOffenceHandler}};
+	use sp_std::vec::Vec;
+	use super::HasSessionKeys;
+
+	pub(crate) type BalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+	type NegativeImbalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::NegativeImbalance;
+
+	/// Counter of the eras, starting at 0 with the genesis authorities.
+	pub type EraIndex = u32;
+
+	/// A candidate to the set of authorities.
+	#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+	pub struct CandidateInfo<Balance, BlockNumber> {
+		/// The reserved bond.
+		pub bond: Balance,
+		/// The block from which the bond can be withdrawn, once the candidate is unbonding.
+		pub unlocking_at: Option<BlockNumber>,
+	}
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config {
+		/// Because this pallet emits events, it depends on the runtime's definition of an event.
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		/// The currency bonds are reserved in, and rewards paid in.
+		type Currency: ReservableCurrency<Self::AccountId>;
+
+		/// The smallest bond a candidate can bond.
+		#[pallet::constant]
+		type MinimumBond: Get<BalanceOf<Self>>;
+
+		/// Number of blocks between the moment a candidate unbonds and the moment its bond can be
+		/// withdrawn. Should be at least the time offences can be reported for, for the reports
+		/// to still find something to slash.
+		#[pallet::constant]
+		type UnbondingPeriod: Get<Self::BlockNumber>;
+
+		/// Number of sessions the elected authorities stay for.
+		#[pallet::constant]
+		type SessionsPerEra: Get<SessionIndex>;
+
+		/// The largest the set of authorities can get, invulnerables included.
+		#[pallet::constant]
+		type MaxAuthorities: Get<u32>;
+
+		/// The amount minted for the author of every block.
+		#[pallet::constant]
+		type BlockReward: Get<BalanceOf<Self>>;
+
+		/// The authorities elected whatever their bond, e.g. those set by root.
+		type Invulnerables: Get<Vec<Self::AccountId>>;
+
+		/// The accounts with session keys, the only ones elected, e.g. `pallet_session::NextKeys`.
+		type SessionKeys: HasSessionKeys<Self::AccountId>;
+
+		/// Where the slashed bonds go.
+		type Slash: OnUnbalanced<NegativeImbalanceOf<Self>>;
+
+		/// Also told about the offences, e.g. to remove invulnerable offenders.
+		type OnOffence: OnOffenceHandler<Self::AccountId, (Self::AccountId, ()), Weight>;
+	}
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T>(_);
+
+	/// The candidates, by account.
+	#[pallet::storage]
+	#[pallet::getter(fn candidates)]
+	pub type Candidates<T: Config> = StorageMap<
+		_,
+		Blake2_128Concat,
+		T::AccountId,
+		CandidateInfo<BalanceOf<T>, T::BlockNumber>,
+	>;
+
+	/// The index of the last election.
+	#[pallet::storage]
+	#[pallet::getter(fn current_era)]
+	pub type CurrentEra<T: Config> = StorageValue<_, EraIndex, ValueQuery>;
+
+	/// The candidates elected for the current era, besides the invulnerables.
+	#[pallet::storage]
+	#[pallet::getter(fn elected)]
+	pub type Elected<T: Config> = StorageValue<_, Vec<T::AccountId>, ValueQuery>;
+
+	/// The candidates elected for the next era, until it starts.
+	#[pallet::storage]
+	pub type QueuedElected<T: Config> = StorageValue<_, Vec<T::AccountId>>;
+
+	/// The accounts that authored blocks since the start of the current era, until the next
+	/// election.
+	#[pallet::storage]
+	#[pallet::getter(fn authors)]
+	pub type Authors<T: Config> = StorageValue<_, Vec<T::AccountId>, ValueQuery>;
+
+	#[pallet::genesis_config]
+	pub struct GenesisConfig<T: Config> {
+		/// The candidates from genesis, with their bond.
+		pub candidates: Vec<(T::AccountId, BalanceOf<T>)>,
+	}
+
+	#[cfg(feature = "std")]
+	impl<T: Config> Default for GenesisConfig<T> {
+		fn default() -> Self {
+			GenesisConfig { candidates: Vec::new() }
+		}
+	}
+
+	#[pallet::genesis_build]
+	impl<T: Config> GenesisBuild<T> for GenesisConfig<T> {
+		fn build(&self) {
+			for (candidate, bond) in &self.candidates {
+				Pallet::<T>::do_bond(candidate.clone(), *bond)
+					.expect("Genesis candidates must be unique and able to afford their bond");
+			}
+		}
+	}
+
+	#[pallet::event]
+	#[pallet::metadata(T::AccountId = "AccountId", BalanceOf<T> = "Balance")]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		/// An account bonded funds to become a candidate. [candidate, bond]
+		CandidateBonded(T::AccountId, BalanceOf<T>),
+		/// A candidate started unbonding. [candidate, unlocking_at]
+		CandidateUnbonding(T::AccountId, T::BlockNumber),
+		/// The bond of a candidate was given back. [candidate, bond]
+		BondWithdrawn(T::AccountId, BalanceOf<T>),
+		/// The authorities of a new era were elected. [era, authorities]
+		AuthoritiesElected(EraIndex, Vec<T::AccountId>),
+		/// An elected candidate was kicked for authoring no block during its era. [candidate]
+		CandidateKicked(T::AccountId),
+		/// The bond of a candidate was slashed for an offence. [candidate, amount]
+		CandidateSlashed(T::AccountId, BalanceOf<T>),
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// The account is already a candidate.
+		AlreadyCandidate,
+		/// The bond is lower than `MinimumBond`.
+		BondTooLow,
+		/// The account can't afford the bond.
+		InsufficientBalance,
+		/// The account is no candidate.
+		NotCandidate,
+		/// The candidate is already unbonding.
+		AlreadyUnbonding,
+		/// The candidate has to unbond before withdrawing.
+		NotUnbonding,
+		/// The unbonding period is not over yet.
+		StillUnbonding,
+	}
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Make the origin a candidate to the set of authorities, reserving `bond` from its
+		/// balance. It can be elected from the next era on.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 2))]
+		pub fn bond(origin: OriginFor<T>, #[pallet::compact] bond: BalanceOf<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+
+			Self::do_bond(who.clone(), bond)?;
+
+			Self::deposit_event(Event::CandidateBonded(who, bond));
+			Ok(())
+		}
+
+		/// Stop the origin from being elected. Its bond can be withdrawn once the unbonding period
+		/// is over.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
+		pub fn unbond(origin: OriginFor<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+
+			let info = Candidates::<T>::get(&who).ok_or(Error::<T>::NotCandidate)?;
+			ensure!(info.unlocking_at.is_none(), Error::<T>::AlreadyUnbonding);
+			let unlocking_at = Self::chill(&who);
+
+			Self::deposit_event(Event::CandidateUnbonding(who, unlocking_at));
+			Ok(())
+		}
+
+		/// Give the bond of the origin back, once it is done unbonding.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 2))]
+		pub fn withdraw(origin: OriginFor<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+
+			let info = Candidates::<T>::get(&who).ok_or(Error::<T>::NotCandidate)?;
+			let unlocking_at = info.unlocking_at.ok_or(Error::<T>::NotUnbonding)?;
+			ensure!(
+				frame_system::Pallet::<T>::block_number() >= unlocking_at,
+				Error::<T>::StillUnbonding,
+			);
+
+			T::Currency::unreserve(&who, info.bond);
+			Candidates::<T>::remove(&who);
+
+			Self::deposit_event(Event::BondWithdrawn(who, info.bond));
+			Ok(())
+		}
+	}
+
+	impl<T: Config> Pallet<T> {
+		fn do_bond(who: T::AccountId, bond: BalanceOf<T>) -> DispatchResult {
+			ensure!(!Candidates::<T>::contains_key(&who), Error::<T>::AlreadyCandidate);
+			ensure!(bond >= T::MinimumBond::get(), Error::<T>::BondTooLow);
+			T::Currency::reserve(&who, bond).map_err(|_| Error::<T>::InsufficientBalance)?;
+
+			Candidates::<T>::insert(&who, CandidateInfo { bond, unlocking_at: None });
+			Ok(())
+		}
+
+		/// Start the unbonding of `who`, if it isn't unbonding already, returning the block its
+		/// bond unlocks at.
+		fn chill(who: &T::AccountId) -> T::BlockNumber {
+			let unlocking_at = frame_system::Pallet::<T>::block_number()
+				.saturating_add(T::UnbondingPeriod::get());
+			Candidates::<T>::mutate(who, |info| match info {
+				Some(info) => *info.unlocking_at.get_or_insert(unlocking_at),
+				None => unlocking_at,
+			})
+		}
+
+		/// Kick the candidates elected for the current era that authored no block during it so far.
+		fn kick_inactive() {
+			let authors = Authors::<T>::take();
+			for candidate in Elected::<T>::get() {
+				let bonded = Candidates::<T>::get(&candidate).map_or(false, |info| info.unlocking_at.is_none());
+				if bonded && !authors.contains(&candidate) {
+					Self::chill(&candidate);
+					Self::deposit_event(Event::CandidateKicked(candidate));
+				}
+			}
+		}
+
+		/// The invulnerables, and the candidates with the largest bonds in the remaining seats, as
+		/// long as they have session keys. `None` if nobody has.
+		fn elect() -> Option<Vec<T::AccountId>> {
+			let mut authorities = T::Invulnerables::get();
+			authorities.retain(T::SessionKeys::has_session_keys);
+			authorities.truncate(T::MaxAuthorities::get() as usize);
+
+			let mut candidates = Candidates::<T>::iter()
+				.filter(|(candidate, info)| {
+					info.unlocking_at.is_none() &&
+						!authorities.contains(candidate) &&
+						T::SessionKeys::has_session_keys(candidate)
+				})
+				.map(|(candidate, info)| (candidate, info.bond))
+				.collect::<Vec<_>>();
+			// ties are broken by the storage order, the same for every node.
+			candidates.sort_by(|a, b| b.1.cmp(&a.1));
+
+			let seats = (T::MaxAuthorities::get() as usize).saturating_sub(authorities.len());
+			let elected = candidates.into_iter()
+				.take(seats)
+				.map(|(candidate, _)| candidate)
+				.collect::<Vec<_>>();
+			if authorities.is_empty() && elected.is_empty() {
+				return None
+			}
+			QueuedElected::<T>::put(&elected);
+
+			authorities.extend(elected);
+			Some(authorities)
+		}
+
+		/// Slash `fraction` of the bond of `who` and kick it, if it is a candidate.
+		fn slash(who: &T::AccountId, fraction: Perbill) {
+			let slashed = Candidates::<T>::mutate(who, |info| {
+				let info = info.as_mut()?;
+				let (imbalance, _) = T::Currency::slash_reserved(who, fraction * info.bond);
+				let slashed = imbalance.peek();
+				info.bond = info.bond.saturating_sub(slashed);
+				T::Slash::on_unbalanced(imbalance);
+				Some(slashed)
+			});
+
+			if let Some(slashed) = slashed {
+				Self::chill(who);
+				Self::deposit_event(Event::CandidateSlashed(who.clone(), slashed));
+			}
+		}
+	}
+
+	impl<T: Config> pallet_session::SessionManager<T::AccountId> for Pallet<T> {
+		/// Elect the authorities at the first session of every era, and keep them otherwise. The
+		/// inactive candidates of the current era are kicked first, so that they aren't elected
+		/// again.
+		fn new_session(new_index: SessionIndex) -> Option<Vec<T::AccountId>> {
+			if new_index % T::SessionsPerEra::get().max(1) != 0 {
+				return None
+			}
+
+			Self::kick_inactive();
+			// better keep the last authorities than stall the chain.
+			let authorities = Self::elect()?;
+
+			let era = new_index / T::SessionsPerEra::get().max(1);
+			CurrentEra::<T>::put(era);
+			Self::deposit_event(Event::AuthoritiesElected(era, authorities.clone()));
+			Some(authorities)
+		}
+
+		fn end_session(_end_index: SessionIndex) {}
+
+		/// Start tracking the authors of the candidates elected for the era starting, if any.
+		fn start_session(start_index: SessionIndex) {
+			if start_index % T::SessionsPerEra::get().max(1) == 0 {
+				if let Some(elected) = QueuedElected::<T>::take() {
+					Elected::<T>::put(elected);
+				}
+				Authors::<T>::kill();
+			}
+		}
+	}
+
+	/// Authorities aren't identified by anything but their account in the historical sessions.
+	impl<T: Config> pallet_session::historical::SessionManager<T::AccountId, ()> for Pallet<T> {
+		fn new_session(new_index: SessionIndex) -> Option<Vec<(T::AccountId, ())>> {
+			<Self as pallet_session::SessionManager<_>>::new_session(new_index)
+				.map(|authorities| authorities.into_iter().map(|authority| (authority, ())).collect())
+		}
+
+		fn end_session(end_index: SessionIndex) {
+			<Self as pallet_session::SessionManager<_>>::end_session(end_index)
+		}
+
+		fn start_session(start_index: SessionIndex) {
+			<Self as pallet_session::SessionManager<_>>::start_session(start_index)
+		}
+	}
+
+	impl<T: Config> pallet_authorship::EventHandler<T::AccountId, T::BlockNumber> for Pallet<T> {
+		/// Mint the reward of the author, and note it wasn't inactive.
+		fn note_author(author: T::AccountId) {
+			let _ = T::Currency::deposit_creating(&author, T::BlockReward::get());
+			Authors::<T>::mutate(|authors| if !authors.contains(&author) {
+				authors.push(author);
+			});
+		}
+
+		fn note_uncle(_author: T::AccountId, _age: T::BlockNumber) {}
+	}
+
+	impl<T: Config> OnOffenceHandler<T::AccountId, (T::AccountId, ()), Weight> for Pallet<T> {
+		/// Slash the offending candidates and kick them, before passing the offences on to
+		/// `T::OnOffence`.
+		fn on_offence(
+			offenders: &[OffenceDetails<T::AccountId, (T::AccountId, ())>],
+			slash_fraction: &[Perbill],
+			session: SessionIndex,
+		) -> Weight {
+			for (details, fraction) in offenders.iter().zip(slash_fraction) {
+				let (offender, ()) = &details.offender;
+				Self::slash(offender, *fraction);
+			}
+
+			let count = offenders.len() as Weight;
+			T::DbWeight::get().reads_writes(count, 2 * count)
+				.saturating_add(T::OnOffence::on_offence(offenders, slash_fraction, session))
+		}
+	}
+}
@@ -0,0 +1,135 @@
+use crate as pallet_validator_staking;
+use sp_core::H256;
+use frame_support::parameter_types;
+use sp_runtime::{
+	traits::{BlakeTwo256, IdentityLookup}, testing::Header,
+};
+use frame_system as system;
+use std::cell::RefCell;
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+// Configure a mock runtime to test the pallet.
+frame_support::construct_runtime!(
+	pub enum Test where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic,
+	{
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
+		Staking: pallet_validator_staking::{Pallet, Call, Storage, Config<T>, Event<T>},
+	}
+);
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const SS58Prefix: u8 = 42;
+}
+
+impl system::Config for Test {
+	type BaseCallFilter = ();
+	type BlockWeights = ();
+	type BlockLength = ();
+	type DbWeight = ();
+	type Origin = Origin;
+	type Call = Call;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type Version = ();
+	type PalletInfo = PalletInfo;
+	type AccountData = pallet_balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+	type SS58Prefix = SS58Prefix;
+	type OnSetCode = ();
+}
+
+parameter_types! {
+	pub const ExistentialDeposit: u64 = 1;
+	pub const MaxLocks: u32 = 50;
+}
+
+impl pallet_balances::Config for Test {
+	type MaxLocks = MaxLocks;
+	type Balance = u64;
+	type Event = Event;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+	type WeightInfo = ();
+}
+
+parameter_types! {
+	pub const MinimumBond: u64 = 100;
+	pub const UnbondingPeriod: u64 = 10;
+	pub const SessionsPerEra: u32 = 3;
+	pub const MaxAuthorities: u32 = 3;
+	pub const BlockReward: u64 = 5;
+	pub Invulnerables: Vec<u64> = vec![ALICE];
+}
+
+thread_local! {
+	/// Accounts that didn't set session keys, the others all did.
+	pub static WITHOUT_KEYS: RefCell<Vec<u64>> = RefCell::new(Vec::new());
+}
+
+pub struct MockSessionKeys;
+
+impl pallet_validator_staking::HasSessionKeys<u64> for MockSessionKeys {
+	fn has_session_keys(who: &u64) -> bool {
+		WITHOUT_KEYS.with(|without_keys| !without_keys.borrow().contains(who))
+	}
+}
+
+/// Drop the session keys of `who`.
+pub fn purge_keys(who: u64) {
+	WITHOUT_KEYS.with(|without_keys| without_keys.borrow_mut().push(who));
+}
+
+impl pallet_validator_staking::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+	type MinimumBond = MinimumBond;
+	type UnbondingPeriod = UnbondingPeriod;
+	type SessionsPerEra = SessionsPerEra;
+	type MaxAuthorities = MaxAuthorities;
+	type BlockReward = BlockReward;
+	type Invulnerables = Invulnerables;
+	type SessionKeys = MockSessionKeys;
+	type Slash = ();
+	type OnOffence = ();
+}
+
+/// Invulnerable authority.
+pub const ALICE: u64 = 1;
+/// Candidates from genesis, with bonds of 300, 200 and 100.
+pub const BOB: u64 = 2;
+pub const CHARLIE: u64 = 3;
+pub const DAVE: u64 = 4;
+/// Account with funds and no bond.
+pub const EVE: u64 = 5;
+
+// Build genesis storage according to the mock runtime.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut storage = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	pallet_balances::GenesisConfig::<Test> {
+		balances: vec![(ALICE, 1_000), (BOB, 1_000), (CHARLIE, 1_000), (DAVE, 1_000), (EVE, 1_000)],
+	}.assimilate_storage(&mut storage).unwrap();
+	pallet_validator_staking::GenesisConfig::<Test> {
+		candidates: vec![(BOB, 300), (CHARLIE, 200), (DAVE, 100)],
+	}.assimilate_storage(&mut storage).unwrap();
+
+	let mut ext: sp_io::TestExternalities = storage.into();
+	ext.execute_with(|| System::set_block_number(1));
+	ext
+}
@@ -0,0 +1,203 @@
+use crate::{CandidateInfo, Error, mock::*};
+use frame_support::{assert_noop, assert_ok};
+use pallet_authorship::EventHandler;
+use pallet_session::{SessionManager, historical};
+use sp_runtime::Perbill;
+use sp_staking::offence::{OffenceDetails, OnOffenceHandler};
+
+fn new_session(index: u32) -> Option<Vec<u64>> {
+	<Staking as SessionManager<_>>::new_session(index)
+}
+
+/// Go from the session before `index` to `index`, the way `pallet_session` does.
+fn rotate_to(index: u32) {
+	<Staking as SessionManager<_>>::end_session(index - 1);
+	<Staking as SessionManager<_>>::start_session(index);
+}
+
+fn report(offender: u64, fraction: Perbill) {
+	let offenders = vec![OffenceDetails { offender: (offender, ()), reporters: vec![EVE] }];
+	Staking::on_offence(&offenders, &[fraction], 3);
+}
+
+#[test]
+fn genesis_candidates_are_bonded() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(Staking::candidates(BOB), Some(CandidateInfo { bond: 300, unlocking_at: None }));
+		assert_eq!(Balances::reserved_balance(BOB), 300);
+		assert_eq!(Balances::reserved_balance(DAVE), 100);
+		assert_eq!(Staking::candidates(EVE), None);
+	});
+}
+
+#[test]
+fn bond_reserves_the_bond() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(Staking::bond(Origin::signed(EVE), 99), Error::<Test>::BondTooLow);
+		assert_noop!(Staking::bond(Origin::signed(EVE), 2_000), Error::<Test>::InsufficientBalance);
+		assert_noop!(Staking::bond(Origin::signed(BOB), 200), Error::<Test>::AlreadyCandidate);
+
+		assert_ok!(Staking::bond(Origin::signed(EVE), 400));
+
+		assert_eq!(Balances::reserved_balance(EVE), 400);
+		assert_eq!(Staking::candidates(EVE), Some(CandidateInfo { bond: 400, unlocking_at: None }));
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_validator_staking(crate::Event::CandidateBonded(EVE, 400)),
+		);
+	});
+}
+
+#[test]
+fn bond_is_withdrawn_after_the_unbonding_period() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(Staking::unbond(Origin::signed(EVE)), Error::<Test>::NotCandidate);
+		assert_noop!(Staking::withdraw(Origin::signed(BOB)), Error::<Test>::NotUnbonding);
+		assert_ok!(Staking::unbond(Origin::signed(BOB)));
+		assert_noop!(Staking::unbond(Origin::signed(BOB)), Error::<Test>::AlreadyUnbonding);
+
+		System::set_block_number(10);
+		assert_noop!(Staking::withdraw(Origin::signed(BOB)), Error::<Test>::StillUnbonding);
+
+		System::set_block_number(11);
+		assert_ok!(Staking::withdraw(Origin::signed(BOB)));
+		assert_eq!(Balances::reserved_balance(BOB), 0);
+		assert_eq!(Balances::free_balance(BOB), 1_000);
+		assert_eq!(Staking::candidates(BOB), None);
+	});
+}
+
+#[test]
+fn largest_bonds_are_elected_each_era() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(
+			<Staking as historical::SessionManager<_, _>>::new_session(0),
+			Some(vec![(ALICE, ()), (BOB, ()), (CHARLIE, ())]),
+		);
+		// elected once the era starts.
+		assert_eq!(Staking::elected(), Vec::<u64>::new());
+		<Staking as SessionManager<_>>::start_session(0);
+		assert_eq!(Staking::elected(), vec![BOB, CHARLIE]);
+
+		// the authorities are kept within the era.
+		assert_ok!(Staking::bond(Origin::signed(EVE), 400));
+		assert_eq!(new_session(1), None);
+		assert_eq!(new_session(2), None);
+
+		Staking::note_author(BOB);
+		Staking::note_author(CHARLIE);
+		assert_eq!(new_session(3), Some(vec![ALICE, EVE, BOB]));
+		assert_eq!(Staking::current_era(), 1);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_validator_staking(crate::Event::AuthoritiesElected(1, vec![ALICE, EVE, BOB])),
+		);
+	});
+}
+
+#[test]
+fn unbonding_candidates_are_not_elected() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Staking::unbond(Origin::signed(BOB)));
+
+		assert_eq!(new_session(3), Some(vec![ALICE, CHARLIE, DAVE]));
+	});
+}
+
+#[test]
+fn candidates_without_session_keys_are_not_elected() {
+	new_test_ext().execute_with(|| {
+		purge_keys(ALICE);
+		purge_keys(BOB);
+
+		assert_eq!(new_session(3), Some(vec![CHARLIE, DAVE]));
+	});
+}
+
+#[test]
+fn authorities_are_kept_when_nobody_can_be_elected() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(new_session(3), Some(vec![ALICE, BOB, CHARLIE]));
+		rotate_to(3);
+		Staking::note_author(BOB);
+		Staking::note_author(CHARLIE);
+		for account in vec![ALICE, BOB, CHARLIE, DAVE] {
+			purge_keys(account);
+		}
+
+		assert_eq!(new_session(6), None);
+		assert_eq!(Staking::current_era(), 1);
+		rotate_to(6);
+		assert_eq!(Staking::elected(), vec![BOB, CHARLIE]);
+	});
+}
+
+#[test]
+fn authors_are_rewarded() {
+	new_test_ext().execute_with(|| {
+		Staking::note_author(ALICE);
+		Staking::note_author(BOB);
+		Staking::note_author(BOB);
+
+		assert_eq!(Balances::free_balance(ALICE), 1_005);
+		assert_eq!(Balances::free_balance(BOB), 710);
+		assert_eq!(Balances::total_issuance(), 5_015);
+		assert_eq!(Staking::authors(), vec![ALICE, BOB]);
+	});
+}
+
+#[test]
+fn inactive_candidates_are_kicked() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(new_session(3), Some(vec![ALICE, BOB, CHARLIE]));
+		// blocks authored before the era starts don't count.
+		Staking::note_author(CHARLIE);
+		rotate_to(3);
+		// ALICE is invulnerable, authoring nothing is fine.
+		Staking::note_author(BOB);
+		rotate_to(4);
+		rotate_to(5);
+		assert_eq!(Staking::candidates(CHARLIE).unwrap().unlocking_at, None);
+
+		// the next era is elected during the last session of the current one, without the
+		// candidates kicked right before.
+		assert_eq!(new_session(6), Some(vec![ALICE, BOB, DAVE]));
+		assert_eq!(
+			Staking::candidates(CHARLIE),
+			Some(CandidateInfo { bond: 200, unlocking_at: Some(11) }),
+		);
+		assert!(System::events().iter().any(|record| record.event ==
+			Event::pallet_validator_staking(crate::Event::CandidateKicked(CHARLIE))
+		));
+		assert_eq!(Staking::authors(), Vec::<u64>::new());
+
+		rotate_to(6);
+		assert_eq!(Staking::elected(), vec![BOB, DAVE]);
+	});
+}
+
+#[test]
+fn offenders_are_slashed_and_kicked() {
+	new_test_ext().execute_with(|| {
+		report(BOB, Perbill::from_percent(50));
+
+		assert_eq!(Staking::candidates(BOB), Some(CandidateInfo { bond: 150, unlocking_at: Some(11) }));
+		assert_eq!(Balances::reserved_balance(BOB), 150);
+		assert_eq!(Balances::free_balance(BOB), 700);
+		assert_eq!(
+			System::events().last().unwrap().event,
+			Event::pallet_validator_staking(crate::Event::CandidateSlashed(BOB, 150)),
+		);
+		assert_eq!(new_session(3), Some(vec![ALICE, CHARLIE, DAVE]));
+	});
+}
+
+#[test]
+fn offences_of_others_slash_nothing() {
+	new_test_ext().execute_with(|| {
+		report(ALICE, Perbill::from_percent(50));
+
+		assert_eq!(Balances::free_balance(ALICE), 1_000);
+		assert!(System::events().is_empty());
+	});
+}
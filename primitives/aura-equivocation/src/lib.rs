@@ -0,0 +1,75 @@
+//! Primitives for reporting Aura authorities that author several blocks for the same slot,
+//! shared by the runtime and the node.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+use sp_consensus_aura::digests::CompatibleDigestItem;
+use sp_core::crypto::KeyTypeId;
+use sp_runtime::{RuntimeAppPublic, traits::Header as HeaderT};
+use sp_std::vec::Vec;
+
+pub use sp_consensus_aura::sr25519::{AuthorityId, AuthoritySignature};
+pub use sp_consensus_slots::Slot;
+
+/// Key type of the authority keys whose ownership is proven in reports.
+pub const KEY_TYPE: KeyTypeId = sp_core::crypto::key_types::AURA;
+
+/// Proof that an authority sealed two different headers for the same slot.
+pub type EquivocationProof<Header> = sp_consensus_slots::EquivocationProof<Header, AuthorityId>;
+
+/// Proof of the ownership of an authority key, opaque to the node.
+#[derive(Decode, Encode, PartialEq)]
+pub struct OpaqueKeyOwnershipProof(Vec<u8>);
+
+impl OpaqueKeyOwnershipProof {
+	/// Wrap the encoded proof.
+	pub fn new(inner: Vec<u8>) -> OpaqueKeyOwnershipProof {
+		OpaqueKeyOwnershipProof(inner)
+	}
+
+	/// Decode the proof into the runtime type.
+	pub fn decode<T: Decode>(self) -> Option<T> {
+		Decode::decode(&mut &self.0[..]).ok()
+	}
+}
+
+/// The slot `header` claims to be authored in.
+pub fn find_slot<Header: HeaderT>(header: &Header) -> Option<Slot> {
+	header.digest().logs().iter()
+		.find_map(CompatibleDigestItem::<AuthoritySignature>::as_aura_pre_digest)
+}
+
+/// The slot of the sealed `header`, if `author` is the one who sealed it.
+fn sealed_slot<Header: HeaderT>(header: &Header, author: &AuthorityId) -> Option<Slot> {
+	let mut header = header.clone();
+	let seal = header.digest_mut().pop()?;
+	let signature = CompatibleDigestItem::<AuthoritySignature>::as_aura_seal(&seal)?;
+	let slot = find_slot(&header)?;
+	// the seal signs the hash of the header without it.
+	author.verify(&header.hash(), &signature).then(|| slot)
+}
+
+/// Check that the headers of `proof` are different, and both sealed by the offender for the
+/// slot of the proof.
+pub fn check_equivocation_proof<Header: HeaderT>(proof: &EquivocationProof<Header>) -> bool {
+	proof.first_header.hash() != proof.second_header.hash()
+		&& sealed_slot(&proof.first_header, &proof.offender) == Some(proof.slot)
+		&& sealed_slot(&proof.second_header, &proof.offender) == Some(proof.slot)
+}
+
+sp_api::decl_runtime_apis! {
+	/// API to report Aura authorities authoring several blocks for the same slot.
+	pub trait AuraEquivocationApi {
+		/// Submit an unsigned extrinsic reporting the equivocation, along with the proof that the
+		/// offender key belongs to an authority, from `generate_key_ownership_proof`. Returns
+		/// `None` if the extrinsic couldn't be submitted.
+		fn submit_report_equivocation_unsigned_extrinsic(
+			equivocation_proof: EquivocationProof<Block::Header>,
+			key_owner_proof: OpaqueKeyOwnershipProof,
+		) -> Option<()>;
+
+		/// Prove that `authority_id` is the key of an authority of the current session, if it is.
+		fn generate_key_ownership_proof(authority_id: AuthorityId) -> Option<OpaqueKeyOwnershipProof>;
+	}
+}
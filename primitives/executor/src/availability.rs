@@ -0,0 +1,251 @@
+//! Data availability of the bundles ordered in a block.
+//!
+//! The bundles of a block are erasure coded into [`TOTAL_CHUNKS`] chunks, any [`DATA_CHUNKS`] of
+//! which are enough to get the bundles back. The runtime commits to the chunks in the header
+//! digest of the block, as the root of the trie of the chunks by index along with the length of
+//! the data. Nodes can then check that the bundles are available by sampling a few chunks from
+//! their peers, each coming with a proof against the commitment, without downloading the bundles
+//! themselves: with more than `TOTAL_CHUNKS - DATA_CHUNKS` chunks withheld, each sample fails
+//! with a probability over one half.
+//!
+//! The code is a systematic Reed-Solomon code over GF(2^8): the data is split into
+//! `DATA_CHUNKS` chunks, read column by column as the values of polynomials at the points `0` to
+//! `DATA_CHUNKS - 1`, and the other chunks are the values of the same polynomials at the
+//! following points.
+
+use codec::{Compact, Decode, Encode};
+use sp_core::H256;
+use sp_runtime::{
+	ConsensusEngineId, DigestItem, RuntimeDebug,
+	traits::{BlakeTwo256, Hash as HashT},
+};
+use sp_std::vec::Vec;
+use sp_trie::{Layout, TrieConfiguration};
+use crate::SignedOpaqueBundle;
+
+/// Engine id of the availability commitments in the header digest.
+pub const AVAILABILITY_ENGINE_ID: ConsensusEngineId = *b"DAVL";
+
+/// Number of chunks the bundles of a block are erasure coded into.
+pub const TOTAL_CHUNKS: usize = 16;
+
+/// Number of chunks needed to get the bundles of a block back.
+pub const DATA_CHUNKS: usize = 8;
+
+/// Commitment of a block to the chunks of its bundles.
+#[derive(Decode, Encode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub struct AvailabilityCommitment {
+	/// Root of the trie of the chunks, keyed by their compact encoded index.
+	pub chunks_root: H256,
+	/// Length of the erasure coded data, the encoded bundles.
+	pub data_len: u32,
+}
+
+impl AvailabilityCommitment {
+	/// The digest item carrying the commitment.
+	pub fn to_digest_item<Hash>(&self) -> DigestItem<Hash> {
+		DigestItem::Consensus(AVAILABILITY_ENGINE_ID, self.encode())
+	}
+}
+
+/// The availability commitment in the given digest items, if any.
+pub fn find_commitment<'a, Hash: 'a>(
+	logs: impl IntoIterator<Item = &'a DigestItem<Hash>>,
+) -> Option<AvailabilityCommitment> {
+	logs.into_iter().find_map(|log| match log {
+		DigestItem::Consensus(id, data) if id == &AVAILABILITY_ENGINE_ID =>
+			AvailabilityCommitment::decode(&mut &data[..]).ok(),
+		_ => None,
+	})
+}
+
+/// A chunk of erasure coded data along with the proof of it against the commitment.
+#[derive(Decode, Encode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ChunkWithProof {
+	/// Index of the chunk.
+	pub index: u32,
+	/// The chunk.
+	pub chunk: Vec<u8>,
+	/// Proof of the chunk in the trie of the chunks.
+	pub proof: Vec<Vec<u8>>,
+}
+
+impl ChunkWithProof {
+	/// Check the chunk against `commitment`.
+	pub fn verify(&self, commitment: &AvailabilityCommitment) -> bool {
+		let chunk_len = chunk_len(commitment.data_len as usize);
+		if self.index as usize >= TOTAL_CHUNKS || self.chunk.len() != chunk_len {
+			return false
+		}
+
+		let item = (chunk_key(self.index as usize), Some(&self.chunk));
+		sp_trie::verify_trie_proof::<Layout<BlakeTwo256>, _, _, _>(
+			&commitment.chunks_root,
+			&self.proof,
+			&[item],
+		).is_ok()
+	}
+}
+
+fn chunk_key(index: usize) -> Vec<u8> {
+	Compact(index as u32).encode()
+}
+
+/// Length of the chunks of `data_len` bytes of data.
+fn chunk_len(data_len: usize) -> usize {
+	((data_len + DATA_CHUNKS - 1) / DATA_CHUNKS).max(1)
+}
+
+/// Erasure code `data` into [`TOTAL_CHUNKS`] chunks.
+pub fn erasure_code(data: &[u8]) -> Vec<Vec<u8>> {
+	let chunk_len = chunk_len(data.len());
+	let mut chunks = Vec::with_capacity(TOTAL_CHUNKS);
+	for index in 0..DATA_CHUNKS {
+		let start = (index * chunk_len).min(data.len());
+		let end = ((index + 1) * chunk_len).min(data.len());
+		let mut chunk = data[start..end].to_vec();
+		chunk.resize(chunk_len, 0);
+		chunks.push(chunk);
+	}
+
+	let points = (0..DATA_CHUNKS).collect::<Vec<_>>();
+	for index in DATA_CHUNKS..TOTAL_CHUNKS {
+		let chunk = interpolate(&points, &chunks, index);
+		chunks.push(chunk);
+	}
+	chunks
+}
+
+/// Get the data of `data_len` bytes back from at least [`DATA_CHUNKS`] of its chunks, by index.
+/// Chunks are assumed to be checked against the commitment already.
+pub fn reconstruct(chunks: &[(usize, Vec<u8>)], data_len: usize) -> Option<Vec<u8>> {
+	let chunk_len = chunk_len(data_len);
+	let mut points = Vec::with_capacity(DATA_CHUNKS);
+	let mut known = Vec::with_capacity(DATA_CHUNKS);
+	for (index, chunk) in chunks {
+		if *index < TOTAL_CHUNKS && chunk.len() == chunk_len && !points.contains(index) {
+			points.push(*index);
+			known.push(chunk.clone());
+		}
+		if points.len() == DATA_CHUNKS {
+			break
+		}
+	}
+	if points.len() < DATA_CHUNKS {
+		return None
+	}
+
+	let mut data = Vec::with_capacity(DATA_CHUNKS * chunk_len);
+	for index in 0..DATA_CHUNKS {
+		match points.iter().position(|point| *point == index) {
+			Some(position) => data.extend_from_slice(&known[position]),
+			None => data.extend(interpolate(&points, &known, index)),
+		}
+	}
+	data.truncate(data_len);
+	Some(data)
+}
+
+/// The commitment to the erasure coded `data`, and its chunks.
+pub fn commit(data: &[u8]) -> (AvailabilityCommitment, Vec<Vec<u8>>) {
+	let chunks = erasure_code(data);
+	let commitment = AvailabilityCommitment {
+		chunks_root: BlakeTwo256::ordered_trie_root(chunks.clone()),
+		data_len: data.len() as u32,
+	};
+	(commitment, chunks)
+}
+
+/// The commitment to the given bundles of a block, in block order, and their chunks. Blocks
+/// without bundles commit to nothing.
+pub fn commit_bundles(bundles: &[SignedOpaqueBundle]) -> Option<(AvailabilityCommitment, Vec<Vec<u8>>)> {
+	if bundles.is_empty() {
+		return None
+	}
+	Some(commit(&bundles.encode()))
+}
+
+/// The proof of the chunk at `index` in the trie of `chunks`.
+#[cfg(feature = "std")]
+pub fn chunk_proof(chunks: &[Vec<u8>], index: usize) -> Result<Vec<Vec<u8>>, String> {
+	use sp_trie::{MemoryDB, TrieDBMut, TrieMut};
+
+	let mut db = MemoryDB::<BlakeTwo256>::default();
+	let mut root = H256::default();
+	{
+		let mut trie = TrieDBMut::<Layout<BlakeTwo256>>::new(&mut db, &mut root);
+		for (index, chunk) in chunks.iter().enumerate() {
+			trie.insert(&chunk_key(index), chunk).map_err(|e| e.to_string())?;
+		}
+	}
+	if root != Layout::<BlakeTwo256>::ordered_trie_root(chunks) {
+		return Err("The trie of the chunks doesn't match their root".into())
+	}
+
+	sp_trie::generate_trie_proof::<Layout<BlakeTwo256>, _, _, _>(&db, root, &[chunk_key(index)])
+		.map_err(|e| e.to_string())
+}
+
+/// Evaluate at `target` the polynomials taking the values of `chunks` at `points`, byte by
+/// byte, through Lagrange interpolation.
+fn interpolate(points: &[usize], chunks: &[Vec<u8>], target: usize) -> Vec<u8> {
+	let target = target as u8;
+	let coefficients = points.iter().map(|&j| {
+		let j = j as u8;
+		points.iter().filter(|&&m| m as u8 != j).fold(1, |product, &m| {
+			let m = m as u8;
+			gf::mul(product, gf::div(target ^ m, j ^ m))
+		})
+	}).collect::<Vec<_>>();
+
+	let mut chunk = sp_std::vec![0u8; chunks[0].len()];
+	for (coefficient, source) in coefficients.into_iter().zip(chunks) {
+		if coefficient == 0 {
+			continue
+		}
+		for (byte, value) in chunk.iter_mut().zip(source) {
+			*byte ^= gf::mul(coefficient, *value);
+		}
+	}
+	chunk
+}
+
+/// Arithmetic in GF(2^8), modulo `x^8 + x^4 + x^3 + x^2 + 1`.
+mod gf {
+	const fn tables() -> ([u8; 512], [u8; 256]) {
+		let mut exp = [0u8; 512];
+		let mut log = [0u8; 256];
+		let mut x: u16 = 1;
+		let mut i = 0;
+		while i < 255 {
+			exp[i] = x as u8;
+			exp[i + 255] = x as u8;
+			log[x as usize] = i as u8;
+			x <<= 1;
+			if x & 0x100 != 0 {
+				x ^= 0x11d;
+			}
+			i += 1;
+		}
+		(exp, log)
+	}
+
+	const TABLES: ([u8; 512], [u8; 256]) = tables();
+
+	pub fn mul(a: u8, b: u8) -> u8 {
+		if a == 0 || b == 0 {
+			return 0
+		}
+		let (exp, log) = &TABLES;
+		exp[log[a as usize] as usize + log[b as usize] as usize]
+	}
+
+	/// `a / b`, for a non-zero `b`.
+	pub fn div(a: u8, b: u8) -> u8 {
+		if a == 0 {
+			return 0
+		}
+		let (exp, log) = &TABLES;
+		exp[log[a as usize] as usize + 255 - log[b as usize] as usize]
+	}
+}
@@ -0,0 +1,509 @@
+//! Primitives of the decoupled execution experiment, shared by the runtime and the node.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+use sp_core::{H256, crypto::KeyTypeId};
+use sp_runtime::{
+	OpaqueExtrinsic, RuntimeAppPublic, RuntimeDebug,
+	traits::{BlakeTwo256, Block as BlockT, Hash as HashT, NumberFor},
+	transaction_validity::TransactionValidityError,
+};
+use sp_runtime_interface::runtime_interface;
+use sp_std::vec::Vec;
+use sp_trie::{Layout, MemoryDB, StorageProof, TrieConfiguration, TrieDBMut, TrieMut};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+pub use sp_trie::CompactProof;
+
+pub mod availability;
+
+/// Key type of executors.
+pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"exec");
+
+mod app {
+	use sp_application_crypto::{app_crypto, sr25519};
+	app_crypto!(sr25519, super::KEY_TYPE);
+}
+
+/// An executor keypair.
+#[cfg(feature = "std")]
+pub type ExecutorPair = app::Pair;
+
+/// An executor identifier.
+pub type ExecutorId = app::Public;
+
+/// Signature of an executor.
+pub type ExecutorSignature = app::Signature;
+
+/// The result of executing a block, as claimed by an executor.
+#[derive(Decode, Encode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ExecutionReceipt<Number, Hash> {
+	/// Number of the executed block.
+	pub primary_number: Number,
+	/// Hash of the executed block.
+	pub primary_hash: Hash,
+	/// Root of the execution state after executing the block.
+	pub state_root: Hash,
+	/// Roots of the execution state before the block, the parent one, and after each step of the
+	/// block, the last one being `state_root`, see [`ExecutionLayout`].
+	pub trace: Vec<Hash>,
+}
+
+impl<Number: Encode, Hash: Encode> ExecutionReceipt<Number, Hash> {
+	/// Hash of the receipt, which is what executors sign.
+	pub fn hash(&self) -> H256 {
+		BlakeTwo256::hash_of(self)
+	}
+
+	/// Merkle root of the execution trace. Two receipts with the same trace root agree on every
+	/// intermediate state of the block.
+	pub fn trace_root(&self) -> H256 {
+		BlakeTwo256::ordered_trie_root(self.trace.iter().map(Encode::encode).collect())
+	}
+}
+
+/// A block whose execution is settled on chain: the receipts of the block left once their
+/// challenge period is over agree on its state root.
+#[derive(Decode, Encode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ConfirmedBlock<Number, Hash> {
+	/// Number of the block.
+	pub number: Number,
+	/// Hash of the block.
+	pub hash: Hash,
+	/// Root of the execution state after executing the block, as claimed by its receipts.
+	pub state_root: Hash,
+}
+
+/// An execution receipt signed by the executor making the claim.
+#[derive(Decode, Encode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct SignedExecutionReceipt<Number, Hash> {
+	/// The claim.
+	pub execution_receipt: ExecutionReceipt<Number, Hash>,
+	/// The executor making the claim.
+	pub signer: ExecutorId,
+	/// Signature of the receipt hash by `signer`.
+	pub signature: ExecutorSignature,
+}
+
+impl<Number: Encode, Hash: Encode> SignedExecutionReceipt<Number, Hash> {
+	/// Check the signature of the receipt.
+	pub fn verify(&self) -> bool {
+		self.signer.verify(&self.execution_receipt.hash(), &self.signature)
+	}
+}
+
+/// Transactions collected by an executor, ordered by consensus nodes as a single extrinsic
+/// without looking into its contents.
+#[derive(Decode, Encode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct Bundle<Extrinsic> {
+	/// The transactions, in the order they are to be applied.
+	pub extrinsics: Vec<Extrinsic>,
+}
+
+impl<Extrinsic: Encode> Bundle<Extrinsic> {
+	/// Hash of the bundle, which is what executors sign.
+	pub fn hash(&self) -> H256 {
+		BlakeTwo256::hash_of(self)
+	}
+}
+
+/// A bundle signed by the executor who collected it.
+#[derive(Decode, Encode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct SignedBundle<Extrinsic> {
+	/// The bundle.
+	pub bundle: Bundle<Extrinsic>,
+	/// The executor who collected the bundle.
+	pub signer: ExecutorId,
+	/// Signature of the bundle hash by `signer`.
+	pub signature: ExecutorSignature,
+}
+
+impl<Extrinsic: Encode> SignedBundle<Extrinsic> {
+	/// Check the signature of the bundle.
+	pub fn verify(&self) -> bool {
+		self.signer.verify(&self.bundle.hash(), &self.signature)
+	}
+}
+
+/// A bundle whose transactions are kept encoded, the way it is ordered on chain.
+pub type SignedOpaqueBundle = SignedBundle<OpaqueExtrinsic>;
+
+/// Finds the bundles among the extrinsics of a block, which the runtime and the node have to
+/// agree on for [availability](availability) commitments.
+pub trait ExtractBundle {
+	/// The bundle the encoded `extrinsic` submits, if it is an unsigned `submit_bundle` call.
+	fn extract_bundle(extrinsic: &[u8]) -> Option<SignedOpaqueBundle>;
+}
+
+/// Finds no bundle at all.
+impl ExtractBundle for () {
+	fn extract_bundle(_extrinsic: &[u8]) -> Option<SignedOpaqueBundle> {
+		None
+	}
+}
+
+/// Finds the execution receipts and fraud proofs among the extrinsics of a block, for nodes
+/// following the execution of blocks they don't have the state of.
+pub trait ExtractExecution<Number, Hash> {
+	/// The receipt the encoded `extrinsic` submits, if it is an unsigned
+	/// `submit_execution_receipt` call.
+	fn extract_receipt(extrinsic: &[u8]) -> Option<SignedExecutionReceipt<Number, Hash>>;
+
+	/// The fraud proof the encoded `extrinsic` submits, if it is an unsigned `submit_fraud_proof`
+	/// call.
+	fn extract_fraud_proof(extrinsic: &[u8]) -> Option<FraudProof<Number, Hash>>;
+}
+
+/// A step of the execution of a block, after which executors record the root of the execution
+/// state in the trace of their receipts.
+#[derive(Decode, Encode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum ExecutionStep {
+	/// Initialize the block.
+	InitializeBlock,
+	/// Apply the extrinsic at the given index of the block.
+	ApplyExtrinsic(u32),
+	/// Apply the transaction at the given position, in the order transactions are applied in, of
+	/// the bundle submitted by the extrinsic at the given index of the block.
+	ApplyBundledExtrinsic(u32, u32),
+	/// Finalize the block.
+	FinalizeBlock,
+}
+
+impl ExecutionStep {
+	/// Index of the extrinsic of the block the step applies, or whose bundle it applies a
+	/// transaction of. `None` for the initialization and finalization of the block.
+	pub fn extrinsic_index(&self) -> Option<u32> {
+		match self {
+			ExecutionStep::ApplyExtrinsic(index) => Some(*index),
+			ExecutionStep::ApplyBundledExtrinsic(index, _) => Some(*index),
+			ExecutionStep::InitializeBlock | ExecutionStep::FinalizeBlock => None,
+		}
+	}
+}
+
+/// The steps executing a block goes through: its initialization, each of its extrinsics, the ones
+/// submitting a bundle followed by the transactions of the bundle, and its finalization.
+///
+/// The consensus state only goes through the extrinsics. The transactions of bundles are only
+/// applied to the execution state, which executors keep on their own.
+#[derive(Decode, Encode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct ExecutionLayout {
+	/// Number of extrinsics of the block.
+	pub extrinsics: u32,
+	/// The index of each extrinsic of the block submitting a bundle, in order, along with the
+	/// number of transactions of the bundle.
+	pub bundles: Vec<(u32, u32)>,
+}
+
+impl ExecutionLayout {
+	/// The layout of a block with the given encoded extrinsics, whose bundles `extract_bundle`
+	/// finds.
+	pub fn new<Extrinsic: AsRef<[u8]>>(
+		extrinsics: &[Extrinsic],
+		extract_bundle: impl Fn(&[u8]) -> Option<SignedOpaqueBundle>,
+	) -> Self {
+		let bundles = extrinsics.iter()
+			.enumerate()
+			.filter_map(|(index, extrinsic)| extract_bundle(extrinsic.as_ref())
+				.map(|bundle| (index as u32, bundle.bundle.extrinsics.len() as u32))
+			)
+			.collect();
+		ExecutionLayout { extrinsics: extrinsics.len() as u32, bundles }
+	}
+
+	/// The steps of the block, in order. `trace[0]` of a receipt is the root of the parent
+	/// execution state, `trace[i + 1]` the one after the step `i`.
+	pub fn steps(&self) -> Vec<ExecutionStep> {
+		let mut steps = Vec::new();
+		steps.push(ExecutionStep::InitializeBlock);
+		for index in 0..self.extrinsics {
+			steps.push(ExecutionStep::ApplyExtrinsic(index));
+			if let Some((_, transactions)) = self.bundle(index) {
+				steps.extend((0..transactions).map(|position| {
+					ExecutionStep::ApplyBundledExtrinsic(index, position)
+				}));
+			}
+		}
+		steps.push(ExecutionStep::FinalizeBlock);
+		steps
+	}
+
+	/// Number of steps of the block, the length of the traces of its receipts being one more.
+	pub fn step_count(&self) -> u32 {
+		let transactions = self.bundles.iter().map(|(_, transactions)| transactions).sum::<u32>();
+		self.extrinsics.saturating_add(transactions).saturating_add(2)
+	}
+
+	/// The index of `step` among the steps of the block, if the block has it.
+	pub fn index_of(&self, step: &ExecutionStep) -> Option<u32> {
+		let index = match step.extrinsic_index() {
+			Some(index) => index,
+			None if *step == ExecutionStep::InitializeBlock => return Some(0),
+			None => return Some(self.step_count() - 1),
+		};
+		if index >= self.extrinsics {
+			return None
+		}
+
+		// the initialization, the extrinsics before and their bundled transactions.
+		let before = 1 + index + self.bundles.iter()
+			.take_while(|(bundle_index, _)| *bundle_index < index)
+			.map(|(_, transactions)| transactions)
+			.sum::<u32>();
+		match step {
+			ExecutionStep::ApplyBundledExtrinsic(_, position) => self.bundle(index)
+				.filter(|(_, transactions)| position < transactions)
+				.map(|_| before + 1 + position),
+			_ => Some(before),
+		}
+	}
+
+	fn bundle(&self, index: u32) -> Option<(u32, u32)> {
+		self.bundles.iter().find(|(bundle_index, _)| *bundle_index == index).copied()
+	}
+}
+
+/// Proof that an execution receipt claims the wrong post state for one of the steps of a block.
+///
+/// The step at index `i` of the block moves the execution state from `trace[i]` to
+/// `trace[i + 1]` of the receipt. Re-executing it on top of the pre-state, given the storage it
+/// touches, yields a post state root that differs from the claimed one.
+///
+/// The extrinsic the step applies, or whose bundle it applies a transaction of, is proven against
+/// the extrinsics root of the block, the header the block is initialized with against the hash
+/// of the block. The runtime code the step is executed with is read from the pre-state proof, so
+/// that verifying the proof doesn't depend on what the verifier happens to have locally.
+#[derive(Decode, Encode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct FraudProof<Number, Hash> {
+	/// Number of the block the disputed receipt is about.
+	pub primary_number: Number,
+	/// Hash of the block the disputed receipt is about.
+	pub primary_hash: Hash,
+	/// The executor who signed the disputed receipt.
+	pub executor: ExecutorId,
+	/// The disputed step.
+	pub step: ExecutionStep,
+	/// The extrinsic of the block at `step.extrinsic_index()`, encoded as the argument of
+	/// `BlockBuilder_apply_extrinsic`. The encoded header of the block, seal included, for
+	/// [`ExecutionStep::InitializeBlock`], and nothing for [`ExecutionStep::FinalizeBlock`].
+	pub extrinsic: Vec<u8>,
+	/// Proof of `extrinsic` being at `step.extrinsic_index()` in the extrinsics root of the
+	/// block, see [`prove_extrinsic`]. Empty for the steps without an extrinsic.
+	pub extrinsic_proof: Vec<Vec<u8>>,
+	/// Root of the execution state before the step, as claimed by the receipt.
+	pub pre_state_root: Hash,
+	/// Root of the execution state after the step, as claimed by the receipt.
+	pub post_state_root: Hash,
+	/// Storage proof of the part of the pre-state the step touches, including the runtime code.
+	pub proof: StorageProof,
+}
+
+/// Prove that the extrinsic at `index` of a block with the given encoded `extrinsics` is part of
+/// its extrinsics root. Returns `None` if there is no such extrinsic.
+pub fn prove_extrinsic<H: sp_core::Hasher>(extrinsics: &[Vec<u8>], index: u32) -> Option<Vec<Vec<u8>>> {
+	if index as usize >= extrinsics.len() {
+		return None
+	}
+
+	let mut db = MemoryDB::<H>::default();
+	let mut root = Default::default();
+	{
+		let mut trie = TrieDBMut::<Layout<H>>::new(&mut db, &mut root);
+		for (i, extrinsic) in extrinsics.iter().enumerate() {
+			trie.insert(&Layout::<H>::encode_index(i as u32), extrinsic).ok()?;
+		}
+	}
+
+	sp_trie::generate_trie_proof::<Layout<H>, _, _, _>(&db, root, &[Layout::<H>::encode_index(index)]).ok()
+}
+
+/// Check that `proof` shows `extrinsic` to be at `index` in the given extrinsics root.
+pub fn verify_extrinsic<H: sp_core::Hasher>(
+	extrinsics_root: &H::Out,
+	proof: &[Vec<u8>],
+	index: u32,
+	extrinsic: &[u8],
+) -> bool {
+	sp_trie::verify_trie_proof::<Layout<H>, _, _, _>(
+		extrinsics_root,
+		proof,
+		&[(Layout::<H>::encode_index(index), Some(extrinsic))],
+	).is_ok()
+}
+
+/// The executors allowed to make execution claims on chain.
+pub trait ExecutorRegistry {
+	/// Whether `executor` may currently submit receipts and bundles.
+	fn is_active(executor: &ExecutorId) -> bool;
+}
+
+/// Lets anyone submit receipts and bundles.
+impl ExecutorRegistry for () {
+	fn is_active(_executor: &ExecutorId) -> bool {
+		true
+	}
+}
+
+/// Handler of the fraud proofs that turned out to be valid.
+pub trait OnFraudProof {
+	/// A fraud proof showed a receipt of `executor` to be wrong.
+	fn on_fraud_proof(executor: &ExecutorId);
+}
+
+impl OnFraudProof for () {
+	fn on_fraud_proof(_executor: &ExecutorId) {}
+}
+
+/// Re-executes the disputed extrinsic of fraud proofs on behalf of the runtime.
+#[cfg(feature = "std")]
+pub trait VerifyFraudProof {
+	/// Check whether the encoded [`FraudProof`] proves its receipt wrong, i.e. whether applying
+	/// the extrinsic on top of the proven pre-state leads to another post state root than the
+	/// claimed one.
+	fn verify_fraud_proof(&self, encoded_fraud_proof: &[u8]) -> Result<bool, String>;
+}
+
+#[cfg(feature = "std")]
+sp_externalities::decl_extension! {
+	/// Extension giving the runtime access to a [`VerifyFraudProof`] implementation.
+	pub struct FraudProofExtension(Arc<dyn VerifyFraudProof + Send + Sync>);
+}
+
+/// Executes blocks on top of a proof of their pre-state on behalf of the runtime.
+#[cfg(feature = "std")]
+pub trait ExecuteBlockStateless {
+	/// Execute the block encoded as its header followed by its extrinsics, the transactions of its
+	/// bundles included, on top of the execution pre-state proven by the encoded [`CompactProof`]
+	/// against `pre_state_root`, with the runtime code of that pre-state. Returns the encoded post
+	/// state root.
+	fn execute_block_stateless(
+		&self,
+		encoded_block: &[u8],
+		pre_state_root: &[u8],
+		encoded_proof: &[u8],
+	) -> Result<Vec<u8>, String>;
+}
+
+#[cfg(feature = "std")]
+sp_externalities::decl_extension! {
+	/// Extension giving the runtime access to an [`ExecuteBlockStateless`] implementation.
+	pub struct StatelessExecutionExtension(Arc<dyn ExecuteBlockStateless + Send + Sync>);
+}
+
+/// Host functions of the decoupled execution pipeline.
+#[runtime_interface]
+pub trait ExecutorExt {
+	/// Check whether the encoded [`FraudProof`] proves its receipt wrong, or `None` if it can't
+	/// be checked, e.g. because the fraud proof extension isn't registered.
+	fn verify_fraud_proof(&mut self, encoded_fraud_proof: &[u8]) -> Option<bool> {
+		use sp_externalities::ExternalitiesExt;
+
+		self.extension::<FraudProofExtension>()?
+			.verify_fraud_proof(encoded_fraud_proof)
+			.ok()
+	}
+
+	/// Execute the encoded block on top of the pre-state proven by the encoded [`CompactProof`],
+	/// returning the encoded post state root, or `None` if the block can't be executed on top of
+	/// the proof.
+	///
+	/// The block is executed with the runtime code of the proven pre-state, whatever the runtime of
+	/// the calling state.
+	fn execute_block_stateless(
+		&mut self,
+		encoded_block: &[u8],
+		pre_state_root: &[u8],
+		encoded_proof: &[u8],
+	) -> Option<Vec<u8>> {
+		use sp_externalities::ExternalitiesExt;
+
+		self.extension::<StatelessExecutionExtension>()?
+			.execute_block_stateless(encoded_block, pre_state_root, encoded_proof)
+			.ok()
+	}
+}
+
+/// Execute a block on top of a compact proof of its pre-state, returning the resulting state
+/// root. See [`executor_ext::execute_block_stateless`].
+pub fn execute_block_stateless<Block: BlockT>(
+	header: &Block::Header,
+	extrinsics: &[Block::Extrinsic],
+	pre_state_root: Block::Hash,
+	proof: &CompactProof,
+) -> Option<Block::Hash> {
+	let post_state_root = executor_ext::execute_block_stateless(
+		&(header, extrinsics).encode(),
+		pre_state_root.as_ref(),
+		&proof.encode(),
+	)?;
+
+	Decode::decode(&mut &post_state_root[..]).ok()
+}
+
+sp_api::decl_runtime_apis! {
+	/// API of the decoupled execution pipeline.
+	pub trait ExecutorApi {
+		/// Submit an execution receipt through an unsigned extrinsic.
+		fn submit_execution_receipt_unsigned(
+			execution_receipt: SignedExecutionReceipt<NumberFor<Block>, <Block as BlockT>::Hash>,
+		) -> Option<()>;
+
+		/// The receipts of the block with the given number still inside their challenge period.
+		fn execution_receipts(
+			number: NumberFor<Block>,
+		) -> Vec<(ExecutorId, ExecutionReceipt<NumberFor<Block>, <Block as BlockT>::Hash>)>;
+
+		/// The highest block with a receipt on chain, i.e. how far executors are known to have
+		/// executed the chain.
+		fn executed_head() -> NumberFor<Block>;
+
+		/// The highest block a majority of the executors have a receipt on chain for, none of
+		/// their receipts having been proven wrong.
+		fn majority_executed_head() -> NumberFor<Block>;
+
+		/// Number of blocks during which the receipts of a block can be challenged.
+		fn challenge_period() -> NumberFor<Block>;
+
+		/// The highest block whose execution is settled on chain, if any.
+		fn confirmed_head() -> Option<ConfirmedBlock<NumberFor<Block>, <Block as BlockT>::Hash>>;
+	}
+
+	/// API to submit bundles, and to apply their transactions.
+	pub trait BundleApi {
+		/// Submit a bundle through an unsigned extrinsic.
+		fn submit_bundle_unsigned(bundle: SignedOpaqueBundle) -> Option<()>;
+
+		/// Apply the transaction at `position`, in the order transactions are applied in, of the
+		/// bundle the encoded `extrinsic` submits. Called by executors on the execution state,
+		/// right after applying the extrinsic of the block, for each
+		/// [`ExecutionStep::ApplyBundledExtrinsic`].
+		fn apply_bundled_extrinsic(
+			extrinsic: Vec<u8>,
+			position: u32,
+		) -> Result<(), TransactionValidityError>;
+	}
+
+	/// API to execute blocks without their state.
+	pub trait StatelessExecutionApi {
+		/// Execute the block made of `header` and `extrinsics`, the transactions of its bundles
+		/// included, on top of the execution pre-state proven by `proof` against `pre_state_root`,
+		/// i.e. the root of the execution state of the parent block. Returns the resulting root,
+		/// or `None` if the proof doesn't cover the state the block touches.
+		fn execute_block_stateless(
+			header: <Block as BlockT>::Header,
+			extrinsics: Vec<<Block as BlockT>::Extrinsic>,
+			pre_state_root: <Block as BlockT>::Hash,
+			proof: CompactProof,
+		) -> Option<<Block as BlockT>::Hash>;
+	}
+
+	/// API to challenge execution receipts.
+	pub trait FraudProofApi {
+		/// Submit a fraud proof through an unsigned extrinsic.
+		fn submit_fraud_proof_unsigned(
+			fraud_proof: FraudProof<NumberFor<Block>, <Block as BlockT>::Hash>,
+		) -> Option<()>;
+	}
+}
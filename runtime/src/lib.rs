@@ -10,11 +10,17 @@ use sp_std::prelude::*;
 use sp_core::{crypto::KeyTypeId, OpaqueMetadata};
 use sp_runtime::{
 	ApplyExtrinsicResult, generic, create_runtime_str, impl_opaque_keys, MultiSignature,
-	transaction_validity::{TransactionValidity, TransactionSource},
+	TransactionOutcome,
+	transaction_validity::{
+		TransactionValidity, TransactionSource, TransactionPriority, TransactionValidityError,
+		InvalidTransaction,
+	},
 };
 use sp_runtime::traits::{
 	BlakeTwo256, Block as BlockT, AccountIdLookup, Verify, IdentifyAccount, NumberFor,
+	Applyable, Checkable, OpaqueKeys, StaticLookup,
 };
+use codec::{Decode, Encode};
 use sp_api::impl_runtime_apis;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
 use pallet_grandpa::{AuthorityId as GrandpaId, AuthorityList as GrandpaAuthorityList};
@@ -37,7 +43,18 @@ pub use frame_support::{
 		constants::{BlockExecutionWeight, ExtrinsicBaseWeight, RocksDbWeight, WEIGHT_PER_SECOND},
 	},
 };
+use frame_support::{
+	StorageHasher, Twox64Concat,
+	storage::{migration::{get_storage_value, take_storage_value}, with_transaction},
+	traits::{ChangeMembers, EnsureOrigin, Filter, Get, OnRuntimeUpgrade},
+	weights::GetDispatchInfo,
+};
+use sp_std::marker::PhantomData;
+use frame_system::{EnsureOneOf, EnsureRoot};
+use sp_core::u32_trait::{_1, _2, _3};
 use pallet_transaction_payment::CurrencyAdapter;
+use pallet_session::historical as pallet_session_historical;
+use sp_staking::SessionIndex;
 
 /// Import the template pallet.
 pub use pallet_template;
@@ -103,10 +120,11 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	//   `spec_version`, and `authoring_version` are the same between Wasm and native.
 	// This value is set to 100 to notify Polkadot-JS App (https://polkadot.js.org/apps) to use
 	//   the compatible custom types.
-	spec_version: 100,
+	spec_version: 101,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
-	transaction_version: 1,
+	// Bumped when the indices of the pallets or calls change, e.g. with the removal of sudo.
+	transaction_version: 2,
 };
 
 /// This determines the average expected block time that we are targeting.
@@ -152,7 +170,7 @@ parameter_types! {
 
 impl frame_system::Config for Runtime {
 	/// The basic call filter to use in dispatchable.
-	type BaseCallFilter = ();
+	type BaseCallFilter = BaseFilter;
 	/// Block & extrinsics weights: base values and limits.
 	type BlockWeights = BlockWeights;
 	/// The maximum length of a block (in bytes).
@@ -209,7 +227,7 @@ impl pallet_grandpa::Config for Runtime {
 	type Event = Event;
 	type Call = Call;
 
-	type KeyOwnerProofSystem = ();
+	type KeyOwnerProofSystem = Historical;
 
 	type KeyOwnerProof =
 		<Self::KeyOwnerProofSystem as KeyOwnerProofSystem<(KeyTypeId, GrandpaId)>>::Proof;
@@ -219,11 +237,177 @@ impl pallet_grandpa::Config for Runtime {
 		GrandpaId,
 	)>>::IdentificationTuple;
 
-	type HandleEquivocation = ();
+	type HandleEquivocation =
+		pallet_grandpa::EquivocationHandler<Self::KeyOwnerIdentification, Offences, ReportLongevity>;
 
 	type WeightInfo = ();
 }
 
+parameter_types! {
+	pub const UncleGenerations: BlockNumber = 0;
+}
+
+impl pallet_authorship::Config for Runtime {
+	type FindAuthor = pallet_session::FindAccountFromAuthorIndex<Self, Aura>;
+	type UncleGenerations = UncleGenerations;
+	type FilterUncle = ();
+	type EventHandler = Staking;
+}
+
+parameter_types! {
+	/// Authorities are rotated every 10 minutes.
+	pub const SessionPeriod: BlockNumber = 10 * MINUTES;
+	pub const SessionOffset: BlockNumber = 0;
+	pub const DisabledValidatorsThreshold: Perbill = Perbill::from_percent(17);
+	/// Offenders can be reported for a day after their offence.
+	pub const ReportLongevity: u64 = DAYS as u64;
+	pub const MinAuthorities: u32 = 1;
+}
+
+impl pallet_session::Config for Runtime {
+	type Event = Event;
+	type ValidatorId = AccountId;
+	type ValidatorIdOf = pallet_validator_set::ValidatorOf<Self>;
+	type ShouldEndSession = pallet_session::PeriodicSessions<SessionPeriod, SessionOffset>;
+	type NextSessionRotation = pallet_session::PeriodicSessions<SessionPeriod, SessionOffset>;
+	type SessionManager = PruningHistoricalSessions;
+	type SessionHandler = <opaque::SessionKeys as OpaqueKeys>::KeyTypeIdProviders;
+	type Keys = opaque::SessionKeys;
+	type DisabledValidatorsThreshold = DisabledValidatorsThreshold;
+	type WeightInfo = pallet_session::weights::SubstrateWeight<Runtime>;
+}
+
+/// Notes the historical roots of the sessions elected by `Staking`, keeping only the ones
+/// offences can still be reported against.
+pub struct PruningHistoricalSessions;
+
+type NoteHistoricalRoot = pallet_session_historical::NoteHistoricalRoot<Runtime, Staking>;
+
+/// Sessions an offence can be reported in after it happened, rounded up.
+pub struct KeptSessions;
+
+impl Get<SessionIndex> for KeptSessions {
+	fn get() -> SessionIndex {
+		let period = SessionPeriod::get() as u64;
+		((ReportLongevity::get() + period - 1) / period) as SessionIndex + 1
+	}
+}
+
+impl pallet_session::SessionManager<AccountId> for PruningHistoricalSessions {
+	fn new_session(new_index: SessionIndex) -> Option<Vec<AccountId>> {
+		Historical::prune_up_to(new_index.saturating_sub(KeptSessions::get()));
+		<NoteHistoricalRoot as pallet_session::SessionManager<_>>::new_session(new_index)
+	}
+
+	fn start_session(start_index: SessionIndex) {
+		<NoteHistoricalRoot as pallet_session::SessionManager<_>>::start_session(start_index)
+	}
+
+	fn end_session(end_index: SessionIndex) {
+		<NoteHistoricalRoot as pallet_session::SessionManager<_>>::end_session(end_index)
+	}
+}
+
+impl pallet_session::historical::Config for Runtime {
+	type FullIdentification = ();
+	type FullIdentificationOf = pallet_validator_set::FullIdentificationOf<Self>;
+}
+
+impl pallet_offences::Config for Runtime {
+	type Event = Event;
+	type IdentificationTuple = pallet_session::historical::IdentificationTuple<Self>;
+	type OnOffenceHandler = Staking;
+}
+
+impl pallet_aura_equivocation::Config for Runtime {
+	type Event = Event;
+
+	type KeyOwnerProofSystem = Historical;
+
+	type KeyOwnerProof =
+		<Self::KeyOwnerProofSystem as KeyOwnerProofSystem<(KeyTypeId, AuraId)>>::Proof;
+
+	type KeyOwnerIdentification = <Self::KeyOwnerProofSystem as KeyOwnerProofSystem<(
+		KeyTypeId,
+		AuraId,
+	)>>::IdentificationTuple;
+
+	type ReportOffence = Offences;
+	type ReportLongevity = ReportLongevity;
+	type CurrentSlot = CurrentSlot;
+	type CurrentSession = CurrentSession;
+	type SessionsKept = KeptSessions;
+}
+
+/// The slot of the current block, set by `Aura` when initializing it.
+pub struct CurrentSlot;
+
+impl Get<sp_aura_equivocation::Slot> for CurrentSlot {
+	fn get() -> sp_aura_equivocation::Slot {
+		Aura::current_slot()
+	}
+}
+
+/// The index of the current session.
+pub struct CurrentSession;
+
+impl Get<SessionIndex> for CurrentSession {
+	fn get() -> SessionIndex {
+		Session::current_index()
+	}
+}
+
+impl pallet_validator_set::Config for Runtime {
+	type Event = Event;
+	type AddRemoveOrigin = EnsureRoot<AccountId>;
+	type MinAuthorities = MinAuthorities;
+}
+
+parameter_types! {
+	/// About a thousandth of the endowment of the development accounts.
+	pub const MinimumCandidateBond: Balance = 1 << 50;
+	/// Candidates stay slashable for as long as their offences can be reported.
+	pub const CandidateUnbondingPeriod: BlockNumber = DAYS;
+	/// Authorities are elected every hour.
+	pub const SessionsPerEra: u32 = 6;
+	pub const MaxAuthorities: u32 = 16;
+	pub const BlockReward: Balance = 1 << 40;
+}
+
+/// The authorities set by root, elected whatever their bond.
+pub struct Invulnerables;
+
+impl Get<Vec<AccountId>> for Invulnerables {
+	fn get() -> Vec<AccountId> {
+		ValidatorSet::validators()
+	}
+}
+
+/// The accounts with session keys set for the next session, in `NextKeys` of `pallet_session`.
+pub struct NextKeysSet;
+
+impl pallet_validator_staking::HasSessionKeys<AccountId> for NextKeysSet {
+	fn has_session_keys(who: &AccountId) -> bool {
+		// the map isn't public, the account being the validator id of `pallet_session`.
+		let key = Twox64Concat::hash(&who.encode());
+		get_storage_value::<opaque::SessionKeys>(b"Session", b"NextKeys", &key).is_some()
+	}
+}
+
+impl pallet_validator_staking::Config for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type MinimumBond = MinimumCandidateBond;
+	type UnbondingPeriod = CandidateUnbondingPeriod;
+	type SessionsPerEra = SessionsPerEra;
+	type MaxAuthorities = MaxAuthorities;
+	type BlockReward = BlockReward;
+	type Invulnerables = Invulnerables;
+	type SessionKeys = NextKeysSet;
+	type Slash = ();
+	type OnOffence = ValidatorSet;
+}
+
 parameter_types! {
 	pub const MinimumPeriod: u64 = SLOT_DURATION / 2;
 }
@@ -264,9 +448,345 @@ impl pallet_transaction_payment::Config for Runtime {
 	type FeeMultiplierUpdate = ();
 }
 
-impl pallet_sudo::Config for Runtime {
+parameter_types! {
+	/// Council motions stay open for a day.
+	pub const CouncilMotionDuration: BlockNumber = DAYS;
+	pub const CouncilMaxProposals: u32 = 100;
+	pub const CouncilMaxMembers: u32 = 100;
+}
+
+type CouncilCollective = pallet_collective::Instance1;
+
+impl pallet_collective::Config<CouncilCollective> for Runtime {
+	type Origin = Origin;
+	type Proposal = Call;
+	type Event = Event;
+	type MotionDuration = CouncilMotionDuration;
+	type MaxProposals = CouncilMaxProposals;
+	type MaxMembers = CouncilMaxMembers;
+	type DefaultVote = pallet_collective::PrimeDefaultVote;
+	type WeightInfo = pallet_collective::weights::SubstrateWeight<Runtime>;
+}
+
+/// Origins passing both `A` and `B`, with the success of `A`.
+pub struct EnsureBoth<A, B>(PhantomData<(A, B)>);
+
+impl<O: Clone, A: EnsureOrigin<O>, B: EnsureOrigin<O>> EnsureOrigin<O> for EnsureBoth<A, B> {
+	type Success = A::Success;
+
+	fn try_origin(o: O) -> Result<Self::Success, O> {
+		B::try_origin(o.clone())?;
+		A::try_origin(o)
+	}
+
+	#[cfg(feature = "runtime-benchmarks")]
+	fn successful_origin() -> O {
+		B::successful_origin()
+	}
+}
+
+/// Two council members at least, so that a council of one, e.g. the former sudo key, can't act
+/// alone.
+type CouncilMembers = pallet_collective::EnsureMembers<_2, AccountId, CouncilCollective>;
+/// More than half of the council.
+type CouncilMajority = EnsureBoth<
+	pallet_collective::EnsureProportionMoreThan<_1, _2, AccountId, CouncilCollective>,
+	CouncilMembers,
+>;
+/// At least two thirds of the council.
+type CouncilSupermajority = EnsureBoth<
+	pallet_collective::EnsureProportionAtLeast<_2, _3, AccountId, CouncilCollective>,
+	CouncilMembers,
+>;
+/// The whole council.
+type CouncilUnanimity = EnsureBoth<
+	pallet_collective::EnsureProportionAtLeast<_1, _1, AccountId, CouncilCollective>,
+	CouncilMembers,
+>;
+
+parameter_types! {
+	pub MaximumSchedulerWeight: Weight = Perbill::from_percent(80) * BlockWeights::get().max_block;
+	pub const MaxScheduledPerBlock: u32 = 50;
+}
+
+impl pallet_scheduler::Config for Runtime {
 	type Event = Event;
+	type Origin = Origin;
+	type PalletsOrigin = OriginCaller;
 	type Call = Call;
+	type MaximumWeight = MaximumSchedulerWeight;
+	type ScheduleOrigin = EnsureRoot<AccountId>;
+	type MaxScheduledPerBlock = MaxScheduledPerBlock;
+	type WeightInfo = pallet_scheduler::weights::SubstrateWeight<Runtime>;
+}
+
+parameter_types! {
+	/// A public proposal or a council motion is put to a referendum every 2 days, for 2 days.
+	pub const LaunchPeriod: BlockNumber = 2 * DAYS;
+	pub const VotingPeriod: BlockNumber = 2 * DAYS;
+	/// Passed referenda are enacted a day after their end.
+	pub const EnactmentPeriod: BlockNumber = DAYS;
+	/// Two thirds of the council can fast track a referendum down to 3 hours, and no less.
+	pub const FastTrackVotingPeriod: BlockNumber = 3 * HOURS;
+	pub const InstantAllowed: bool = false;
+	/// Fast tracked referenda are still enacted 3 hours after they pass at the earliest.
+	pub const MinimumEnactmentPeriod: BlockNumber = 3 * HOURS;
+	/// About a thousandth of the endowment of the development accounts.
+	pub const MinimumDeposit: Balance = 1 << 50;
+	pub const PreimageByteDeposit: Balance = 1 << 30;
+	/// Vetoed council motions can't be proposed again for a week.
+	pub const CooloffPeriod: BlockNumber = 7 * DAYS;
+	pub const MaxVotes: u32 = 100;
+	pub const MaxProposals: u32 = 100;
+}
+
+impl pallet_democracy::Config for Runtime {
+	type Proposal = Call;
+	type Event = Event;
+	type Currency = Balances;
+	type EnactmentPeriod = EnactmentPeriod;
+	type LaunchPeriod = LaunchPeriod;
+	type VotingPeriod = VotingPeriod;
+	type MinimumDeposit = MinimumDeposit;
+	type ExternalOrigin = CouncilMajority;
+	type ExternalMajorityOrigin = CouncilSupermajority;
+	type ExternalDefaultOrigin = CouncilUnanimity;
+	type FastTrackOrigin = CouncilSupermajority;
+	type InstantOrigin = CouncilUnanimity;
+	type InstantAllowed = InstantAllowed;
+	type FastTrackVotingPeriod = FastTrackVotingPeriod;
+	type CancellationOrigin = CouncilSupermajority;
+	type BlacklistOrigin = EnsureRoot<AccountId>;
+	type CancelProposalOrigin = EnsureOneOf<AccountId, EnsureRoot<AccountId>, CouncilUnanimity>;
+	type VetoOrigin = pallet_collective::EnsureMember<AccountId, CouncilCollective>;
+	type CooloffPeriod = CooloffPeriod;
+	type PreimageByteDeposit = PreimageByteDeposit;
+	type OperationalPreimageOrigin = pallet_collective::EnsureMember<AccountId, CouncilCollective>;
+	type Slash = ();
+	type Scheduler = Scheduler;
+	type PalletsOrigin = OriginCaller;
+	type MaxVotes = MaxVotes;
+	type WeightInfo = pallet_democracy::weights::SubstrateWeight<Runtime>;
+	type MaxProposals = MaxProposals;
+}
+
+/// Keeps the council from fast tracking referenda with an enactment delay below
+/// `MinimumEnactmentPeriod`. Root calls aren't filtered.
+pub struct BaseFilter;
+
+impl Filter<Call> for BaseFilter {
+	fn filter(call: &Call) -> bool {
+		match call {
+			Call::Democracy(pallet_democracy::Call::fast_track(_, _, delay)) =>
+				*delay >= MinimumEnactmentPeriod::get(),
+			_ => true,
+		}
+	}
+}
+
+/// Moves the powers of the former sudo key to the governance: the key becomes the only member of
+/// the council, unless it has members already, and the sudo storage is removed. Alone, it can't
+/// act on referenda, see `CouncilMembers`.
+pub struct SudoToCouncil;
+
+impl OnRuntimeUpgrade for SudoToCouncil {
+	fn on_runtime_upgrade() -> Weight {
+		let key = match take_storage_value::<AccountId>(b"Sudo", b"Key", &[]) {
+			Some(key) => key,
+			None => return RocksDbWeight::get().reads(1),
+		};
+		if Council::members().is_empty() {
+			<Council as ChangeMembers<AccountId>>::set_members_sorted(&[key], &[]);
+		}
+		RocksDbWeight::get().reads_writes(2, 3)
+	}
+}
+
+parameter_types! {
+	/// About a thousandth of the endowment of the development accounts.
+	pub const MinimumExecutorBond: Balance = 1 << 50;
+}
+
+/// Executors stay slashable for as long as their last receipts are kept, which is past the whole
+/// challenge period of the receipts they submitted right before unbonding, whatever the receipt
+/// parameters are set to.
+pub struct ExecutorUnbondingPeriod;
+
+impl Get<BlockNumber> for ExecutorUnbondingPeriod {
+	fn get() -> BlockNumber {
+		ExecutionReceipts::pruning_depth()
+	}
+}
+
+impl pallet_executors::Config for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type MinimumBond = MinimumExecutorBond;
+	type UnbondingPeriod = ExecutorUnbondingPeriod;
+	type Slash = ();
+}
+
+// The receipt parameters are storage items, that can be changed by a referendum on
+// `system.set_storage` under the key `twox_128(":<name>:")`, e.g.
+// `twox_128(":ReceiptsChallengePeriod:")`.
+parameter_types! {
+	/// Receipts can be challenged for an hour after they are included.
+	pub storage ReceiptsChallengePeriod: BlockNumber = HOURS;
+	/// Executors have an hour to submit the receipt of a block, short of a block to stay below
+	/// the challenge period.
+	pub storage MaxReceiptLag: BlockNumber = HOURS;
+	/// Receipts are pruned as soon as they can't be challenged anymore, about two hours after
+	/// their block with the receipt lag and the challenge period above.
+	pub storage ReceiptsPruningDepth: BlockNumber = HOURS;
+	pub const ExecutionReceiptsUnsignedPriority: TransactionPriority = TransactionPriority::max_value();
+	/// Fraud proofs are free, a handful of them per block is plenty to challenge wrong receipts.
+	pub const MaxFraudProofsPerBlock: u32 = 4;
+}
+
+impl pallet_execution_receipts::Config for Runtime {
+	type Event = Event;
+	type ChallengePeriod = ReceiptsChallengePeriod;
+	type MaxReceiptLag = MaxReceiptLag;
+	type ReceiptsPruningDepth = ReceiptsPruningDepth;
+	type UnsignedPriority = ExecutionReceiptsUnsignedPriority;
+	type MaxFraudProofsPerBlock = MaxFraudProofsPerBlock;
+	type FraudProofVerifier = pallet_execution_receipts::HostFraudProofVerifier;
+	type ExecutorRegistry = Executors;
+	type OnFraudProof = Executors;
+	type ExtractBundle = BundleExtractor;
+}
+
+parameter_types! {
+	/// A bundle may use up to a quarter of the block.
+	pub const MaxBundleWeight: Weight = WEIGHT_PER_SECOND / 2;
+	pub const BundlesUnsignedPriority: TransactionPriority = TransactionPriority::max_value() / 2;
+}
+
+/// Whether the call is a user transaction, which only makes it into the execution state through
+/// the bundle of an executor. The calls of the pallets consensus relies on go directly into
+/// blocks, so that they apply to the consensus state.
+pub fn is_bundled_call(call: &Call) -> bool {
+	matches!(
+		call,
+		Call::Balances(..) | Call::TemplateModule(..) | Call::HelloSubstrate(..)
+			| Call::GenericEvent(..) | Call::SimpleEvent(..)
+	)
+}
+
+/// Whether the extrinsic is a signed user transaction, see [`is_bundled_call`].
+pub fn is_bundled(extrinsic: &UncheckedExtrinsic) -> bool {
+	extrinsic.signature.is_some() && is_bundled_call(&extrinsic.function)
+}
+
+/// Applies the transactions of bundles the way `Executive::apply_extrinsic` applies the ones of
+/// the block, except that they are not noted as extrinsics of the block, and that their weight is
+/// accounted for by the bundle rather than by `CheckWeight`.
+pub struct BundledExtrinsicApplier;
+
+impl pallet_bundles::ApplyBundledExtrinsic for BundledExtrinsicApplier {
+	fn apply_bundled_extrinsic(
+		extrinsic: &[u8],
+		remaining_weight: Weight,
+	) -> Result<Weight, TransactionValidityError> {
+		let uxt = UncheckedExtrinsic::decode(&mut &extrinsic[..])
+			.map_err(|_| InvalidTransaction::Call)?;
+		// bundles are for user transactions, unsigned ones are validated by their pallet when
+		// submitted on their own.
+		if !is_bundled(&uxt) {
+			return Err(InvalidTransaction::Call.into())
+		}
+
+		let xt = uxt.check(&Default::default())?;
+		let dispatch_info = xt.get_dispatch_info();
+		if dispatch_info.weight > remaining_weight {
+			return Err(InvalidTransaction::ExhaustsResources.into())
+		}
+
+		let (who, extra) = xt.signed.ok_or(InvalidTransaction::Call)?;
+		let (spec_version, tx_version, genesis, era, nonce, _check_weight, payment) = extra;
+		let xt = generic::CheckedExtrinsic {
+			signed: Some((who, (spec_version, tx_version, genesis, era, nonce, payment))),
+			function: xt.function,
+		};
+
+		// an invalid transaction leaves no trace, e.g. of its nonce bumped before its fee
+		// couldn't be paid.
+		let result = with_transaction(|| match xt.apply::<Runtime>(&dispatch_info, extrinsic.len()) {
+			Ok(result) => TransactionOutcome::Commit(Ok(result)),
+			Err(e) => TransactionOutcome::Rollback(Err(e)),
+		});
+		let post_info = match result? {
+			Ok(post_info) => post_info,
+			Err(error) => error.post_info,
+		};
+		Ok(post_info.calc_actual_weight(&dispatch_info))
+	}
+
+	fn sender_and_nonce(extrinsic: &[u8]) -> Option<(Vec<u8>, u64)> {
+		let uxt = UncheckedExtrinsic::decode(&mut &extrinsic[..]).ok()?;
+		let (address, _, extra) = uxt.signature?;
+		let who = <Runtime as frame_system::Config>::Lookup::lookup(address).ok()?;
+		let frame_system::CheckNonce(nonce) = extra.4;
+		Some((who.encode(), nonce.into()))
+	}
+}
+
+/// Finds the bundles of blocks: the unsigned `Bundles::submit_bundle` extrinsics.
+pub struct BundleExtractor;
+
+impl sp_executor::ExtractBundle for BundleExtractor {
+	fn extract_bundle(extrinsic: &[u8]) -> Option<sp_executor::SignedOpaqueBundle> {
+		let uxt = UncheckedExtrinsic::decode(&mut &extrinsic[..]).ok()?;
+		match uxt.function {
+			Call::Bundles(pallet_bundles::Call::submit_bundle(bundle)) if uxt.signature.is_none() =>
+				Some(bundle),
+			_ => None,
+		}
+	}
+}
+
+/// Finds the execution receipts and fraud proofs of blocks: the unsigned
+/// `ExecutionReceipts::submit_execution_receipt` and `ExecutionReceipts::submit_fraud_proof`
+/// extrinsics.
+pub struct ExecutionExtractor;
+
+impl sp_executor::ExtractExecution<BlockNumber, Hash> for ExecutionExtractor {
+	fn extract_receipt(
+		extrinsic: &[u8],
+	) -> Option<sp_executor::SignedExecutionReceipt<BlockNumber, Hash>> {
+		let uxt = UncheckedExtrinsic::decode(&mut &extrinsic[..]).ok()?;
+		match uxt.function {
+			Call::ExecutionReceipts(pallet_execution_receipts::Call::submit_execution_receipt(receipt))
+				if uxt.signature.is_none() => Some(receipt),
+			_ => None,
+		}
+	}
+
+	fn extract_fraud_proof(extrinsic: &[u8]) -> Option<sp_executor::FraudProof<BlockNumber, Hash>> {
+		let uxt = UncheckedExtrinsic::decode(&mut &extrinsic[..]).ok()?;
+		match uxt.function {
+			Call::ExecutionReceipts(pallet_execution_receipts::Call::submit_fraud_proof(fraud_proof))
+				if uxt.signature.is_none() => Some(fraud_proof),
+			_ => None,
+		}
+	}
+}
+
+impl pallet_bundles::Config for Runtime {
+	type Event = Event;
+	type MaxBundleWeight = MaxBundleWeight;
+	type UnsignedPriority = BundlesUnsignedPriority;
+	type ApplyExtrinsic = BundledExtrinsicApplier;
+	type ExecutorRegistry = Executors;
+	type Randomness = RandomnessCollectiveFlip;
+	type ExtractBundle = BundleExtractor;
+}
+
+impl<C> frame_system::offchain::SendTransactionTypes<C> for Runtime where
+	Call: From<C>,
+{
+	type OverarchingCall = Call;
+	type Extrinsic = UncheckedExtrinsic;
 }
 
 /// Configure the pallet-template in pallets/template.
@@ -294,16 +814,32 @@ construct_runtime!(
 		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
 		RandomnessCollectiveFlip: pallet_randomness_collective_flip::{Pallet, Call, Storage},
 		Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
-		Aura: pallet_aura::{Pallet, Config<T>},
-		Grandpa: pallet_grandpa::{Pallet, Call, Storage, Config, Event},
+		// The session takes a reference on the accounts of the authorities at genesis, so it has to
+		// be built after the balances endowing them, and after the validator set and staking
+		// electing them.
 		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
+		Authorship: pallet_authorship::{Pallet, Call, Storage},
+		ValidatorSet: pallet_validator_set::{Pallet, Call, Storage, Config<T>, Event<T>},
+		Staking: pallet_validator_staking::{Pallet, Call, Storage, Config<T>, Event<T>},
+		Session: pallet_session::{Pallet, Call, Storage, Config<T>, Event},
+		Historical: pallet_session_historical::{Pallet},
+		Offences: pallet_offences::{Pallet, Call, Storage, Event},
+		Aura: pallet_aura::{Pallet, Config<T>},
+		Grandpa: pallet_grandpa::{Pallet, Call, Storage, Config, Event, ValidateUnsigned},
+		AuraEquivocation: pallet_aura_equivocation::{Pallet, Call, Event<T>, ValidateUnsigned},
 		TransactionPayment: pallet_transaction_payment::{Pallet, Storage},
-		Sudo: pallet_sudo::{Pallet, Call, Config<T>, Storage, Event<T>},
+		Executors: pallet_executors::{Pallet, Call, Storage, Config<T>, Event<T>},
+		ExecutionReceipts: pallet_execution_receipts::{Pallet, Call, Storage, Event<T>, ValidateUnsigned},
+		Bundles: pallet_bundles::{Pallet, Call, Event<T>, ValidateUnsigned},
 		// Include the custom logic from the pallet-template in the runtime.
 		TemplateModule: pallet_template::{Pallet, Call, Storage, Event<T>},
 		HelloSubstrate: hello_substrate::{Pallet, Call},
 		GenericEvent: generic_event::{Pallet, Call, Event<T>},
 		SimpleEvent: simple_event::{Pallet, Call, Event},
+		// The governance replacing sudo.
+		Scheduler: pallet_scheduler::{Pallet, Call, Storage, Event<T>},
+		Council: pallet_collective::<Instance1>::{Pallet, Call, Storage, Origin<T>, Event<T>, Config<T>},
+		Democracy: pallet_democracy::{Pallet, Call, Storage, Config, Event<T>},
 	}
 );
 
@@ -338,6 +874,7 @@ pub type Executive = frame_executive::Executive<
 	frame_system::ChainContext<Runtime>,
 	Runtime,
 	AllPallets,
+	SudoToCouncil,
 >;
 
 impl_runtime_apis! {
@@ -386,6 +923,25 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl sp_executor::FraudProofApi<Block> for Runtime {
+		fn submit_fraud_proof_unsigned(
+			fraud_proof: sp_executor::FraudProof<NumberFor<Block>, <Block as BlockT>::Hash>,
+		) -> Option<()> {
+			ExecutionReceipts::submit_fraud_proof_unsigned(fraud_proof).ok()
+		}
+	}
+
+	impl sp_executor::StatelessExecutionApi<Block> for Runtime {
+		fn execute_block_stateless(
+			header: <Block as BlockT>::Header,
+			extrinsics: Vec<<Block as BlockT>::Extrinsic>,
+			pre_state_root: <Block as BlockT>::Hash,
+			proof: sp_executor::CompactProof,
+		) -> Option<<Block as BlockT>::Hash> {
+			sp_executor::execute_block_stateless::<Block>(&header, &extrinsics, pre_state_root, &proof)
+		}
+	}
+
 	impl sp_transaction_pool::runtime_api::TaggedTransactionQueue<Block> for Runtime {
 		fn validate_transaction(
 			source: TransactionSource,
@@ -429,23 +985,86 @@ impl_runtime_apis! {
 		}
 
 		fn submit_report_equivocation_unsigned_extrinsic(
-			_equivocation_proof: fg_primitives::EquivocationProof<
+			equivocation_proof: fg_primitives::EquivocationProof<
 				<Block as BlockT>::Hash,
 				NumberFor<Block>,
 			>,
-			_key_owner_proof: fg_primitives::OpaqueKeyOwnershipProof,
+			key_owner_proof: fg_primitives::OpaqueKeyOwnershipProof,
 		) -> Option<()> {
-			None
+			let key_owner_proof = key_owner_proof.decode()?;
+
+			Grandpa::submit_unsigned_equivocation_report(equivocation_proof, key_owner_proof)
 		}
 
 		fn generate_key_ownership_proof(
 			_set_id: fg_primitives::SetId,
-			_authority_id: GrandpaId,
+			authority_id: GrandpaId,
 		) -> Option<fg_primitives::OpaqueKeyOwnershipProof> {
-			// NOTE: this is the only implementation possible since we've
-			// defined our key owner proof type as a bottom type (i.e. a type
-			// with no values).
-			None
+			Historical::prove((fg_primitives::KEY_TYPE, authority_id))
+				.map(|proof| proof.encode())
+				.map(fg_primitives::OpaqueKeyOwnershipProof::new)
+		}
+	}
+
+	impl sp_aura_equivocation::AuraEquivocationApi<Block> for Runtime {
+		fn submit_report_equivocation_unsigned_extrinsic(
+			equivocation_proof: sp_aura_equivocation::EquivocationProof<<Block as BlockT>::Header>,
+			key_owner_proof: sp_aura_equivocation::OpaqueKeyOwnershipProof,
+		) -> Option<()> {
+			let key_owner_proof = key_owner_proof.decode()?;
+
+			AuraEquivocation::submit_unsigned_equivocation_report(equivocation_proof, key_owner_proof).ok()
+		}
+
+		fn generate_key_ownership_proof(
+			authority_id: AuraId,
+		) -> Option<sp_aura_equivocation::OpaqueKeyOwnershipProof> {
+			Historical::prove((sp_aura_equivocation::KEY_TYPE, authority_id))
+				.map(|proof| proof.encode())
+				.map(sp_aura_equivocation::OpaqueKeyOwnershipProof::new)
+		}
+	}
+
+	impl sp_executor::ExecutorApi<Block> for Runtime {
+		fn submit_execution_receipt_unsigned(
+			execution_receipt: sp_executor::SignedExecutionReceipt<NumberFor<Block>, <Block as BlockT>::Hash>,
+		) -> Option<()> {
+			ExecutionReceipts::submit_execution_receipt_unsigned(execution_receipt).ok()
+		}
+
+		fn execution_receipts(
+			number: NumberFor<Block>,
+		) -> Vec<(sp_executor::ExecutorId, sp_executor::ExecutionReceipt<NumberFor<Block>, <Block as BlockT>::Hash>)> {
+			ExecutionReceipts::receipts_of(number)
+		}
+
+		fn executed_head() -> NumberFor<Block> {
+			ExecutionReceipts::executed_head()
+		}
+
+		fn majority_executed_head() -> NumberFor<Block> {
+			ExecutionReceipts::majority_executed_head()
+		}
+
+		fn challenge_period() -> NumberFor<Block> {
+			ReceiptsChallengePeriod::get()
+		}
+
+		fn confirmed_head() -> Option<sp_executor::ConfirmedBlock<NumberFor<Block>, <Block as BlockT>::Hash>> {
+			ExecutionReceipts::confirmed_head()
+		}
+	}
+
+	impl sp_executor::BundleApi<Block> for Runtime {
+		fn submit_bundle_unsigned(bundle: sp_executor::SignedOpaqueBundle) -> Option<()> {
+			Bundles::submit_bundle_unsigned(bundle).ok()
+		}
+
+		fn apply_bundled_extrinsic(
+			extrinsic: Vec<u8>,
+			position: u32,
+		) -> Result<(), TransactionValidityError> {
+			Bundles::apply_bundled_extrinsic(&extrinsic, position).map(|_| ())
 		}
 	}
 
@@ -0,0 +1,101 @@
+//! Synthetic load: balance transfers and `TemplateModule::do_something` calls from the
+//! pre-funded accounts of the local testnet.
+
+use codec::{Decode, Encode};
+use node_template::service::{FullClient, FullPool};
+use node_template_runtime::{
+	AccountId, BalancesCall, Call, Hash, Index, Runtime, SignedExtra, UncheckedExtrinsic, VERSION,
+	opaque::Block, pallet_template,
+};
+use sp_blockchain::HeaderBackend;
+use sp_core::{Pair, sr25519};
+use sp_runtime::{
+	generic::{BlockId, Era, SignedPayload},
+	traits::Block as BlockT,
+};
+use sp_transaction_pool::{TransactionPool, TransactionSource};
+
+/// The accounts sending the load.
+const SENDERS: &[&str] = &["Alice", "Bob", "Charlie", "Dave", "Eve", "Ferdie"];
+
+/// Sign `call` as `sender` with the given nonce, valid forever on the chain with the given
+/// genesis hash.
+pub fn sign(sender: &sr25519::Pair, nonce: Index, call: Call, genesis_hash: Hash) -> UncheckedExtrinsic {
+	let extra: SignedExtra = (
+		frame_system::CheckSpecVersion::<Runtime>::new(),
+		frame_system::CheckTxVersion::<Runtime>::new(),
+		frame_system::CheckGenesis::<Runtime>::new(),
+		frame_system::CheckEra::<Runtime>::from(Era::Immortal),
+		frame_system::CheckNonce::<Runtime>::from(nonce),
+		frame_system::CheckWeight::<Runtime>::new(),
+		pallet_transaction_payment::ChargeTransactionPayment::<Runtime>::from(0),
+	);
+	let payload = SignedPayload::from_raw(
+		call,
+		extra,
+		(VERSION.spec_version, VERSION.transaction_version, genesis_hash, genesis_hash, (), (), ()),
+	);
+	let signature = payload.using_encoded(|payload| sender.sign(payload));
+	let (call, extra, _) = payload.deconstruct();
+
+	UncheckedExtrinsic::new_signed(call, AccountId::from(sender.public()).into(), signature.into(), extra)
+}
+
+/// The development key of `name`.
+pub fn account(name: &str) -> sr25519::Pair {
+	sr25519::Pair::from_string(&format!("//{}", name), None).expect("Development seeds are valid; qed")
+}
+
+/// Submit `extrinsic` to the pool of a node, on top of its best block.
+pub async fn submit(
+	client: &FullClient,
+	pool: &FullPool,
+	extrinsic: UncheckedExtrinsic,
+) -> Result<(), String> {
+	let extrinsic = <Block as BlockT>::Extrinsic::decode(&mut &extrinsic.encode()[..])
+		.map_err(|e| format!("Failed to convert the extrinsic: {}", e))?;
+	let at = BlockId::Hash(client.info().best_hash);
+	pool.submit_one(&at, TransactionSource::External, extrinsic).await
+		.map(|_| ())
+		.map_err(|e| format!("{:?}", e))
+}
+
+/// Generates the extrinsics of the load: transfers and `do_something` calls in turn, from each
+/// sender in turn.
+pub struct LoadGenerator {
+	senders: Vec<(sr25519::Pair, Index)>,
+	genesis_hash: Hash,
+	count: usize,
+}
+
+impl LoadGenerator {
+	/// Create a generator for the chain with the given genesis hash, whose senders haven't sent
+	/// anything yet.
+	pub fn new(genesis_hash: Hash) -> Self {
+		LoadGenerator {
+			senders: SENDERS.iter().map(|name| (account(name), 0)).collect(),
+			genesis_hash,
+			count: 0,
+		}
+	}
+
+	/// The next extrinsic of the load.
+	pub fn next_extrinsic(&mut self) -> UncheckedExtrinsic {
+		let count = self.count;
+		self.count += 1;
+
+		let sender_count = self.senders.len();
+		// every sender sends both calls, one after the other.
+		let call = if (count / sender_count) % 2 == 0 {
+			let dest = self.senders[(count + 1) % sender_count].0.public();
+			Call::Balances(BalancesCall::transfer(AccountId::from(dest).into(), 1_000))
+		} else {
+			Call::TemplateModule(pallet_template::Call::do_something(count as u32))
+		};
+
+		let (sender, nonce) = &mut self.senders[count % sender_count];
+		let extrinsic = sign(sender, *nonce, call, self.genesis_hash);
+		*nonce += 1;
+		extrinsic
+	}
+}
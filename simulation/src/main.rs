@@ -0,0 +1,220 @@
+//! Runs the local testnet in process under a synthetic load, once with coupled execution and once
+//! with decoupled execution, and reports the throughput and confirmation latency of both.
+
+mod load;
+mod measure;
+mod node;
+
+use std::{str::FromStr, time::{Duration, Instant}};
+use futures::FutureExt;
+use node_template::executor::ExecutionRole;
+use node_template_runtime::BlockNumber;
+use sc_service::{TaskExecutor, TaskType};
+use sp_blockchain::HeaderBackend;
+use structopt::StructOpt;
+use load::LoadGenerator;
+use measure::{Meter, Report};
+use node::Node;
+
+/// The interval between two rounds of load and measurements.
+const TICK: Duration = Duration::from_millis(100);
+
+/// The longest the nodes are waited for to author their first block.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Which execution modes to measure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Modes {
+	Coupled,
+	Decoupled,
+	Both,
+}
+
+impl FromStr for Modes {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"coupled" => Ok(Modes::Coupled),
+			"decoupled" => Ok(Modes::Decoupled),
+			"both" => Ok(Modes::Both),
+			_ => Err(format!("Unknown mode {}, expected coupled, decoupled or both", s)),
+		}
+	}
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "node-template-simulation")]
+struct Options {
+	/// The execution modes to measure: `coupled`, `decoupled` or `both`.
+	#[structopt(long, default_value = "both")]
+	mode: Modes,
+
+	/// The load, in extrinsics per second.
+	#[structopt(long, default_value = "50")]
+	tps: u32,
+
+	/// How long to measure for, in seconds.
+	#[structopt(long, default_value = "120")]
+	duration: u64,
+
+	/// How long to run the load for before measuring, in seconds.
+	#[structopt(long, default_value = "30")]
+	warmup: u64,
+
+	/// The receipts challenge period of the chain, in blocks.
+	#[structopt(long, default_value = "5")]
+	challenge_period: BlockNumber,
+}
+
+/// The nodes of a run: the authorities, then the executors if any, and the index of the node the
+/// load is sent to and measured on.
+fn start_nodes(
+	decoupled: bool,
+	challenge_period: BlockNumber,
+	base_path: &std::path::Path,
+	task_executor: &TaskExecutor,
+) -> Result<(Vec<Node>, usize), String> {
+	let (authority_role, first_port) = if decoupled {
+		(ExecutionRole::Consensus, 30500)
+	} else {
+		(ExecutionRole::Coupled, 30400)
+	};
+	let mut topology = vec![("Alice", authority_role, true), ("Bob", authority_role, true)];
+	if decoupled {
+		topology.push(("Charlie", ExecutionRole::Executor, false));
+		topology.push(("Dave", ExecutionRole::Executor, false));
+	}
+
+	let nodes = topology.into_iter().enumerate()
+		.map(|(index, (name, role, authority))| node::start_node(
+			name,
+			role,
+			authority,
+			challenge_period,
+			first_port + 10 * index as u16,
+			&base_path.join(name),
+			task_executor.clone(),
+		))
+		.collect::<Result<Vec<_>, _>>()?;
+	node::connect(&nodes)?;
+
+	// decoupled chains only execute user transactions on executors.
+	let measured = if decoupled { 2 } else { 0 };
+	Ok((nodes, measured))
+}
+
+/// Run the testnet in the given mode under the load, and measure it.
+async fn run(decoupled: bool, options: &Options, task_executor: TaskExecutor) -> Result<(Report, usize), String> {
+	let base_path = tempfile::tempdir().map_err(|e| e.to_string())?;
+	let (nodes, measured) = start_nodes(decoupled, options.challenge_period, base_path.path(), &task_executor)?;
+	let client = nodes[measured].parts.client.clone();
+	let pool = nodes[measured].parts.transaction_pool.clone();
+
+	let started = Instant::now();
+	while client.info().best_number == 0 {
+		if started.elapsed() > STARTUP_TIMEOUT {
+			return Err(format!("{} didn't import any block", nodes[measured].name))
+		}
+		futures_timer::Delay::new(TICK).await;
+	}
+
+	let genesis_hash = client.info().genesis_hash;
+	let mut load = LoadGenerator::new(genesis_hash);
+	let mut meter = Meter::new(&client, decoupled)?;
+	let mut rejected = 0;
+	let mut owed = 0f64;
+
+	let warmup = Duration::from_secs(options.warmup);
+	let window = Duration::from_secs(options.duration);
+	// confirmations lag finality by the receipt lag and the challenge period, both capped by
+	// the challenge period, plus the execution lag on executors.
+	let drain = Duration::from_secs(6 * 3 * options.challenge_period as u64 + 60);
+	let started = Instant::now();
+	let mut measuring = false;
+	loop {
+		let elapsed = started.elapsed();
+		if !measuring && elapsed >= warmup && elapsed < warmup + window {
+			meter.start();
+			measuring = true;
+		}
+		if measuring && elapsed >= warmup + window {
+			meter.stop();
+			measuring = false;
+		}
+		if elapsed >= warmup + window && (meter.is_drained() || elapsed >= warmup + window + drain) {
+			break
+		}
+
+		if elapsed < warmup + window {
+			owed += options.tps as f64 * TICK.as_secs_f64();
+			while owed >= 1.0 {
+				owed -= 1.0;
+				if load::submit(&client, &pool, load.next_extrinsic()).await.is_err() {
+					rejected += 1;
+				}
+			}
+		}
+
+		meter.poll(&client)?;
+		futures_timer::Delay::new(TICK).await;
+	}
+
+	for node in nodes {
+		node.parts.task_manager.clean_shutdown().await;
+	}
+
+	Ok((meter.report(), rejected))
+}
+
+fn print_report(mode: &str, report: &Report, rejected: usize) {
+	let (mean, max) = report.confirmation_latency()
+		.map(|(mean, max)| (format!("{:.1}s", mean.as_secs_f64()), format!("{:.1}s", max.as_secs_f64())))
+		.unwrap_or_else(|| ("-".into(), "-".into()));
+	println!(
+		"{:<10} {:>9.2} {:>13.1} {:>14} {:>13} {:>12} {:>9}",
+		mode,
+		report.blocks_per_second(),
+		report.extrinsics_per_second(),
+		mean,
+		max,
+		report.unconfirmed,
+		rejected,
+	);
+}
+
+fn main() -> Result<(), String> {
+	let options = Options::from_args();
+
+	let mut runtime = sc_cli::build_runtime().map_err(|e| e.to_string())?;
+	let runtime_handle = runtime.handle().clone();
+	let task_executor: TaskExecutor = (move |fut, task_type| match task_type {
+		TaskType::Async => runtime_handle.spawn(fut).map(drop),
+		TaskType::Blocking => runtime_handle.spawn_blocking(move || futures::executor::block_on(fut))
+			.map(drop),
+	}).into();
+
+	let modes: &[(&str, bool)] = match options.mode {
+		Modes::Coupled => &[("coupled", false)],
+		Modes::Decoupled => &[("decoupled", true)],
+		Modes::Both => &[("coupled", false), ("decoupled", true)],
+	};
+
+	let mut reports = Vec::new();
+	for (mode, decoupled) in modes {
+		println!("Measuring {} execution for {}s at {} tx/s", mode, options.duration, options.tps);
+		let report = runtime.block_on(run(*decoupled, &options, task_executor.clone()))?;
+		reports.push((mode, report));
+	}
+
+	println!();
+	println!(
+		"{:<10} {:>9} {:>13} {:>14} {:>13} {:>12} {:>9}",
+		"mode", "blocks/s", "extrinsics/s", "mean latency", "max latency", "unconfirmed", "rejected",
+	);
+	for (mode, (report, rejected)) in &reports {
+		print_report(mode, report, *rejected);
+	}
+
+	Ok(())
+}
@@ -0,0 +1,183 @@
+//! Measurements taken on a node while the load runs.
+
+use std::{
+	collections::BTreeMap,
+	time::{Duration, Instant},
+};
+use codec::Decode;
+use frame_system::EventRecord;
+use node_template::{executor::aux_schema, service::FullClient};
+use node_template_runtime::{BlockNumber, Event, Hash};
+use sc_client_api::StorageProvider;
+use sp_blockchain::HeaderBackend;
+use sp_core::{storage::StorageKey, twox_128};
+use sp_runtime::generic::BlockId;
+
+/// The key of the events of the last block, `System::Events`.
+fn events_key() -> StorageKey {
+	StorageKey([twox_128(b"System"), twox_128(b"Events")].concat())
+}
+
+/// The number of load extrinsics that went through in the block `hash`, from its events.
+fn load_extrinsics(client: &FullClient, hash: Hash) -> Result<usize, String> {
+	let events = match client.storage(&BlockId::Hash(hash), &events_key()).map_err(|e| e.to_string())? {
+		Some(events) => Vec::<EventRecord<Event, Hash>>::decode(&mut &events.0[..])
+			.map_err(|e| format!("Failed to decode the events of {}: {}", hash, e))?,
+		None => return Ok(0),
+	};
+
+	Ok(events.iter().filter(|record| matches!(
+		record.event,
+		Event::pallet_balances(pallet_balances::Event::Transfer(..)) |
+			Event::pallet_template(pallet_template::Event::SomethingStored(..))
+	)).count())
+}
+
+/// What a node went through over the measurement window.
+#[derive(Debug, Default)]
+pub struct Report {
+	/// The length of the window.
+	pub window: Duration,
+	/// The blocks executed within the window.
+	pub blocks: usize,
+	/// The load extrinsics executed within the window.
+	pub extrinsics: usize,
+	/// The time from finalization to execution-confirmation of each block finalized within the
+	/// window, confirmed by the end of the measurement.
+	pub confirmation_latencies: Vec<Duration>,
+	/// The blocks finalized within the window that were never confirmed.
+	pub unconfirmed: usize,
+}
+
+impl Report {
+	/// Executed blocks per second.
+	pub fn blocks_per_second(&self) -> f64 {
+		self.blocks as f64 / self.window.as_secs_f64()
+	}
+
+	/// Executed load extrinsics per second.
+	pub fn extrinsics_per_second(&self) -> f64 {
+		self.extrinsics as f64 / self.window.as_secs_f64()
+	}
+
+	/// The mean and maximum confirmation latencies, if any block was confirmed.
+	pub fn confirmation_latency(&self) -> Option<(Duration, Duration)> {
+		let max = *self.confirmation_latencies.iter().max()?;
+		let mean = self.confirmation_latencies.iter().sum::<Duration>() /
+			self.confirmation_latencies.len() as u32;
+		Some((mean, max))
+	}
+}
+
+/// Follows the finalized, executed and confirmed heads of a node.
+pub struct Meter {
+	/// Whether the node executes blocks after finality, rather than on import.
+	decoupled: bool,
+	finalized: BlockNumber,
+	executed: BlockNumber,
+	confirmed: BlockNumber,
+	/// When each block was seen finalized, for the ones finalized within the window.
+	finalized_at: BTreeMap<BlockNumber, Instant>,
+	window: Option<(Instant, Option<Instant>)>,
+	report: Report,
+}
+
+impl Meter {
+	/// Create a meter following the given node from its current heads.
+	pub fn new(client: &FullClient, decoupled: bool) -> Result<Self, String> {
+		let mut meter = Meter {
+			decoupled,
+			finalized: 0,
+			executed: 0,
+			confirmed: 0,
+			finalized_at: BTreeMap::new(),
+			window: None,
+			report: Report::default(),
+		};
+		meter.finalized = client.info().finalized_number;
+		meter.executed = meter.executed_head(client)?;
+		meter.confirmed = meter.confirmed_head(client)?;
+		Ok(meter)
+	}
+
+	fn executed_head(&self, client: &FullClient) -> Result<BlockNumber, String> {
+		if !self.decoupled {
+			// coupled nodes execute blocks on import, the finalized ones are final.
+			return Ok(client.info().finalized_number)
+		}
+		Ok(aux_schema::load_executed_head::<_, Hash, BlockNumber>(client)
+			.map_err(|e| e.to_string())?
+			.map_or(0, |(_, number)| number))
+	}
+
+	fn confirmed_head(&self, client: &FullClient) -> Result<BlockNumber, String> {
+		Ok(aux_schema::load_confirmed_head::<_, Hash, BlockNumber>(client)
+			.map_err(|e| e.to_string())?
+			.map_or(0, |(_, number)| number))
+	}
+
+	fn in_window(&self, now: Instant) -> bool {
+		matches!(self.window, Some((start, end)) if now >= start && end.map_or(true, |end| now < end))
+	}
+
+	/// Start the measurement window.
+	pub fn start(&mut self) {
+		self.window = Some((Instant::now(), None));
+	}
+
+	/// End the measurement window. Blocks finalized within it are still followed until they are
+	/// confirmed.
+	pub fn stop(&mut self) {
+		if let Some((start, end)) = &mut self.window {
+			let now = Instant::now();
+			*end = Some(now);
+			self.report.window = now - *start;
+		}
+	}
+
+	/// Whether all the blocks finalized within the window are confirmed.
+	pub fn is_drained(&self) -> bool {
+		self.finalized_at.is_empty()
+	}
+
+	/// Catch up with the heads of the node.
+	pub fn poll(&mut self, client: &FullClient) -> Result<(), String> {
+		let now = Instant::now();
+		let in_window = self.in_window(now);
+
+		let finalized = client.info().finalized_number;
+		if in_window {
+			for number in self.finalized + 1..=finalized {
+				self.finalized_at.insert(number, now);
+			}
+		}
+		self.finalized = self.finalized.max(finalized);
+
+		let executed = self.executed_head(client)?;
+		if in_window {
+			for number in self.executed + 1..=executed {
+				let hash = client.hash(number)
+					.map_err(|e| e.to_string())?
+					.ok_or_else(|| format!("No hash for executed block #{}", number))?;
+				self.report.blocks += 1;
+				self.report.extrinsics += load_extrinsics(client, hash)?;
+			}
+		}
+		self.executed = self.executed.max(executed);
+
+		let confirmed = self.confirmed_head(client)?;
+		let still_pending = self.finalized_at.split_off(&(confirmed + 1));
+		for (_, finalized_at) in std::mem::replace(&mut self.finalized_at, still_pending) {
+			self.report.confirmation_latencies.push(now - finalized_at);
+		}
+		self.confirmed = self.confirmed.max(confirmed);
+
+		Ok(())
+	}
+
+	/// The report of the measurement, counting the blocks left unconfirmed.
+	pub fn report(mut self) -> Report {
+		self.report.unconfirmed = self.finalized_at.len();
+		self.report
+	}
+}
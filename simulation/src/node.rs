@@ -0,0 +1,156 @@
+//! In-process nodes of the local testnet.
+
+use std::path::Path;
+use codec::Encode;
+use node_template::{
+	chain_spec,
+	executor::{ExecutionConfig, ExecutionRole},
+	service::{self, NewFullBase},
+};
+use node_template_runtime::{BlockNumber, MaxReceiptLag, ReceiptsChallengePeriod, ReceiptsPruningDepth};
+use sc_cli::{ChainSpec, CliConfiguration, RunCmd, RuntimeVersion, SubstrateCli};
+use sc_keystore::LocalKeystore;
+use sc_service::{Configuration, KeystoreConfig, TaskExecutor};
+use sp_core::{crypto::{KeyTypeId, Pair, key_types}, ed25519, sr25519};
+use sp_keystore::SyncCryptoStore;
+use sp_runtime::BuildStorage;
+use structopt::StructOpt;
+
+/// Builds the configuration of the nodes, on the local testnet.
+struct SimulationCli {
+	/// The receipts challenge period set at genesis, in blocks.
+	challenge_period: BlockNumber,
+}
+
+impl SubstrateCli for SimulationCli {
+	fn impl_name() -> String {
+		"Node Template Simulation".into()
+	}
+
+	fn impl_version() -> String {
+		env!("CARGO_PKG_VERSION").into()
+	}
+
+	fn description() -> String {
+		env!("CARGO_PKG_DESCRIPTION").into()
+	}
+
+	fn author() -> String {
+		env!("CARGO_PKG_AUTHORS").into()
+	}
+
+	fn support_url() -> String {
+		"support.anonymous.an".into()
+	}
+
+	fn copyright_start_year() -> i32 {
+		2017
+	}
+
+	fn load_spec(&self, _id: &str) -> Result<Box<dyn ChainSpec>, String> {
+		let mut spec = chain_spec::local_testnet_config()?;
+		// the default challenge period of an hour leaves nothing to confirm within a run, and
+		// changing it takes a referendum. Receipts are settled once pruned, which the receipt lag
+		// and the pruning depth hold back as well.
+		let mut storage = spec.build_storage()?;
+		for key in [ReceiptsChallengePeriod::key(), MaxReceiptLag::key(), ReceiptsPruningDepth::key()].iter() {
+			storage.top.insert(key.to_vec(), self.challenge_period.encode());
+		}
+		spec.set_storage(storage);
+		Ok(Box::new(spec))
+	}
+
+	fn native_runtime_version(_: &Box<dyn ChainSpec>) -> &'static RuntimeVersion {
+		&node_template_runtime::VERSION
+	}
+}
+
+/// A running node.
+pub struct Node {
+	/// The development account the node is named after, e.g. `Alice`.
+	pub name: &'static str,
+	/// The TCP port the node listens on.
+	pub port: u16,
+	/// The parts of the node the simulation drives.
+	pub parts: NewFullBase,
+}
+
+/// Put the AURA, GRANDPA and executor keys of the development account `name` in the keystore of
+/// the node, before it starts.
+fn insert_keys(config: &Configuration, name: &str) -> Result<(), String> {
+	let keystore = match &config.keystore {
+		KeystoreConfig::Path { path, password } => LocalKeystore::open(path.clone(), password.clone())
+			.map_err(|e| format!("Failed to open the keystore of {}: {}", name, e))?,
+		KeystoreConfig::InMemory => return Err(format!("The keystore of {} isn't on disk", name)),
+	};
+
+	let suri = format!("//{}", name);
+	let insert = |key_type: KeyTypeId, public: &[u8]| {
+		SyncCryptoStore::insert_unknown(&keystore, key_type, &suri, public)
+			.map_err(|()| format!("Failed to insert the {:?} key of {}", key_type, name))
+	};
+	let sr25519 = sr25519::Pair::from_string(&suri, None)
+		.map_err(|e| format!("Bad seed {}: {:?}", suri, e))?
+		.public();
+	let ed25519 = ed25519::Pair::from_string(&suri, None)
+		.map_err(|e| format!("Bad seed {}: {:?}", suri, e))?
+		.public();
+
+	insert(key_types::AURA, sr25519.as_ref())?;
+	insert(key_types::GRANDPA, ed25519.as_ref())?;
+	insert(sp_executor::KEY_TYPE, sr25519.as_ref())
+}
+
+/// Start a node of the local testnet named after the development account `name`, listening on
+/// `port`, with its database and keystore under `base_path`.
+pub fn start_node(
+	name: &'static str,
+	role: ExecutionRole,
+	authority: bool,
+	challenge_period: BlockNumber,
+	port: u16,
+	base_path: &Path,
+	task_executor: TaskExecutor,
+) -> Result<Node, String> {
+	let mut args = vec![
+		"node-template-simulation".to_string(),
+		"--base-path".into(), base_path.display().to_string(),
+		"--name".into(), name.into(),
+		"--port".into(), port.to_string(),
+		"--rpc-port".into(), (port + 1).to_string(),
+		"--ws-port".into(), (port + 2).to_string(),
+		"--no-prometheus".into(),
+		"--no-telemetry".into(),
+		"--no-mdns".into(),
+	];
+	if authority {
+		args.push("--validator".into());
+	}
+
+	let config = RunCmd::from_iter(args)
+		.create_configuration(&SimulationCli { challenge_period }, task_executor)
+		.map_err(|e| format!("Failed to configure {}: {}", name, e))?;
+	insert_keys(&config, name)?;
+
+	let execution_config = ExecutionConfig { role, ..Default::default() };
+	let parts = service::new_full_base(config, execution_config)
+		.map_err(|e| format!("Failed to start {}: {}", name, e))?;
+
+	Ok(Node { name, port, parts })
+}
+
+/// Connect every node to all the ones before it.
+pub fn connect(nodes: &[Node]) -> Result<(), String> {
+	for (index, node) in nodes.iter().enumerate() {
+		for other in &nodes[..index] {
+			let address = format!(
+				"/ip4/127.0.0.1/tcp/{}/p2p/{}",
+				other.port,
+				other.parts.network.local_peer_id(),
+			);
+			node.parts.network.add_reserved_peer(address)
+				.map_err(|e| format!("Failed to connect {} to {}: {}", node.name, other.name, e))?;
+		}
+	}
+	Ok(())
+}